@@ -0,0 +1,154 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Query},
+    http::{header, request::Parts},
+};
+
+use crate::{
+    error::Error,
+    state::AppState,
+    types::booking::{Booking, BookingReference},
+};
+
+/// The bearer tokens that staff authenticate with. Nobody can act in a
+/// role that has no token configured.
+#[derive(Debug, Clone, Default)]
+pub struct StaffTokens {
+    pub operator: Option<String>,
+    pub conductor: Option<String>,
+}
+
+impl StaffTokens {
+    /// Read the tokens from the environment: `TAKEOFF_OPERATOR_TOKEN`
+    /// and `TAKEOFF_CONDUCTOR_TOKEN`
+    pub fn from_env() -> Self {
+        let token = |name| std::env::var(name).ok().filter(|t: &String| !t.is_empty());
+        Self {
+            operator: token("TAKEOFF_OPERATOR_TOKEN"),
+            conductor: token("TAKEOFF_CONDUCTOR_TOKEN"),
+        }
+    }
+}
+
+/// The token in the `Authorization: Bearer` header, if any
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Whether `token` is `expected`, comparing all bytes whatever their
+/// differences, so that how long it takes doesn't give away how much
+/// of a guessed token was right
+fn token_matches(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Proof that the request was made by operator staff, who authenticate
+/// with the operator token
+#[derive(Debug)]
+pub struct Operator;
+
+#[async_trait]
+impl FromRequestParts<AppState> for Operator {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        match (bearer_token(parts), &state.staff.operator) {
+            (Some(token), Some(operator)) if token_matches(token, operator) => Ok(Self),
+            _ => Err(Error::Unauthorized("Authenticate as operator staff")),
+        }
+    }
+}
+
+/// Proof that the request was made by a conductor, or by operator staff,
+/// who can do whatever conductors can
+#[derive(Debug)]
+pub struct Conductor;
+
+#[async_trait]
+impl FromRequestParts<AppState> for Conductor {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let staff = &state.staff;
+        match bearer_token(parts) {
+            Some(token)
+                if [&staff.conductor, &staff.operator]
+                    .into_iter()
+                    .flatten()
+                    .fold(false, |matched, t| matched | token_matches(token, t)) =>
+            {
+                Ok(Self)
+            }
+            _ => Err(Error::Unauthorized("Authenticate as a conductor")),
+        }
+    }
+}
+
+/// What a customer has to show to get at their booking, besides its
+/// reference: the email address of the contact it was booked under
+#[derive(Debug, serde::Deserialize)]
+struct OwnershipProof {
+    email: String,
+}
+
+/// The booking whose reference is in the path, for customers who prove
+/// it's theirs with an `email` query parameter. References are short
+/// enough to guess, so they're not enough by themselves. Whether the
+/// booking doesn't exist or belongs to someone else isn't told apart.
+#[derive(Debug)]
+pub struct OwnedBooking(pub Booking);
+
+#[async_trait]
+impl FromRequestParts<AppState> for OwnedBooking {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Path(reference) = Path::<BookingReference>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::BadRequest("Invalid booking reference"))?;
+        let Query(proof) = Query::<OwnershipProof>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::Unauthorized("Give the email address the booking was made with"))?;
+
+        state
+            .bookings
+            .get(&reference)?
+            .filter(|booking| {
+                String::from(booking.contact.email.clone()).eq_ignore_ascii_case(&proof.email)
+            })
+            .map(Self)
+            .ok_or(Error::NotFound("Booking not found"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::token_matches;
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cres", "s3cret"));
+        assert!(!token_matches("s3cre", "s3cret"));
+        assert!(!token_matches("", "s3cret"));
+    }
+}
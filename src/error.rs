@@ -1,4 +1,23 @@
 use axum::{http::StatusCode, response::IntoResponse};
+use takeoff_tickets::TicketError;
+
+use crate::{
+    inventory::InventoryError,
+    payment::PaymentError,
+    repository::RepositoryError,
+    session::SessionError,
+    types::{
+        ancillary::AncillaryError,
+        booking::{CancellationError, ChangeError, StatusTransitionError},
+        discount_card::DiscountCardError,
+        fare::FareError,
+        invoice::InvoiceError,
+        money::MoneyError,
+        passenger::PassengersError,
+        travel_document::TravelDocumentError,
+        voucher::VoucherError,
+    },
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -7,6 +26,82 @@ pub enum Error {
 
     #[error("Bad Request: {0}")]
     BadRequest(&'static str),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(&'static str),
+
+    #[error("Not Found: {0}")]
+    NotFound(&'static str),
+
+    #[error("Conflict: {0}")]
+    Conflict(&'static str),
+
+    #[error("Unprocessable Entity: {0}")]
+    UnprocessableEntity(&'static str),
+
+    #[error("Internal Server Error: {0}")]
+    Internal(&'static str),
+
+    /// The server is configured in a way it can't run with
+    #[error("Configuration error: {0}")]
+    Config(&'static str),
+
+    #[error("Session error: {0}")]
+    Session(#[from] SessionError),
+
+    #[error("Repository error: {0}")]
+    Repository(#[from] RepositoryError),
+
+    #[error("Payment error: {0}")]
+    Payment(#[from] PaymentError),
+
+    #[error("Booking status error: {0}")]
+    StatusTransition(#[from] StatusTransitionError),
+
+    #[error("Cancellation error: {0}")]
+    Cancellation(#[from] CancellationError),
+
+    #[error("Change error: {0}")]
+    Change(#[from] ChangeError),
+
+    #[error("Inventory error: {0}")]
+    Inventory(#[from] InventoryError),
+
+    #[error("Fare error: {0}")]
+    Fare(#[from] FareError),
+
+    #[error("Money error: {0}")]
+    Money(#[from] MoneyError),
+
+    #[error("Voucher error: {0}")]
+    Voucher(#[from] VoucherError),
+
+    #[error("Discount card error: {0}")]
+    DiscountCard(#[from] DiscountCardError),
+
+    #[error("Ancillary error: {0}")]
+    Ancillary(#[from] AncillaryError),
+
+    #[error("Invoice error: {0}")]
+    Invoice(#[from] InvoiceError),
+
+    #[error("PDF error: {0}")]
+    Pdf(#[from] printpdf::Error),
+
+    #[error("Ticket error: {0}")]
+    Ticket(#[from] TicketError),
+
+    #[error("QR code error: {0}")]
+    QrCode(#[from] qrcode::types::QrError),
+
+    #[error("PNG error: {0}")]
+    Png(#[from] png::EncodingError),
+
+    #[error("Passengers error: {0}")]
+    Passengers(#[from] PassengersError),
+
+    #[error("Travel document error: {0}")]
+    TravelDocument(#[from] TravelDocumentError),
 }
 
 impl Error {
@@ -14,6 +109,50 @@ impl Error {
         match self {
             Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Internal(_) | Error::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Session(SessionError::StateReset(_)) => StatusCode::CONFLICT,
+            Error::Session(SessionError::PreconditionFailed) => StatusCode::PRECONDITION_FAILED,
+            Error::Session(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Repository(
+                RepositoryError::DuplicateInvoice(_) | RepositoryError::StatusChanged(_),
+            ) => StatusCode::CONFLICT,
+            Error::Repository(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Payment(PaymentError::Declined) => StatusCode::PAYMENT_REQUIRED,
+            Error::Payment(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::StatusTransition(_) => StatusCode::CONFLICT,
+            Error::Cancellation(CancellationError::Status(_)) => StatusCode::CONFLICT,
+            Error::Cancellation(CancellationError::AlreadyDeparted) => StatusCode::BAD_REQUEST,
+            Error::Cancellation(CancellationError::Refund(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Change(ChangeError::NotConfirmed(_)) => StatusCode::CONFLICT,
+            Error::Change(ChangeError::CurrencyMismatch) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Change(_) => StatusCode::BAD_REQUEST,
+            Error::Inventory(_) => StatusCode::CONFLICT,
+            Error::Fare(FareError::UnknownRoute(..)) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Fare(FareError::UnknownAncillary(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Fare(FareError::Money(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Money(MoneyError::NoExchangeRate(_)) => StatusCode::BAD_REQUEST,
+            Error::Money(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Voucher(VoucherError::Unknown(_)) => StatusCode::NOT_FOUND,
+            Error::Voucher(VoucherError::UsedUp | VoucherError::UsedUpByCustomer) => {
+                StatusCode::CONFLICT
+            }
+            Error::Voucher(VoucherError::Money(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Voucher(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::DiscountCard(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Ancillary(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Invoice(InvoiceError::NotConfirmed) => StatusCode::CONFLICT,
+            Error::Invoice(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Pdf(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Ticket(TicketError::InvalidKey) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Ticket(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::QrCode(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Png(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Passengers(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::TravelDocument(_) => StatusCode::UNPROCESSABLE_ENTITY,
         }
     }
 }
@@ -0,0 +1,114 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue},
+    response::{IntoResponseParts, ResponseParts},
+};
+
+use crate::{error::Error, session::SessionError};
+
+/// An `ETag` header carrying the revision of the session state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ETag(pub u64);
+
+impl IntoResponseParts for ETag {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let value = HeaderValue::from_str(&format!("\"{}\"", self.0))
+            .expect("a quoted number is a valid header value");
+        res.headers_mut().insert(header::ETAG, value);
+        Ok(res)
+    }
+}
+
+/// The `If-Match` header of a request, if any. Only strong entity tags
+/// as produced by [`ETag`] ever match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum IfMatch {
+    /// No `If-Match` header: the request is unconditional
+    #[default]
+    Absent,
+    /// `If-Match: *`: matches any existing state
+    Any,
+    /// Matches the state if it has any of these revisions
+    Revisions(Vec<u64>),
+}
+
+impl IfMatch {
+    /// Check whether the current revision of the state, or the lack
+    /// thereof, satisfies this precondition
+    pub fn check(&self, current: Option<u64>) -> Result<(), SessionError> {
+        let matches = match (self, current) {
+            (IfMatch::Absent, _) => true,
+            (_, None) => false,
+            (IfMatch::Any, Some(_)) => true,
+            (IfMatch::Revisions(revisions), Some(current)) => revisions.contains(&current),
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(SessionError::PreconditionFailed)
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        if value.trim() == "*" {
+            return IfMatch::Any;
+        }
+
+        let revisions = value
+            .split(',')
+            .filter_map(|tag| {
+                tag.trim()
+                    .strip_prefix('"')?
+                    .strip_suffix('"')?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        IfMatch::Revisions(revisions)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(header::IF_MATCH) else {
+            return Ok(IfMatch::Absent);
+        };
+        let value = value
+            .to_str()
+            .map_err(|_| Error::BadRequest("Invalid If-Match header"))?;
+        Ok(IfMatch::parse(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IfMatch;
+    use test_case::test_case;
+
+    #[test_case("*" => IfMatch::Any)]
+    #[test_case(r#""3""# => IfMatch::Revisions(vec![3]))]
+    #[test_case(r#""3", "4""# => IfMatch::Revisions(vec![3, 4]))]
+    #[test_case(r#"W/"3""# => IfMatch::Revisions(vec![]); "weak tags never match")]
+    #[test_case("3" => IfMatch::Revisions(vec![]); "unquoted")]
+    fn test_parse_if_match(value: &str) -> IfMatch {
+        IfMatch::parse(value)
+    }
+
+    #[test_case(IfMatch::Absent, None => true)]
+    #[test_case(IfMatch::Absent, Some(1) => true)]
+    #[test_case(IfMatch::Any, None => false)]
+    #[test_case(IfMatch::Any, Some(1) => true)]
+    #[test_case(IfMatch::Revisions(vec![1, 2]), Some(2) => true)]
+    #[test_case(IfMatch::Revisions(vec![1, 2]), Some(3) => false)]
+    #[test_case(IfMatch::Revisions(vec![1]), None => false)]
+    fn test_check_if_match(if_match: IfMatch, current: Option<u64>) -> bool {
+        if_match.check(current).is_ok()
+    }
+}
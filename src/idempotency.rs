@@ -0,0 +1,311 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{error::Error, session::Session, state::AppState};
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on responses that are replayed rather than produced by the handler
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// How long a response is kept around to be replayed
+pub const RETENTION: Duration = Duration::hours(24);
+
+const MAX_KEY_LEN: usize = 255;
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+/// What a request consists of, as far as deciding whether a request is a
+/// repetition of an earlier one goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub method: Method,
+    pub path: String,
+    pub body: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl IntoResponse for StoredResponse {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.headers, self.body).into_response();
+        response
+            .headers_mut()
+            .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    fingerprint: Fingerprint,
+    created_at: DateTime<Utc>,
+    response: Option<StoredResponse>,
+}
+
+/// The outcome of [`IdempotencyStore::begin`]
+#[derive(Debug)]
+pub enum Begin {
+    /// The key is new: go ahead and handle the request
+    Proceed,
+    /// The request was handled before: send this response again
+    Replay(StoredResponse),
+}
+
+/// Keeps track of requests by their idempotency key, along with the
+/// response they got, for [`RETENTION`]. Keys are scoped to the session
+/// they were sent in, so that nobody else gets to see the responses.
+#[derive(Debug, Default)]
+pub struct IdempotencyStore {
+    /// Keyed by session id and idempotency key
+    entries: Mutex<HashMap<(String, String), Entry>>,
+}
+
+impl IdempotencyStore {
+    /// Register a request with the given key in `session`. Fails if another
+    /// request with the same key is still being handled, or if the key was
+    /// used before for a different request.
+    pub fn begin(
+        &self,
+        session: &str,
+        key: &str,
+        fingerprint: Fingerprint,
+        now: DateTime<Utc>,
+    ) -> Result<Begin, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| now - e.created_at < RETENTION);
+
+        let key = (session.to_owned(), key.to_owned());
+        let Some(entry) = entries.get(&key) else {
+            entries.insert(
+                key,
+                Entry {
+                    fingerprint,
+                    created_at: now,
+                    response: None,
+                },
+            );
+            return Ok(Begin::Proceed);
+        };
+
+        if entry.fingerprint != fingerprint {
+            return Err(Error::UnprocessableEntity(
+                "Idempotency-Key was already used for a different request",
+            ));
+        }
+
+        match &entry.response {
+            Some(response) => Ok(Begin::Replay(response.clone())),
+            None => Err(Error::Conflict(
+                "A request with this Idempotency-Key is still being processed",
+            )),
+        }
+    }
+
+    /// Store the response to a request registered with [`Self::begin`]
+    pub fn complete(&self, session: &str, key: &str, response: StoredResponse) {
+        let key = (session.to_owned(), key.to_owned());
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&key) {
+            entry.response = Some(response);
+        }
+    }
+
+    /// Forget about a request registered with [`Self::begin`], so that it
+    /// can be retried with the same key
+    pub fn abandon(&self, session: &str, key: &str) {
+        let key = (session.to_owned(), key.to_owned());
+        self.entries.lock().unwrap().remove(&key);
+    }
+}
+
+/// Middleware that makes the routes it wraps idempotent for requests that
+/// carry an `Idempotency-Key` header: repeating such a request in the same
+/// session replays the original response instead of handling the request
+/// again. Only successful responses are stored: errors may no longer apply
+/// once the client has fixed what was wrong, so those can be retried.
+pub async fn idempotency(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|k| !k.is_empty() && k.len() <= MAX_KEY_LEN)
+        .ok_or(Error::BadRequest("Invalid Idempotency-Key"))?
+        .to_owned();
+
+    let (mut parts, body) = request.into_parts();
+    let session = Session::from_request_parts(&mut parts, &state)
+        .await
+        .map_err(|_| Error::Internal("No session to scope the Idempotency-Key to"))?
+        .get_session_id();
+    let body = axum::body::to_bytes(body, MAX_BODY_LEN)
+        .await
+        .map_err(|_| Error::BadRequest("Could not read request body"))?;
+    let fingerprint = Fingerprint {
+        method: parts.method.clone(),
+        path: parts.uri.path().to_owned(),
+        body: body.clone(),
+    };
+
+    match state
+        .idempotency
+        .begin(&session, &key, fingerprint, Utc::now())?
+    {
+        Begin::Replay(response) => return Ok(response.into_response()),
+        Begin::Proceed => {}
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !response.status().is_success() {
+        state.idempotency.abandon(&session, &key);
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(_) => {
+            state.idempotency.abandon(&session, &key);
+            return Err(Error::Internal("Could not read response body"));
+        }
+    };
+    state.idempotency.complete(
+        &session,
+        &key,
+        StoredResponse {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        },
+    );
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, Method, StatusCode};
+    use chrono::{Duration, Utc};
+
+    use super::{Begin, Fingerprint, IdempotencyStore, StoredResponse, RETENTION};
+    use crate::error::Error;
+
+    fn fingerprint(body: &'static str) -> Fingerprint {
+        Fingerprint {
+            method: Method::POST,
+            path: "/book_trip".to_owned(),
+            body: body.into(),
+        }
+    }
+
+    fn response() -> StoredResponse {
+        StoredResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: "booked".into(),
+        }
+    }
+
+    #[test]
+    fn test_replay_completed_request() {
+        let store = IdempotencyStore::default();
+        let now = Utc::now();
+
+        assert!(matches!(
+            store.begin("session", "key", fingerprint("card"), now),
+            Ok(Begin::Proceed)
+        ));
+        assert!(matches!(
+            store.begin("session", "key", fingerprint("card"), now),
+            Err(Error::Conflict(_))
+        ));
+
+        store.complete("session", "key", response());
+        assert!(matches!(
+            store.begin("session", "key", fingerprint("card"), now),
+            Ok(Begin::Replay(r)) if r.body == "booked"
+        ));
+    }
+
+    #[test]
+    fn test_reject_reused_key() {
+        let store = IdempotencyStore::default();
+        let now = Utc::now();
+
+        store
+            .begin("session", "key", fingerprint("card"), now)
+            .unwrap();
+        store.complete("session", "key", response());
+
+        assert!(matches!(
+            store.begin("session", "key", fingerprint("other card"), now),
+            Err(Error::UnprocessableEntity(_))
+        ));
+    }
+
+    #[test]
+    fn test_abandoned_request_can_be_retried() {
+        let store = IdempotencyStore::default();
+        let now = Utc::now();
+
+        store
+            .begin("session", "key", fingerprint("card"), now)
+            .unwrap();
+        store.abandon("session", "key");
+
+        assert!(matches!(
+            store.begin("session", "key", fingerprint("card"), now),
+            Ok(Begin::Proceed)
+        ));
+    }
+
+    #[test]
+    fn test_keys_are_scoped_to_the_session() {
+        let store = IdempotencyStore::default();
+        let now = Utc::now();
+
+        store
+            .begin("session", "key", fingerprint("card"), now)
+            .unwrap();
+        store.complete("session", "key", response());
+
+        assert!(matches!(
+            store.begin("other session", "key", fingerprint("card"), now),
+            Ok(Begin::Proceed)
+        ));
+    }
+
+    #[test]
+    fn test_expired_keys_are_forgotten() {
+        let store = IdempotencyStore::default();
+        let now = Utc::now();
+
+        store
+            .begin("session", "key", fingerprint("card"), now)
+            .unwrap();
+        store.complete("session", "key", response());
+
+        let later = now + RETENTION + Duration::seconds(1);
+        assert!(matches!(
+            store.begin("session", "key", fingerprint("other card"), later),
+            Ok(Begin::Proceed)
+        ));
+    }
+}
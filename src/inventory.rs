@@ -0,0 +1,768 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::types::{
+    ancillary::{Ancillary, AncillaryType},
+    booking::Booking,
+    class::Class,
+    seat::{SeatId, SeatLayout},
+    trip::TripId,
+};
+
+/// How long a seat is held for a customer after their last activity
+pub const HOLD_TTL: Duration = Duration::minutes(15);
+
+/// How often [`sweep_holds`] releases expired holds
+pub const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum InventoryError {
+    #[error("No seats left in {1:?} class on trip {0:?}")]
+    SoldOut(TripId, Class),
+
+    #[error("The seat hold has expired, please select a class again")]
+    HoldExpired,
+
+    #[error("Seat {0} has been taken already")]
+    SeatTaken(SeatId),
+
+    #[error("No room left for {1:?} on trip {0:?}")]
+    AncillarySoldOut(TripId, AncillaryType),
+
+    #[error("Not enough wheelchair spaces left in {1:?} class on trip {0:?}")]
+    NoWheelchairSpace(TripId, Class),
+}
+
+/// Identifies a seat that is set aside for a customer while they're booking
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct HoldId(Uuid);
+
+/// Number of seats in each class, for trips that weren't given
+/// a capacity of their own
+pub fn default_capacity(class: &Class) -> u32 {
+    SeatLayout::standard().capacity(class)
+}
+
+/// Number of wheelchair spaces in each class, for trips that
+/// weren't given a number of their own
+pub fn default_wheelchair_spaces(class: &Class) -> u32 {
+    SeatLayout::standard().wheelchair_spaces(class)
+}
+
+/// Room for each kind of add-on on trips that weren't given a capacity
+/// of their own, provided the trip carries them at all
+pub fn default_ancillary_capacity(ancillary_type: &AncillaryType) -> u32 {
+    match ancillary_type {
+        AncillaryType::Bicycle => 8,
+        AncillaryType::ExtraLuggage => 40,
+        AncillaryType::Pet => 10,
+    }
+}
+
+/// Counts seats, or wheelchair spaces or room for add-ons, which are
+/// never held
+#[derive(Debug, Clone, Copy)]
+struct Seats {
+    capacity: u32,
+    sold: u32,
+    held: u32,
+}
+
+impl Seats {
+    fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            sold: 0,
+            held: 0,
+        }
+    }
+
+    fn available(&self) -> u32 {
+        self.capacity.saturating_sub(self.sold + self.held)
+    }
+}
+
+#[derive(Debug)]
+struct Hold {
+    trip: TripId,
+    class: Class,
+    /// The number of seats that are held
+    count: u32,
+    seat: Option<SeatId>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Stock {
+    seats: HashMap<(TripId, Class), Seats>,
+    ancillaries: HashMap<(TripId, AncillaryType), Seats>,
+    wheelchair_spaces: HashMap<(TripId, Class), Seats>,
+    holds: HashMap<HoldId, Hold>,
+    /// Specific seats that were sold, as opposed to just any seat in a class
+    sold_seats: HashSet<(TripId, SeatId)>,
+}
+
+impl Stock {
+    fn seats(&mut self, trip: &TripId, class: &Class) -> &mut Seats {
+        self.seats
+            .entry((trip.clone(), class.clone()))
+            .or_insert_with(|| Seats::new(default_capacity(class)))
+    }
+
+    fn ancillaries(&mut self, trip: &TripId, ancillary_type: &AncillaryType) -> &mut Seats {
+        self.ancillaries
+            .entry((trip.clone(), *ancillary_type))
+            .or_insert_with(|| Seats::new(default_ancillary_capacity(ancillary_type)))
+    }
+
+    fn wheelchair_spaces(&mut self, trip: &TripId, class: &Class) -> &mut Seats {
+        self.wheelchair_spaces
+            .entry((trip.clone(), class.clone()))
+            .or_insert_with(|| Seats::new(default_wheelchair_spaces(class)))
+    }
+
+    fn sweep(&mut self, now: DateTime<Utc>) -> usize {
+        let expired: Vec<_> = self
+            .holds
+            .iter()
+            .filter(|(_, h)| h.expires_at <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            let hold = self.holds.remove(id).unwrap();
+            let seats = self.seats(&hold.trip, &hold.class);
+            seats.held = seats.held.saturating_sub(hold.count);
+        }
+        expired.len()
+    }
+
+    fn taken_seats(&self, trip: &TripId, now: DateTime<Utc>) -> HashSet<SeatId> {
+        let held = self
+            .holds
+            .values()
+            .filter(|h| h.trip == *trip && h.expires_at > now)
+            .filter_map(|h| h.seat);
+        let sold = self
+            .sold_seats
+            .iter()
+            .filter(|(t, _)| t == trip)
+            .map(|(_, seat)| *seat);
+        held.chain(sold).collect()
+    }
+}
+
+/// Keeps track of how many seats are left per trip and [`Class`]. Seats are
+/// either held for a customer who's still booking, or sold right away. Every
+/// operation checks and updates the counts under a single lock, so concurrent
+/// bookings can't oversell.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    stock: Mutex<Stock>,
+}
+
+impl Inventory {
+    /// Set the number of seats in `class` on `trip`. Seats that
+    /// have been sold already stay sold.
+    pub fn set_capacity(&self, trip: &TripId, class: &Class, capacity: u32) {
+        self.stock.lock().unwrap().seats(trip, class).capacity = capacity;
+    }
+
+    /// The number of seats that are neither sold nor held
+    pub fn available(&self, trip: &TripId, class: &Class) -> u32 {
+        let stock = self.stock.lock().unwrap();
+        match stock.seats.get(&(trip.clone(), class.clone())) {
+            Some(seats) => seats.available(),
+            None => default_capacity(class),
+        }
+    }
+
+    /// Sell `count` seats right away, if there are that many left
+    pub fn reserve(&self, trip: &TripId, class: &Class, count: u32) -> Result<(), InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        let seats = stock.seats(trip, class);
+        if seats.available() < count {
+            return Err(InventoryError::SoldOut(trip.clone(), class.clone()));
+        }
+        seats.sold += count;
+        Ok(())
+    }
+
+    /// Put `count` seats that were sold back up for sale
+    pub fn release(&self, trip: &TripId, class: &Class, count: u32, seat: Option<SeatId>) {
+        let mut stock = self.stock.lock().unwrap();
+        let seats = stock.seats(trip, class);
+        seats.sold = seats.sold.saturating_sub(count);
+        if let Some(seat) = seat {
+            stock.sold_seats.remove(&(trip.clone(), seat));
+        }
+    }
+
+    /// Set `count` seats aside for [`HOLD_TTL`], if there are that many
+    /// left. Holds that have expired already are released first, so that
+    /// they don't keep the seats from being held again.
+    pub fn hold(
+        &self,
+        trip: &TripId,
+        class: &Class,
+        count: u32,
+        now: DateTime<Utc>,
+    ) -> Result<HoldId, InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        stock.sweep(now);
+
+        let seats = stock.seats(trip, class);
+        if seats.available() < count {
+            return Err(InventoryError::SoldOut(trip.clone(), class.clone()));
+        }
+        seats.held += count;
+
+        let id = HoldId(Uuid::new_v4());
+        stock.holds.insert(
+            id.clone(),
+            Hold {
+                trip: trip.clone(),
+                class: class.clone(),
+                count,
+                seat: None,
+                expires_at: now + HOLD_TTL,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Pick the specific seat for the first of the held seats. The seat must
+    /// not be held or sold to anyone else, and should be in the held class,
+    /// which is up to the caller to check against the [`SeatLayout`].
+    pub fn assign_seat(
+        &self,
+        id: &HoldId,
+        seat: SeatId,
+        now: DateTime<Utc>,
+    ) -> Result<(), InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        let Some(hold) = stock.holds.get(id).filter(|h| now < h.expires_at) else {
+            return Err(InventoryError::HoldExpired);
+        };
+        if hold.seat != Some(seat) && stock.taken_seats(&hold.trip, now).contains(&seat) {
+            return Err(InventoryError::SeatTaken(seat));
+        }
+
+        let hold = stock.holds.get_mut(id).unwrap();
+        hold.seat = Some(seat);
+        hold.expires_at = now + HOLD_TTL;
+        Ok(())
+    }
+
+    /// Let go of the seat that was picked for a hold, if any, keeping
+    /// the hold itself
+    pub fn unassign_seat(&self, id: &HoldId) {
+        if let Some(hold) = self.stock.lock().unwrap().holds.get_mut(id) {
+            hold.seat = None;
+        }
+    }
+
+    /// The seats on `trip` that are sold, or held at `now`
+    pub fn taken_seats(&self, trip: &TripId, now: DateTime<Utc>) -> HashSet<SeatId> {
+        self.stock.lock().unwrap().taken_seats(trip, now)
+    }
+
+    /// Keep a hold for another [`HOLD_TTL`] from `now`
+    pub fn extend_hold(&self, id: &HoldId, now: DateTime<Utc>) -> Result<(), InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        match stock.holds.get_mut(id) {
+            Some(hold) if now < hold.expires_at => {
+                hold.expires_at = now + HOLD_TTL;
+                Ok(())
+            }
+            _ => Err(InventoryError::HoldExpired),
+        }
+    }
+
+    /// Put held seats back up for sale. Does nothing if
+    /// the hold has expired already.
+    pub fn release_hold(&self, id: &HoldId) {
+        let mut stock = self.stock.lock().unwrap();
+        if let Some(hold) = stock.holds.remove(id) {
+            let seats = stock.seats(&hold.trip, &hold.class);
+            seats.held = seats.held.saturating_sub(hold.count);
+        }
+    }
+
+    /// Sell the seats that are held, provided the hold hasn't expired.
+    /// Returns the specific seat that was assigned, if any.
+    pub fn convert_hold(
+        &self,
+        id: &HoldId,
+        now: DateTime<Utc>,
+    ) -> Result<Option<SeatId>, InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        stock.sweep(now);
+
+        let hold = stock.holds.remove(id).ok_or(InventoryError::HoldExpired)?;
+        let seats = stock.seats(&hold.trip, &hold.class);
+        seats.held = seats.held.saturating_sub(hold.count);
+        seats.sold += hold.count;
+        if let Some(seat) = hold.seat {
+            stock.sold_seats.insert((hold.trip, seat));
+        }
+        Ok(hold.seat)
+    }
+
+    /// Undo [`Inventory::convert_hold`], for when the sale fell through.
+    /// The `count` seats are held again under the same id, for another
+    /// [`HOLD_TTL`].
+    pub fn revert_sale(
+        &self,
+        id: HoldId,
+        trip: &TripId,
+        class: &Class,
+        count: u32,
+        seat: Option<SeatId>,
+        now: DateTime<Utc>,
+    ) {
+        let mut stock = self.stock.lock().unwrap();
+        let seats = stock.seats(trip, class);
+        seats.sold = seats.sold.saturating_sub(count);
+        seats.held += count;
+        if let Some(seat) = seat {
+            stock.sold_seats.remove(&(trip.clone(), seat));
+        }
+        stock.holds.insert(
+            id,
+            Hold {
+                trip: trip.clone(),
+                class: class.clone(),
+                count,
+                seat,
+                expires_at: now + HOLD_TTL,
+            },
+        );
+    }
+
+    /// Set the room for `ancillary_type` on `trip`
+    pub fn set_ancillary_capacity(
+        &self,
+        trip: &TripId,
+        ancillary_type: &AncillaryType,
+        capacity: u32,
+    ) {
+        self.stock
+            .lock()
+            .unwrap()
+            .ancillaries(trip, ancillary_type)
+            .capacity = capacity;
+    }
+
+    /// The room for `ancillary_type` on `trip` that hasn't been sold
+    pub fn available_ancillary(&self, trip: &TripId, ancillary_type: &AncillaryType) -> u32 {
+        let stock = self.stock.lock().unwrap();
+        match stock.ancillaries.get(&(trip.clone(), *ancillary_type)) {
+            Some(room) => room.available(),
+            None => default_ancillary_capacity(ancillary_type),
+        }
+    }
+
+    /// Sell room for all of `ancillaries` on `trip`, or for none
+    /// of them if there isn't enough room for any one of them
+    pub fn reserve_ancillaries(
+        &self,
+        trip: &TripId,
+        ancillaries: &[Ancillary],
+    ) -> Result<(), InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        for ancillary in ancillaries {
+            if stock
+                .ancillaries(trip, &ancillary.ancillary_type)
+                .available()
+                < ancillary.quantity
+            {
+                return Err(InventoryError::AncillarySoldOut(
+                    trip.clone(),
+                    ancillary.ancillary_type,
+                ));
+            }
+        }
+        for ancillary in ancillaries {
+            stock.ancillaries(trip, &ancillary.ancillary_type).sold += ancillary.quantity;
+        }
+        Ok(())
+    }
+
+    /// Put room for `ancillaries` that was sold back up for sale
+    pub fn release_ancillaries(&self, trip: &TripId, ancillaries: &[Ancillary]) {
+        let mut stock = self.stock.lock().unwrap();
+        for ancillary in ancillaries {
+            let room = stock.ancillaries(trip, &ancillary.ancillary_type);
+            room.sold = room.sold.saturating_sub(ancillary.quantity);
+        }
+    }
+
+    /// Set the number of wheelchair spaces in `class` on `trip`
+    pub fn set_wheelchair_spaces(&self, trip: &TripId, class: &Class, spaces: u32) {
+        self.stock
+            .lock()
+            .unwrap()
+            .wheelchair_spaces(trip, class)
+            .capacity = spaces;
+    }
+
+    /// The wheelchair spaces in `class` on `trip` that haven't been sold
+    pub fn available_wheelchair_spaces(&self, trip: &TripId, class: &Class) -> u32 {
+        let stock = self.stock.lock().unwrap();
+        match stock.wheelchair_spaces.get(&(trip.clone(), class.clone())) {
+            Some(spaces) => spaces.available(),
+            None => default_wheelchair_spaces(class),
+        }
+    }
+
+    /// Sell `count` wheelchair spaces, if there are that many left. The
+    /// seats for the passengers are sold separately.
+    pub fn reserve_wheelchair_spaces(
+        &self,
+        trip: &TripId,
+        class: &Class,
+        count: u32,
+    ) -> Result<(), InventoryError> {
+        let mut stock = self.stock.lock().unwrap();
+        let spaces = stock.wheelchair_spaces(trip, class);
+        if spaces.available() < count {
+            return Err(InventoryError::NoWheelchairSpace(
+                trip.clone(),
+                class.clone(),
+            ));
+        }
+        spaces.sold += count;
+        Ok(())
+    }
+
+    /// Put `count` wheelchair spaces that were sold back up for sale
+    pub fn release_wheelchair_spaces(&self, trip: &TripId, class: &Class, count: u32) {
+        let mut stock = self.stock.lock().unwrap();
+        let spaces = stock.wheelchair_spaces(trip, class);
+        spaces.sold = spaces.sold.saturating_sub(count);
+    }
+
+    /// Count the seats, wheelchair spaces and room for add-ons taken by
+    /// `booking` as sold, both ways, for bookings that were made before
+    /// a restart. They're counted even if there's no room for them left.
+    pub fn restore(&self, booking: &Booking) {
+        let mut stock = self.stock.lock().unwrap();
+        let (count, wheelchairs) = (booking.seats(), booking.wheelchair_spaces());
+        for (trip, seat) in std::iter::once((&booking.trip, booking.seat))
+            .chain(booking.return_trip.iter().map(|t| (t, None)))
+        {
+            stock.seats(&trip.id, &booking.class).sold += count;
+            stock.wheelchair_spaces(&trip.id, &booking.class).sold += wheelchairs;
+            for ancillary in booking.ancillaries.iter() {
+                stock.ancillaries(&trip.id, &ancillary.ancillary_type).sold += ancillary.quantity;
+            }
+            if let Some(seat) = seat {
+                stock.sold_seats.insert((trip.id.clone(), seat));
+            }
+        }
+    }
+
+    /// Release all holds that have expired by `now`, returning how many
+    pub fn sweep(&self, now: DateTime<Utc>) -> usize {
+        self.stock.lock().unwrap().sweep(now)
+    }
+}
+
+/// Release expired holds every [`SWEEP_INTERVAL`], so that seats held in
+/// sessions that were abandoned become available again
+pub async fn sweep_holds(inventory: Arc<Inventory>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        inventory.sweep(Utc::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use super::{
+        default_ancillary_capacity, default_capacity, default_wheelchair_spaces, Inventory,
+        InventoryError, HOLD_TTL,
+    };
+    use crate::types::{
+        ancillary::{Ancillary, AncillaryType},
+        booking::tests::booking,
+        class::Class,
+        seat::SeatId,
+        trip::TripId,
+    };
+
+    fn trip_id() -> TripId {
+        serde_json::from_value(serde_json::json!(uuid::Uuid::new_v4())).unwrap()
+    }
+
+    #[test]
+    fn test_reserve_and_release() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        assert_eq!(
+            inventory.available(&trip, &Class::First),
+            default_capacity(&Class::First)
+        );
+
+        inventory.set_capacity(&trip, &Class::First, 1);
+        inventory.reserve(&trip, &Class::First, 1).unwrap();
+        assert_eq!(inventory.available(&trip, &Class::First), 0);
+        assert!(matches!(
+            inventory.reserve(&trip, &Class::First, 1),
+            Err(InventoryError::SoldOut(..))
+        ));
+
+        // Other classes and trips are unaffected
+        inventory.reserve(&trip, &Class::Second, 1).unwrap();
+        inventory.reserve(&trip_id(), &Class::First, 1).unwrap();
+
+        inventory.release(&trip, &Class::First, 1, None);
+        assert_eq!(inventory.available(&trip, &Class::First), 1);
+    }
+
+    #[test]
+    fn test_concurrent_reservations() {
+        let inventory = Arc::new(Inventory::default());
+        let trip = trip_id();
+        inventory.set_capacity(&trip, &Class::Second, 10);
+
+        let handles: Vec<_> = (0..50)
+            .map(|_| {
+                let (inventory, trip) = (inventory.clone(), trip.clone());
+                std::thread::spawn(move || inventory.reserve(&trip, &Class::Second, 1).is_ok())
+            })
+            .collect();
+        let reserved = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&ok| ok)
+            .count();
+
+        assert_eq!(reserved, 10);
+        assert_eq!(inventory.available(&trip, &Class::Second), 0);
+    }
+
+    #[test]
+    fn test_hold_expiry() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        inventory.set_capacity(&trip, &Class::First, 1);
+        let now = Utc::now();
+
+        let hold = inventory.hold(&trip, &Class::First, 1, now).unwrap();
+        assert_eq!(inventory.available(&trip, &Class::First), 0);
+        assert!(matches!(
+            inventory.hold(&trip, &Class::First, 1, now),
+            Err(InventoryError::SoldOut(..))
+        ));
+
+        // Activity keeps the hold alive past its original expiry
+        let later = now + HOLD_TTL - chrono::Duration::minutes(1);
+        inventory.extend_hold(&hold, later).unwrap();
+        assert_eq!(inventory.sweep(now + HOLD_TTL), 0);
+
+        // Until it's left alone for too long
+        assert_eq!(inventory.sweep(later + HOLD_TTL), 1);
+        assert_eq!(inventory.available(&trip, &Class::First), 1);
+        assert!(matches!(
+            inventory.convert_hold(&hold, later + HOLD_TTL),
+            Err(InventoryError::HoldExpired)
+        ));
+    }
+
+    #[test]
+    fn test_convert_hold_with_disagreeing_counts() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        let now = Utc::now();
+
+        let hold = inventory.hold(&trip, &Class::Second, 2, now).unwrap();
+        // The held count went out of step with the hold, as after a restore
+        inventory
+            .stock
+            .lock()
+            .unwrap()
+            .seats(&trip, &Class::Second)
+            .held = 1;
+
+        inventory.convert_hold(&hold, now).unwrap();
+        assert_eq!(
+            inventory.available(&trip, &Class::Second),
+            default_capacity(&Class::Second) - 2
+        );
+    }
+
+    #[test]
+    fn test_convert_hold() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        inventory.set_capacity(&trip, &Class::Second, 1);
+        let now = Utc::now();
+
+        let hold = inventory.hold(&trip, &Class::Second, 1, now).unwrap();
+        assert_eq!(inventory.convert_hold(&hold, now).unwrap(), None);
+
+        // A sold seat doesn't expire, and can't be converted twice
+        assert_eq!(inventory.sweep(now + HOLD_TTL), 0);
+        assert_eq!(inventory.available(&trip, &Class::Second), 0);
+        assert!(inventory.convert_hold(&hold, now).is_err());
+
+        inventory.revert_sale(hold.clone(), &trip, &Class::Second, 1, None, now);
+        assert_eq!(inventory.available(&trip, &Class::Second), 0);
+        inventory.release_hold(&hold);
+        assert_eq!(inventory.available(&trip, &Class::Second), 1);
+    }
+
+    #[test]
+    fn test_hold_for_group() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        inventory.set_capacity(&trip, &Class::First, 5);
+        let now = Utc::now();
+
+        let hold = inventory.hold(&trip, &Class::First, 3, now).unwrap();
+        assert_eq!(inventory.available(&trip, &Class::First), 2);
+        assert!(matches!(
+            inventory.hold(&trip, &Class::First, 3, now),
+            Err(InventoryError::SoldOut(..))
+        ));
+        assert!(matches!(
+            inventory.reserve(&trip, &Class::First, 3),
+            Err(InventoryError::SoldOut(..))
+        ));
+
+        inventory.convert_hold(&hold, now).unwrap();
+        assert_eq!(inventory.available(&trip, &Class::First), 2);
+        inventory.release(&trip, &Class::First, 3, None);
+        assert_eq!(inventory.available(&trip, &Class::First), 5);
+    }
+
+    #[test]
+    fn test_assign_seat() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        let seat = SeatId { coach: 3, seat: 1 };
+        let now = Utc::now();
+
+        let hold = inventory.hold(&trip, &Class::Second, 1, now).unwrap();
+        inventory.assign_seat(&hold, seat, now).unwrap();
+        inventory.assign_seat(&hold, seat, now).unwrap();
+
+        let other = inventory.hold(&trip, &Class::Second, 1, now).unwrap();
+        assert!(matches!(
+            inventory.assign_seat(&other, seat, now),
+            Err(InventoryError::SeatTaken(_))
+        ));
+        // The same seat on another trip is a different seat
+        let elsewhere = inventory.hold(&trip_id(), &Class::Second, 1, now).unwrap();
+        inventory.assign_seat(&elsewhere, seat, now).unwrap();
+
+        // Selling the seat keeps it taken, until it's released
+        assert_eq!(inventory.convert_hold(&hold, now).unwrap(), Some(seat));
+        assert!(inventory.taken_seats(&trip, now).contains(&seat));
+        inventory.release(&trip, &Class::Second, 1, Some(seat));
+        inventory.assign_seat(&other, seat, now).unwrap();
+        inventory.unassign_seat(&other);
+        assert!(inventory.taken_seats(&trip, now).is_empty());
+        inventory.assign_seat(&other, seat, now).unwrap();
+
+        // Expired holds let go of their seat, even before they're swept
+        assert!(inventory.taken_seats(&trip, now + HOLD_TTL).is_empty());
+        inventory.sweep(now + HOLD_TTL);
+        assert!(inventory.taken_seats(&trip, now).is_empty());
+    }
+
+    #[test]
+    fn test_ancillaries() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        let ancillary = |ancillary_type, quantity| Ancillary {
+            ancillary_type,
+            quantity,
+        };
+        inventory.set_ancillary_capacity(&trip, &AncillaryType::Bicycle, 2);
+
+        inventory
+            .reserve_ancillaries(&trip, &[ancillary(AncillaryType::Bicycle, 2)])
+            .unwrap();
+        // Nothing is sold if any one of them doesn't fit
+        assert!(matches!(
+            inventory.reserve_ancillaries(
+                &trip,
+                &[
+                    ancillary(AncillaryType::Pet, 1),
+                    ancillary(AncillaryType::Bicycle, 1)
+                ]
+            ),
+            Err(InventoryError::AncillarySoldOut(_, AncillaryType::Bicycle))
+        ));
+        assert_eq!(
+            inventory.available_ancillary(&trip, &AncillaryType::Pet),
+            default_ancillary_capacity(&AncillaryType::Pet)
+        );
+
+        inventory.release_ancillaries(&trip, &[ancillary(AncillaryType::Bicycle, 1)]);
+        assert_eq!(
+            inventory.available_ancillary(&trip, &AncillaryType::Bicycle),
+            1
+        );
+    }
+
+    #[test]
+    fn test_wheelchair_spaces() {
+        let inventory = Inventory::default();
+        let trip = trip_id();
+        inventory.set_wheelchair_spaces(&trip, &Class::Second, 2);
+
+        inventory
+            .reserve_wheelchair_spaces(&trip, &Class::Second, 2)
+            .unwrap();
+        assert!(matches!(
+            inventory.reserve_wheelchair_spaces(&trip, &Class::Second, 1),
+            Err(InventoryError::NoWheelchairSpace(..))
+        ));
+        assert_eq!(
+            inventory.available_wheelchair_spaces(&trip, &Class::First),
+            default_wheelchair_spaces(&Class::First)
+        );
+
+        inventory.release_wheelchair_spaces(&trip, &Class::Second, 1);
+        inventory
+            .reserve_wheelchair_spaces(&trip, &Class::Second, 1)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_restore() {
+        let inventory = Inventory::default();
+        let mut booking = booking();
+        booking.seat = Some(SeatId { coach: 3, seat: 1 });
+        booking.ancillaries = serde_json::from_value(serde_json::json!([
+            { "type": "bicycle", "quantity": 1 },
+        ]))
+        .unwrap();
+        inventory.set_capacity(&booking.trip.id, &Class::Second, 1);
+
+        // Bookings that are already made count, even when overbooked
+        inventory.restore(&booking);
+        inventory.restore(&booking);
+        assert_eq!(inventory.available(&booking.trip.id, &Class::Second), 0);
+        assert!(inventory
+            .taken_seats(&booking.trip.id, Utc::now())
+            .contains(&SeatId { coach: 3, seat: 1 }));
+        assert_eq!(
+            inventory.available_ancillary(&booking.trip.id, &AncillaryType::Bicycle),
+            default_ancillary_capacity(&AncillaryType::Bicycle) - 2
+        );
+    }
+}
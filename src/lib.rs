@@ -1,29 +1,108 @@
+use access::{Conductor, Operator, OwnedBooking, StaffTokens};
 use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json,
 };
 use axum_session::{SessionConfig, SessionLayer, SessionNullSessionStore, SessionStore};
+use chrono::{DateTime, Utc};
 use error::Error;
+use etag::{ETag, IfMatch};
+use inventory::{Inventory, InventoryError};
+use qrcode::QrCode;
+use repository::{BookingRepository, RepositoryError, SqliteBookingRepository};
 use session::{Session, SessionExt};
 
+use state::AppState;
+use std::sync::Arc;
+use takeoff_tickets::{ScanOutcome, SignedTicket, TicketError, Train};
+use tickets::TicketIssuer;
+use timetable::Timetable;
 use tokio::net::TcpListener;
 use types::{
+    address::BillingAddress,
+    ancillary::{Ancillaries, Ancillary, AncillaryType},
+    booking::{Booking, BookingReference, BookingStatus, ChangeError, Charge, Leg, PriceBreakdown},
     class::Class,
     customer_details::{Email, Name, PhoneNumber},
     departure_or_arrival::{DepartureOrArrival, FutureTimestamp},
+    fare::FareError,
+    invoice::{Invoice, InvoiceLine, InvoiceNumber},
+    journey::JourneyType,
     location::Location,
+    money::{Currency, ExchangeRates, Locale, Money},
+    passenger::Passengers,
     payment_info::PaymentInfo,
+    seat::SeatId,
+    ticket::{self, TicketScan},
     ticket_machine::TicketMachine,
     trip::{Trip, TripId},
+    voucher::VoucherCode,
+};
+use views::{
+    AncillaryOptionView, AssistanceRequestView, Availability, BookingView, ChangeOptionView,
+    CoachView, ScanView, SeatView, TicketKeyView, TicketMachineView, TicketView, TripView,
+    VoucherView,
 };
 
+pub mod access;
 pub mod error;
+pub mod etag;
+pub mod idempotency;
+pub mod inventory;
+pub mod payment;
+pub mod pdf;
+pub mod qr;
+pub mod repository;
 pub mod session;
+pub mod state;
+pub mod storage;
+pub mod tickets;
+pub mod timetable;
 pub mod types;
+pub mod views;
+pub mod vouchers;
 
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 pub async fn run() -> Result<()> {
+    // Stored bookings outlive a restart, so their tickets have to be
+    // signed with a key that does too
+    if std::env::var_os("TAKEOFF_DATABASE").is_some()
+        && std::env::var_os("TAKEOFF_TICKET_KEY").is_none()
+    {
+        return Err(Error::Config(
+            "Set TAKEOFF_TICKET_KEY when bookings are stored in TAKEOFF_DATABASE",
+        ));
+    }
+    // Store bookings in SQLite if a database is configured,
+    // and keep them in memory otherwise
+    let state = match std::env::var("TAKEOFF_DATABASE") {
+        Ok(path) => AppState::new(SqliteBookingRepository::open(path)?),
+        Err(_) => AppState::default(),
+    };
+    // Sign tickets with the configured key, so that they stay valid
+    // across restarts and can be verified by others
+    let state = match std::env::var("TAKEOFF_TICKET_KEY") {
+        Ok(key) => AppState {
+            tickets: Arc::new(TicketIssuer::from_base64(&key)?),
+            ..state
+        },
+        Err(_) => state,
+    };
+    let state = AppState {
+        staff: Arc::new(StaffTokens::from_env()),
+        ..state
+    };
+    // Pick up where we left off with the bookings that are stored already
+    state.restore()?;
+
+    // Release seats held in abandoned sessions
+    tokio::spawn(inventory::sweep_holds(state.inventory.clone()));
+
     // Setup router
     let router = axum::Router::new()
         .route("/origin", post(set_origin))
@@ -32,11 +111,49 @@ pub async fn run() -> Result<()> {
         .route("/arrival", post(set_arrival))
         .route("/trips", get(list_trips))
         .route("/trip", post(set_trip))
+        .route("/journey_type", post(set_journey_type))
+        .route("/return_departure", post(set_return_departure))
+        .route("/return_arrival", post(set_return_arrival))
+        .route("/return_trips", get(list_return_trips))
+        .route("/return_trip", post(set_return_trip))
         .route("/class", post(set_class))
+        .route("/trips/:id/seats", get(list_seats))
+        .route("/seat", post(set_seat))
+        .route("/passengers", post(set_passengers))
+        .route("/ancillaries", get(list_ancillaries).post(set_ancillaries))
         .route("/name", post(set_name))
         .route("/email", post(set_email))
         .route("/phone_number", post(set_phone_number))
-        .route("/book_trip", post(book_trip));
+        .route("/billing_address", post(set_billing_address))
+        .route("/voucher", post(set_voucher))
+        .route(
+            "/book_trip",
+            post(book_trip).layer(middleware::from_fn_with_state(
+                state.clone(),
+                idempotency::idempotency,
+            )),
+        )
+        .route("/bookings/:reference", get(get_booking))
+        .route(
+            "/bookings/:reference/cancel",
+            post(cancel_booking).layer(middleware::from_fn_with_state(
+                state.clone(),
+                idempotency::idempotency,
+            )),
+        )
+        .route("/bookings/:reference/alternatives", get(list_alternatives))
+        .route("/bookings/:reference/invoice", get(get_invoice))
+        .route("/bookings/:reference/ticket", get(get_tickets))
+        .route("/tickets/public_key", get(get_ticket_key))
+        .route("/verify", post(verify_ticket))
+        .route("/operator/trips/:id/assistance", get(export_assistance))
+        .route(
+            "/bookings/:reference/change",
+            post(change_booking).layer(middleware::from_fn_with_state(
+                state.clone(),
+                idempotency::idempotency,
+            )),
+        );
 
     // Create in-memory session store
     let session_store: SessionNullSessionStore = SessionStore::new(None, SessionConfig::default())
@@ -45,6 +162,7 @@ pub async fn run() -> Result<()> {
 
     // Stitch them together
     let app = router
+        .with_state(state)
         .layer(SessionLayer::new(session_store))
         .into_make_service();
 
@@ -55,107 +173,1300 @@ pub async fn run() -> Result<()> {
     Ok(())
 }
 
-async fn set_origin(session: Session, Json(origin): Json<Location>) -> Result<Json<TicketMachine>> {
-    Ok(session.get_or_init_state(|s| {
+/// Response to each step: the updated state, tagged with its revision
+type StateResponse = (ETag, Json<TicketMachineView>);
+
+fn state_response(state: TicketMachine) -> StateResponse {
+    (ETag(state.revision), Json(state.into()))
+}
+
+async fn set_origin(
+    session: Session,
+    if_match: IfMatch,
+    Json(origin): Json<Location>,
+) -> Result<StateResponse> {
+    let state = session.get_or_init_state(&if_match, |s| {
         s.origin = Some(origin);
-    }))
-    .map(Json)
+    })?;
+    Ok(state_response(state))
 }
 
 async fn set_destination(
     session: Session,
+    if_match: IfMatch,
     Json(destination): Json<Location>,
-) -> Result<Json<TicketMachine>> {
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.destination = Some(destination))
+        .update_state(&if_match, |s| s.destination = Some(destination))?
         .ok_or(Error::BadRequest("Set origin first"))
-        .map(Json)
+        .map(state_response)
 }
 
 async fn set_departure(
     session: Session,
+    if_match: IfMatch,
     Json(departure): Json<FutureTimestamp>,
-) -> Result<Json<TicketMachine>> {
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.time = Some(DepartureOrArrival::Departure(departure)))
+        .update_state(&if_match, |s| {
+            s.time = Some(DepartureOrArrival::Departure(departure))
+        })?
         .ok_or(Error::BadRequest("Set destination first"))
-        .map(Json)
+        .map(state_response)
 }
 
 async fn set_arrival(
     session: Session,
+    if_match: IfMatch,
     Json(arrival): Json<FutureTimestamp>,
-) -> Result<Json<TicketMachine>> {
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.time = Some(DepartureOrArrival::Arrival(arrival)))
+        .update_state(&if_match, |s| {
+            s.time = Some(DepartureOrArrival::Arrival(arrival))
+        })?
         .ok_or(Error::BadRequest("Set destination first"))
-        .map(Json)
+        .map(state_response)
 }
 
-async fn list_trips(session: Session) -> Result<Json<Vec<Trip>>> {
-    let state = session
-        .try_get_state()
+/// How the customer wants to see prices. Prices are converted into
+/// `currency` for display only: they're charged in the currency they're
+/// quoted in.
+#[derive(Debug, Default, serde::Deserialize)]
+struct DisplayOptions {
+    currency: Option<Currency>,
+    #[serde(default)]
+    locale: Locale,
+}
+
+impl DisplayOptions {
+    fn display(&self, money: Money, rates: &ExchangeRates) -> Result<String> {
+        let money = rates.convert(money, self.currency.unwrap_or(money.currency))?;
+        Ok(money.format(self.locale))
+    }
+}
+
+async fn list_trips(
+    State(state): State<AppState>,
+    session: Session,
+    Query(display): Query<DisplayOptions>,
+) -> Result<Json<Vec<TripView>>> {
+    let ticket_machine = session
+        .try_get_state()?
         .ok_or(Error::BadRequest("Set trip details first"))?;
 
-    let ((origin, destination), time) = state
+    let ((origin, destination), time) = ticket_machine
         .origin
-        .zip(state.destination)
-        .zip(state.time)
+        .zip(ticket_machine.destination)
+        .zip(ticket_machine.time)
         .ok_or(Error::BadRequest("Trip details incomplete"))?;
 
-    Ok(Trip::list_matching(origin, destination, time)).map(Json)
+    let now = Utc::now();
+    let trips = state.timetable.list_matching(origin, destination, time);
+    trip_views(&state, trips, &display, |trip, class| {
+        state.fares.quote(trip, class, now)
+    })
+    .map(Json)
+}
+
+/// List `trips` with the seats left and the price in each class, as
+/// worked out by `quote`
+fn trip_views(
+    state: &AppState,
+    trips: Vec<Trip>,
+    display: &DisplayOptions,
+    quote: impl Fn(&Trip, &Class) -> std::result::Result<Money, FareError>,
+) -> Result<Vec<TripView>> {
+    trips
+        .into_iter()
+        .map(|trip| {
+            let availability = [Class::First, Class::Second]
+                .into_iter()
+                .map(|class| {
+                    let price = quote(&trip, &class)?;
+                    Ok(Availability {
+                        seats: state.inventory.available(&trip.id, &class),
+                        display_price: display.display(price, &state.fares.exchange_rates)?,
+                        price,
+                        class,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Ok(TripView { trip, availability })
+        })
+        .collect()
+}
+
+async fn set_trip(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(trip_id): Json<TripId>,
+) -> Result<StateResponse> {
+    if state.timetable.find(&trip_id).is_none() {
+        return Err(Error::BadRequest("Unknown trip"));
+    }
+
+    // A seat held on another trip is of no use anymore, once
+    // the other trip is selected
+    let mut previous = None;
+    let ticket_machine = session
+        .update_state(&if_match, |s| {
+            previous = s.hold.take();
+            s.seat = None;
+            s.trip = Some(trip_id)
+        })?
+        .ok_or(Error::BadRequest("Set departure or arrival time first"))?;
+    if let Some(previous) = previous {
+        state.inventory.release_hold(&previous);
+    }
+    Ok(state_response(ticket_machine))
 }
 
-async fn set_trip(session: Session, Json(trip_id): Json<TripId>) -> Result<Json<TicketMachine>> {
+async fn set_journey_type(
+    session: Session,
+    if_match: IfMatch,
+    Json(journey_type): Json<JourneyType>,
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.trip = Some(trip_id))
-        .ok_or(Error::BadRequest("Set departure or arrival time first"))
+        .update_state(&if_match, |s| {
+            if journey_type != JourneyType::Return {
+                s.return_time = None;
+                s.return_trip = None;
+            }
+            s.journey_type = journey_type;
+        })?
+        .ok_or(Error::BadRequest("Set origin first"))
+        .map(state_response)
+}
+
+/// Set when to travel back, which only a return needs
+fn set_return_time(
+    session: &Session,
+    if_match: &IfMatch,
+    time: DepartureOrArrival,
+) -> Result<StateResponse> {
+    // Checked on the state that's updated, so that the journey can't
+    // become a single one in between
+    let mut is_return = false;
+    let ticket_machine = session
+        .update_state(if_match, |s| {
+            is_return = s.journey_type == JourneyType::Return;
+            if is_return {
+                s.return_time = Some(time);
+            }
+        })?
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    if !is_return {
+        return Err(Error::BadRequest("Choose a return journey first"));
+    }
+
+    Ok(state_response(ticket_machine))
+}
+
+async fn set_return_departure(
+    session: Session,
+    if_match: IfMatch,
+    Json(departure): Json<FutureTimestamp>,
+) -> Result<StateResponse> {
+    set_return_time(
+        &session,
+        &if_match,
+        DepartureOrArrival::Departure(departure),
+    )
+}
+
+async fn set_return_arrival(
+    session: Session,
+    if_match: IfMatch,
+    Json(arrival): Json<FutureTimestamp>,
+) -> Result<StateResponse> {
+    set_return_time(&session, &if_match, DepartureOrArrival::Arrival(arrival))
+}
+
+/// List the trips back that depart after the trip out arrives, priced
+/// as a return for the passengers together with the trip out
+async fn list_return_trips(
+    State(state): State<AppState>,
+    session: Session,
+    Query(display): Query<DisplayOptions>,
+) -> Result<Json<Vec<TripView>>> {
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Set trip details first"))?;
+    let passengers = ticket_machine.fare_categories();
+    let outbound = ticket_machine
+        .trip
+        .and_then(|trip| state.timetable.find(&trip))
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    let time = ticket_machine.return_time.ok_or(Error::BadRequest(
+        "Set return departure or arrival time first",
+    ))?;
+
+    let now = Utc::now();
+    let trips = state
+        .timetable
+        .list_matching(outbound.destination.clone(), outbound.origin.clone(), time)
+        .into_iter()
+        .filter(|trip| trip.departure >= outbound.arrival)
+        .collect();
+    trip_views(&state, trips, &display, |trip, class| {
+        state
+            .fares
+            .quote_return(&outbound, Some(trip), class, &passengers, now)
+    })
+    .map(Json)
+}
+
+async fn set_return_trip(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(trip_id): Json<TripId>,
+) -> Result<StateResponse> {
+    let trip = state
+        .timetable
+        .find(&trip_id)
+        .ok_or(Error::BadRequest("Unknown trip"))?;
+
+    // Checked on the state that's updated, so that the journey type
+    // and the trip out can't change in between
+    let mut checked = Ok(());
+    let ticket_machine = session
+        .update_state(&if_match, |s| {
+            checked = check_return_trip(&state.timetable, s, &trip);
+            if checked.is_ok() {
+                s.return_trip = Some(trip_id);
+            }
+        })?
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    checked?;
+
+    Ok(state_response(ticket_machine))
+}
+
+/// Check that `trip` can be taken back on the journey in `ticket_machine`
+fn check_return_trip(
+    timetable: &Timetable,
+    ticket_machine: &TicketMachine,
+    trip: &Trip,
+) -> Result<()> {
+    if ticket_machine.journey_type != JourneyType::Return {
+        return Err(Error::BadRequest("Choose a return journey first"));
+    }
+    let outbound = ticket_machine
+        .trip
+        .as_ref()
+        .and_then(|trip| timetable.find(trip))
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    if trip.origin != outbound.destination || trip.destination != outbound.origin {
+        return Err(Error::BadRequest("The return trip must go the other way"));
+    }
+    if trip.departure < outbound.arrival {
+        return Err(Error::BadRequest(
+            "The return trip departs before the trip out arrives",
+        ));
+    }
+    Ok(())
+}
+
+async fn set_class(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(class): Json<Class>,
+) -> Result<StateResponse> {
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    let trip = ticket_machine
+        .trip
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    let (count, wheelchairs) = ticket_machine
+        .passengers
+        .map_or((1, 0), |p| (p.seats(), p.wheelchair_spaces()));
+    check_wheelchair_spaces(&state.inventory, &trip, &class, wheelchairs)?;
+
+    // Hold seats in the selected class, letting go of any seats held before
+    let hold = state.inventory.hold(&trip, &class, count, Utc::now())?;
+    let mut previous = None;
+    let result = session.update_state(&if_match, |s| {
+        previous = s.hold.replace(hold.clone());
+        s.seat = None;
+        s.class = Some(class);
+    });
+
+    match result {
+        Ok(Some(ticket_machine)) => {
+            if let Some(previous) = previous {
+                state.inventory.release_hold(&previous);
+            }
+            Ok(state_response(ticket_machine))
+        }
+        Ok(None) => {
+            state.inventory.release_hold(&hold);
+            Err(Error::BadRequest("Select a trip first"))
+        }
+        Err(e) => {
+            state.inventory.release_hold(&hold);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_seats(
+    State(state): State<AppState>,
+    Path(trip): Path<TripId>,
+) -> Result<Json<Vec<CoachView>>> {
+    let layout = state
+        .timetable
+        .seat_layout(&trip)
+        .ok_or(Error::NotFound("Trip not found"))?;
+    let taken = state.inventory.taken_seats(&trip, Utc::now());
+
+    let coaches = layout
+        .coaches
+        .into_iter()
+        .map(|coach| CoachView {
+            number: coach.number,
+            class: coach.class,
+            seats: coach
+                .seats
+                .into_iter()
+                .map(|seat| SeatView {
+                    available: !taken.contains(&seat.id),
+                    id: seat.id,
+                    attributes: seat.attributes,
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Json(coaches))
+}
+
+async fn set_seat(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(seat): Json<SeatId>,
+) -> Result<StateResponse> {
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Set class first"))?;
+    let (Some(trip), Some(class), Some(hold)) = (
+        ticket_machine.trip,
+        ticket_machine.class,
+        ticket_machine.hold,
+    ) else {
+        return Err(Error::BadRequest("Set class first"));
+    };
+
+    let layout = state
+        .timetable
+        .seat_layout(&trip)
+        .ok_or(Error::BadRequest("Selected trip is no longer available"))?;
+    match layout.find(&seat) {
+        Some((_, seat_class)) if *seat_class == class => {}
+        Some(_) => return Err(Error::BadRequest("Seat is not in the selected class")),
+        None => return Err(Error::BadRequest("No such seat on the selected trip")),
+    }
+    let now = Utc::now();
+    state.inventory.assign_seat(&hold, seat, now)?;
+
+    let result = session.update_state(&if_match, |s| s.seat = Some(seat));
+    if !matches!(result, Ok(Some(_))) {
+        // Go back to the seat that was picked before, if it's still free
+        let previous = ticket_machine
+            .seat
+            .map(|previous| state.inventory.assign_seat(&hold, previous, now));
+        if !matches!(previous, Some(Ok(()))) {
+            state.inventory.unassign_seat(&hold);
+        }
+    }
+    result?
+        .ok_or(Error::BadRequest("Set class first"))
+        .map(state_response)
+}
+
+async fn set_passengers(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(passengers): Json<Passengers>,
+) -> Result<StateResponse> {
+    passengers.check_dates_of_birth(Utc::now().date_naive())?;
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+
+    // Once a class is chosen, hold seats for the new group instead
+    let hold = match (&ticket_machine.trip, &ticket_machine.class) {
+        (Some(trip), Some(class)) => {
+            check_wheelchair_spaces(
+                &state.inventory,
+                trip,
+                class,
+                passengers.wheelchair_spaces(),
+            )?;
+            Some(
+                state
+                    .inventory
+                    .hold(trip, class, passengers.seats(), Utc::now())?,
+            )
+        }
+        _ => None,
+    };
+    let mut previous = None;
+    let result = session.update_state(&if_match, |s| {
+        if hold.is_some() {
+            previous = std::mem::replace(&mut s.hold, hold.clone());
+            s.seat = None;
+        }
+        s.passengers = Some(passengers);
+    });
+
+    let release_new = || {
+        if let Some(hold) = &hold {
+            state.inventory.release_hold(hold);
+        }
+    };
+    match result {
+        Ok(Some(ticket_machine)) => {
+            if let Some(previous) = previous {
+                state.inventory.release_hold(&previous);
+            }
+            Ok(state_response(ticket_machine))
+        }
+        Ok(None) => {
+            release_new();
+            Err(Error::BadRequest("Select a trip first"))
+        }
+        Err(e) => {
+            release_new();
+            Err(e.into())
+        }
+    }
+}
+
+/// List the add-ons that can be taken on the selected trips
+async fn list_ancillaries(
+    State(state): State<AppState>,
+    session: Session,
+    Query(display): Query<DisplayOptions>,
+) -> Result<Json<Vec<AncillaryOptionView>>> {
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    let (trip, return_trip) = ticket_machine.trips(&state.timetable)?;
+    let trips: Vec<&Trip> = std::iter::once(&trip).chain(&return_trip).collect();
+
+    AncillaryType::ALL
+        .into_iter()
+        .filter(|ancillary_type| trips.iter().all(|t| t.carries.contains(ancillary_type)))
+        .map(|ancillary_type| {
+            let price = state.fares.quote_ancillaries(
+                &trip,
+                &ticket_machine.journey_type,
+                &[Ancillary {
+                    ancillary_type,
+                    quantity: 1,
+                }],
+            )?;
+            Ok(AncillaryOptionView {
+                ancillary_type,
+                available: trips
+                    .iter()
+                    .map(|t| state.inventory.available_ancillary(&t.id, &ancillary_type))
+                    .min()
+                    .unwrap_or(0),
+                display_price: display.display(price, &state.fares.exchange_rates)?,
+                price,
+            })
+        })
+        .collect::<Result<_>>()
         .map(Json)
 }
 
-async fn set_class(session: Session, Json(class): Json<Class>) -> Result<Json<TicketMachine>> {
+/// Choose the add-ons to take along, replacing any chosen before. They must
+/// be carried on the selected trips, and there must be room for them, but
+/// that room is only taken when booking.
+async fn set_ancillaries(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(ancillaries): Json<Ancillaries>,
+) -> Result<StateResponse> {
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Select a trip first"))?;
+    let (trip, return_trip) = ticket_machine.trips(&state.timetable)?;
+    ancillaries.check_passengers(ticket_machine.passengers.map_or(1, |p| p.seats()))?;
+    for trip in std::iter::once(&trip).chain(&return_trip) {
+        ancillaries.check_carried(trip)?;
+        for ancillary in ancillaries.iter() {
+            let available = state
+                .inventory
+                .available_ancillary(&trip.id, &ancillary.ancillary_type);
+            if available < ancillary.quantity {
+                return Err(InventoryError::AncillarySoldOut(
+                    trip.id.clone(),
+                    ancillary.ancillary_type,
+                )
+                .into());
+            }
+        }
+    }
+
     session
-        .update_state(|s| s.class = Some(class))
+        .update_state(&if_match, |s| s.ancillaries = ancillaries)?
         .ok_or(Error::BadRequest("Select a trip first"))
-        .map(Json)
+        .inspect(|s| extend_hold(&state.inventory, s))
+        .map(state_response)
 }
 
-async fn set_name(session: Session, Json(name): Json<Name>) -> Result<Json<TicketMachine>> {
+/// Keep the seat held for as long as the customer is busy booking. A hold
+/// that expired already is left alone: booking will report that.
+fn extend_hold(inventory: &Inventory, ticket_machine: &TicketMachine) {
+    if let Some(hold) = &ticket_machine.hold {
+        let _ = inventory.extend_hold(hold, Utc::now());
+    }
+}
+
+async fn set_name(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(name): Json<Name>,
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.name = Some(name))
+        .update_state(&if_match, |s| s.name = Some(name))?
         .ok_or(Error::BadRequest("Set class first"))
-        .map(Json)
+        .inspect(|s| extend_hold(&state.inventory, s))
+        .map(state_response)
 }
 
-async fn set_email(session: Session, Json(email): Json<Email>) -> Result<Json<TicketMachine>> {
+async fn set_email(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(email): Json<Email>,
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.email = Some(email))
+        .update_state(&if_match, |s| s.email = Some(email))?
         .ok_or(Error::BadRequest("Set name first"))
-        .map(Json)
+        .inspect(|s| extend_hold(&state.inventory, s))
+        .map(state_response)
 }
 
 async fn set_phone_number(
+    State(state): State<AppState>,
     session: Session,
+    if_match: IfMatch,
     Json(phone_number): Json<PhoneNumber>,
-) -> Result<Json<TicketMachine>> {
+) -> Result<StateResponse> {
     session
-        .update_state(|s| s.phone_number = Some(phone_number))
+        .update_state(&if_match, |s| s.phone_number = Some(phone_number))?
         .ok_or(Error::BadRequest("Set email first"))
-        .map(Json)
+        .inspect(|s| extend_hold(&state.inventory, s))
+        .map(state_response)
+}
+
+/// Set the address invoices are made out to, or remove it by sending `null`
+async fn set_billing_address(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(billing_address): Json<Option<BillingAddress>>,
+) -> Result<StateResponse> {
+    session
+        .update_state(&if_match, |s| s.billing_address = billing_address)?
+        .ok_or(Error::BadRequest("Select a trip first"))
+        .inspect(|s| extend_hold(&state.inventory, s))
+        .map(state_response)
+}
+
+/// Check a voucher against the selected trips, class and passengers, and
+/// against the email address if that's been set already. It's only redeemed
+/// when booking, so it may still turn out to be used up by then.
+async fn set_voucher(
+    State(state): State<AppState>,
+    session: Session,
+    if_match: IfMatch,
+    Json(code): Json<VoucherCode>,
+) -> Result<(ETag, Json<VoucherView>)> {
+    let ticket_machine = session
+        .try_get_state()?
+        .ok_or(Error::BadRequest("Set class first"))?;
+    let price = ticket_machine.quote(&state.timetable, &state.fares)?;
+    let Some(class) = &ticket_machine.class else {
+        return Err(Error::BadRequest("Set class first"));
+    };
+    let (trip, return_trip) = ticket_machine.trips(&state.timetable)?;
+    let trips: Vec<&Trip> = std::iter::once(&trip).chain(&return_trip).collect();
+
+    let voucher = state.vouchers.check(
+        &code,
+        &trips,
+        class,
+        ticket_machine.email.as_ref(),
+        Utc::now(),
+    )?;
+    let discount = voucher.discount(price, &state.fares.exchange_rates)?;
+    let discounted_price = price.checked_sub(discount)?;
+
+    let ticket_machine = session
+        .update_state(&if_match, |s| s.voucher = Some(code))?
+        .ok_or(Error::BadRequest("Set class first"))?;
+    extend_hold(&state.inventory, &ticket_machine);
+    Ok((
+        ETag(ticket_machine.revision),
+        Json(VoucherView {
+            state: ticket_machine.into(),
+            price,
+            discount,
+            discounted_price,
+        }),
+    ))
 }
 
 async fn book_trip(
+    State(state): State<AppState>,
     session: Session,
+    if_match: IfMatch,
     Json(payment_info): Json<PaymentInfo>,
-) -> Result<Json<TicketMachine>> {
-    session
-        .update_state(|s| {
-            s.payment_info = Some(payment_info);
+) -> Result<Json<BookingView>> {
+    // Taken out of the session up front, so that concurrent requests
+    // can't book and charge for the same state twice
+    let taken = session
+        .take_state(&if_match)?
+        .ok_or(Error::BadRequest("Set phone_number first"))?;
+    let put_back = |e: Error| match session.put_back_state(taken.clone()) {
+        Ok(()) => e,
+        Err(_) => Error::Internal("The booking failed, and the session was lost"),
+    };
+    let mut ticket_machine = taken.clone();
+    ticket_machine.payment_info = Some(payment_info);
+
+    let booking = ticket_machine
+        .book(
+            &state.timetable,
+            &state.inventory,
+            &state.fares,
+            &state.vouchers,
+            state.payments.as_ref(),
+        )
+        .map_err(put_back)?;
+    let mut unstored = booking.clone();
+    let booking = match store_booking(state.bookings.as_ref(), booking) {
+        Ok(booking) => booking,
+        Err(e) => {
+            // The customer has paid for a booking that doesn't exist
+            release_seats(&state.inventory, &unstored);
+            if let Some(voucher) = &unstored.voucher {
+                state
+                    .vouchers
+                    .unredeem(&voucher.code, &unstored.contact.email);
+            }
+            unstored
+                .refund(state.payments.as_ref(), unstored.price, Utc::now())
+                .map_err(|_| {
+                    Error::Internal("The booking failed, and the payment could not be refunded")
+                })?;
+            return Err(put_back(e));
+        }
+    };
+
+    Ok(Json(booking.into()))
+}
+
+/// Put the seats taken by `booking` back up for sale, both ways, along
+/// with its wheelchair spaces and the room for its add-ons
+fn release_seats(inventory: &Inventory, booking: &Booking) {
+    let (count, wheelchairs) = (booking.seats(), booking.wheelchair_spaces());
+    for (trip, seat) in std::iter::once((&booking.trip, booking.seat))
+        .chain(booking.return_trip.iter().map(|t| (t, None)))
+    {
+        inventory.release(&trip.id, &booking.class, count, seat);
+        inventory.release_wheelchair_spaces(&trip.id, &booking.class, wheelchairs);
+        inventory.release_ancillaries(&trip.id, &booking.ancillaries);
+    }
+}
+
+/// Sell `count` seats in `class` on `trip`, along with
+/// wheelchair spaces for `wheelchairs` of the passengers
+fn reserve_seats(
+    inventory: &Inventory,
+    trip: &TripId,
+    class: &Class,
+    count: u32,
+    wheelchairs: u32,
+) -> Result<()> {
+    inventory.reserve_wheelchair_spaces(trip, class, wheelchairs)?;
+    inventory
+        .reserve(trip, class, count)
+        .inspect_err(|_| inventory.release_wheelchair_spaces(trip, class, wheelchairs))?;
+    Ok(())
+}
+
+/// Check that there are wheelchair spaces left for `wheelchairs`
+/// passengers, without taking them yet
+fn check_wheelchair_spaces(
+    inventory: &Inventory,
+    trip: &TripId,
+    class: &Class,
+    wheelchairs: u32,
+) -> Result<()> {
+    if inventory.available_wheelchair_spaces(trip, class) < wheelchairs {
+        return Err(InventoryError::NoWheelchairSpace(trip.clone(), class.clone()).into());
+    }
+    Ok(())
+}
+
+/// Store a new booking, generating a fresh reference in the
+/// unlikely case that its reference has been taken already
+fn store_booking(bookings: &dyn BookingRepository, mut booking: Booking) -> Result<Booking> {
+    const MAX_ATTEMPTS: usize = 3;
+
+    let mut attempt = 1;
+    loop {
+        match bookings.insert(&booking) {
+            Ok(()) => return Ok(booking),
+            Err(RepositoryError::DuplicateReference(_)) if attempt < MAX_ATTEMPTS => {
+                attempt += 1;
+                booking.reference = BookingReference::generate();
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+async fn get_booking(OwnedBooking(booking): OwnedBooking) -> Json<BookingView> {
+    Json(booking.into())
+}
+
+/// Cancel a booking and refund what the cancellation policy allows. The
+/// booking is moved on before the refund is paid, and only if no other
+/// request did so first, so the refund can't be paid twice. If the refund
+/// fails, cancelling again tries again.
+async fn cancel_booking(
+    State(state): State<AppState>,
+    OwnedBooking(mut booking): OwnedBooking,
+) -> Result<Json<BookingView>> {
+    let status = booking.status;
+    booking.cancel(&state.cancellation_policy, Utc::now())?;
+    state.bookings.update_from(status, &booking)?;
+    if status == BookingStatus::Confirmed {
+        release_seats(&state.inventory, &booking);
+        if let Some(voucher) = &booking.voucher {
+            state
+                .vouchers
+                .unredeem(&voucher.code, &booking.contact.email);
+        }
+    }
+
+    if booking.status == BookingStatus::RefundPending {
+        let result = booking.settle_refund(state.payments.as_ref(), Utc::now());
+        state.bookings.update(&booking)?;
+        result?;
+    }
+
+    Ok(Json(booking.into()))
+}
+
+async fn list_alternatives(
+    State(state): State<AppState>,
+    OwnedBooking(booking): OwnedBooking,
+    Query(display): Query<DisplayOptions>,
+) -> Result<Json<Vec<ChangeOptionView>>> {
+    let departure = FutureTimestamp::try_from(booking.trip.departure)
+        .map_err(|_| ChangeError::AlreadyDeparted)?;
+    let trips = state.timetable.list_matching(
+        booking.trip.origin.clone(),
+        booking.trip.destination.clone(),
+        DepartureOrArrival::Departure(departure),
+    );
+
+    let now = Utc::now();
+    let mut options = vec![];
+    for trip in std::iter::once(booking.trip.clone()).chain(trips) {
+        for class in [Class::First, Class::Second] {
+            let to = Leg {
+                trip: trip.clone(),
+                price: quote_booking(&state, &booking, &trip, &class, now)?.total()?,
+                class,
+            };
+            match booking.check_change(&to, now) {
+                Ok(_)
+                    if state.inventory.available(&to.trip.id, &to.class) < booking.seats()
+                        || state
+                            .inventory
+                            .available_wheelchair_spaces(&to.trip.id, &to.class)
+                            < booking.wheelchair_spaces() =>
+                {
+                    continue
+                }
+                Ok(fare_difference) => options.push(ChangeOptionView {
+                    display_fare_difference: display
+                        .display(fare_difference, &state.fares.exchange_rates)?,
+                    trip: to.trip,
+                    class: to.class,
+                    price: to.price,
+                    fare_difference,
+                }),
+                Err(
+                    ChangeError::Unchanged
+                    | ChangeError::AfterReturn
+                    | ChangeError::AncillariesNotCarried,
+                ) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    Ok(Json(options))
+}
+
+/// What the price of `trip` in `class` for the passengers and add-ons on
+/// `booking` is made up of, together with the way back for a return. The
+/// discount of a voucher that was used carries over, as far as it goes.
+fn quote_booking(
+    state: &AppState,
+    booking: &Booking,
+    trip: &Trip,
+    class: &Class,
+    now: DateTime<Utc>,
+) -> Result<PriceBreakdown> {
+    let passengers = booking.fare_categories();
+    let transport = match booking.journey_type {
+        JourneyType::Single => state.fares.quote_group(trip, class, &passengers, now)?,
+        JourneyType::Return | JourneyType::OpenReturn => {
+            state
+                .fares
+                .quote_return(trip, booking.return_trip.as_ref(), class, &passengers, now)?
+        }
+    };
+    let ancillaries = booking
+        .ancillaries
+        .iter()
+        .map(|ancillary| {
+            state
+                .fares
+                .quote_ancillary(trip, &booking.journey_type, ancillary)
         })
-        .ok_or(Error::BadRequest("Set phone_number first"))
-        .map(|t| {
-            t.book()?;
-            Ok(t)
-        })?
-        .map(Json)
+        .collect::<std::result::Result<_, _>>()?;
+    let mut breakdown = PriceBreakdown {
+        transport,
+        ancillaries,
+        discount: Money::new(0, transport.currency),
+    };
+    if let Some(voucher) = &booking.voucher {
+        let subtotal = breakdown.subtotal()?;
+        breakdown.discount = Money::new(
+            voucher.discount.amount.min(subtotal.amount),
+            subtotal.currency,
+        );
+    }
+    Ok(breakdown)
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum InvoiceFormat {
+    #[default]
+    Json,
+    Pdf,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct InvoiceOptions {
+    #[serde(default)]
+    format: InvoiceFormat,
+}
+
+/// Get the invoice for a booking, issuing it the first time it's asked for
+async fn get_invoice(
+    State(state): State<AppState>,
+    OwnedBooking(booking): OwnedBooking,
+    Query(options): Query<InvoiceOptions>,
+) -> Result<Response> {
+    let invoice = match state.bookings.get_invoice(&booking.reference)? {
+        Some(invoice) => invoice,
+        None => {
+            // Draw up the invoice before it's numbered, so that
+            // a failure doesn't use up a number
+            let lines = InvoiceLine::for_booking(&booking, &state.fares)?;
+            let draft = Invoice::new(InvoiceNumber(0), &booking, lines, Utc::now())?;
+            let issued = state.bookings.issue_invoice(&|number| Invoice {
+                number,
+                ..draft.clone()
+            });
+            match issued {
+                // Another request invoiced the booking in the meantime
+                Err(RepositoryError::DuplicateInvoice(_)) => state
+                    .bookings
+                    .get_invoice(&booking.reference)?
+                    .ok_or(Error::Internal("The invoice that was issued is missing"))?,
+                issued => issued?,
+            }
+        }
+    };
+
+    Ok(match options.format {
+        InvoiceFormat::Json => Json(invoice).into_response(),
+        InvoiceFormat::Pdf => {
+            let filename = format!("attachment; filename=\"invoice-{}.pdf\"", invoice.number);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_owned()),
+                    (header::CONTENT_DISPOSITION, filename),
+                ],
+                pdf::invoice(&invoice)?,
+            )
+                .into_response()
+        }
+    })
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TicketFormat {
+    Json,
+    #[default]
+    Pdf,
+    Png,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TicketOptions {
+    #[serde(default)]
+    format: TicketFormat,
+    /// Which ticket to get the QR code of, as a PNG
+    #[serde(default)]
+    ticket: usize,
+}
+
+/// Get the signed tickets for a confirmed booking, with their QR codes
+async fn get_tickets(
+    State(state): State<AppState>,
+    OwnedBooking(booking): OwnedBooking,
+    Query(options): Query<TicketOptions>,
+) -> Result<Response> {
+    if booking.status != BookingStatus::Confirmed {
+        return Err(Error::Conflict("Only confirmed bookings have tickets"));
+    }
+    let tickets: Vec<_> = ticket::for_booking(&booking)
+        .into_iter()
+        .map(|ticket| {
+            let signed = state.tickets.sign(&ticket);
+            TicketView { ticket, signed }
+        })
+        .collect();
+
+    Ok(match options.format {
+        TicketFormat::Json => Json(tickets).into_response(),
+        TicketFormat::Png => {
+            let ticket = tickets
+                .get(options.ticket)
+                .ok_or(Error::NotFound("Ticket not found"))?;
+            let code = QrCode::new(ticket.signed.as_str())?;
+            ([(header::CONTENT_TYPE, "image/png")], qr::png(&code, 8)?).into_response()
+        }
+        TicketFormat::Pdf => {
+            let tickets = tickets
+                .into_iter()
+                .map(|TicketView { ticket, signed }| Ok((ticket, QrCode::new(signed.as_str())?)))
+                .collect::<Result<Vec<_>>>()?;
+            let filename = format!("attachment; filename=\"tickets-{}.pdf\"", booking.reference);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_owned()),
+                    (header::CONTENT_DISPOSITION, filename),
+                ],
+                pdf::tickets(&tickets)?,
+            )
+                .into_response()
+        }
+    })
+}
+
+/// The public key to verify ticket signatures with, for the devices
+/// that check tickets offline
+async fn get_ticket_key(State(state): State<AppState>) -> Json<TicketKeyView> {
+    Json(TicketKeyView {
+        public_key: takeoff_tickets::encode_key(&state.tickets.verifying_key()),
+    })
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ScanRequest {
+    /// The ticket as read from its QR code
+    ticket: SignedTicket,
+    /// The trip of the train it was scanned on
+    trip: TripId,
+}
+
+/// Check a ticket a conductor scanned, and log the scan. Tickets that
+/// aren't genuine are rejected; the outcome of checking genuine ones is
+/// returned and logged. Only for conductors and operator staff.
+async fn verify_ticket(
+    State(state): State<AppState>,
+    _: Conductor,
+    Json(request): Json<ScanRequest>,
+) -> Result<Json<ScanView>> {
+    let ticket = takeoff_tickets::verify(&state.tickets.verifying_key(), &request.ticket)?;
+    let reference =
+        BookingReference::try_from(ticket.reference.clone()).map_err(|_| TicketError::Malformed)?;
+    // Unlike the devices that check tickets offline, we know whether the
+    // booking still has this ticket, or has been cancelled or changed
+    let booking = state.bookings.get(&reference)?.filter(|booking| {
+        booking.status == BookingStatus::Confirmed && ticket::for_booking(booking).contains(&ticket)
+    });
+
+    let scanned_on: uuid::Uuid = request.trip.clone().into();
+    // The outcome if it's known without the train. A ticket bound to a
+    // trip is on the wrong train on any other, even one that has left
+    // the timetable.
+    let train = match &booking {
+        None => Err(ScanOutcome::Revoked),
+        Some(_) if ticket.trip.is_some_and(|trip| trip != scanned_on) => {
+            Err(ScanOutcome::WrongTrain)
+        }
+        // The trips of the booking are kept after they've left the
+        // timetable. Other trains are looked up there, as the way back
+        // of an open return can be taken on any train on its route.
+        Some(booking) => {
+            let trip = std::iter::once(&booking.trip)
+                .chain(&booking.return_trip)
+                .find(|trip| trip.id == request.trip)
+                .cloned()
+                .or_else(|| state.timetable.find(&request.trip))
+                .ok_or(Error::NotFound("Trip not found"))?;
+            Ok(Train {
+                trip: trip.id.into(),
+                origin: trip.origin.to_string(),
+                destination: trip.destination.to_string(),
+            })
+        }
+    };
+    let now = Utc::now();
+    let scan = state
+        .bookings
+        .record_scan(request.ticket.id(), &|accepted_before| TicketScan {
+            ticket: request.ticket.id().to_owned(),
+            reference: reference.clone(),
+            trip: request.trip.clone(),
+            outcome: match &train {
+                Ok(train) => takeoff_tickets::check(&ticket, train, now, accepted_before),
+                Err(outcome) => *outcome,
+            },
+            scanned_at: now,
+        })?;
+
+    Ok(Json(ScanView {
+        outcome: scan.outcome,
+        ticket,
+    }))
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ExportOptions {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Export the assistance that passengers on confirmed bookings asked for
+/// on a trip, for the operator to arrange. Only for operator staff.
+async fn export_assistance(
+    State(state): State<AppState>,
+    _: Operator,
+    Path(trip): Path<TripId>,
+    Query(options): Query<ExportOptions>,
+) -> Result<Response> {
+    let bookings = state.bookings.find_by_trip(&trip)?;
+    // The trips of the bookings are kept after they've left the
+    // timetable, which is only needed for trips nobody booked
+    let trip = bookings
+        .iter()
+        .flat_map(|b| std::iter::once(&b.trip).chain(&b.return_trip))
+        .find(|t| t.id == trip)
+        .cloned()
+        .or_else(|| state.timetable.find(&trip))
+        .ok_or(Error::NotFound("Trip not found"))?;
+    let requests: Vec<_> = bookings
+        .iter()
+        .filter(|b| b.status == BookingStatus::Confirmed)
+        .flat_map(|b| AssistanceRequestView::for_trip(b, &trip))
+        .collect();
+
+    Ok(match options.format {
+        ExportFormat::Json => Json(requests).into_response(),
+        ExportFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv")],
+            AssistanceRequestView::to_csv(&requests),
+        )
+            .into_response(),
+    })
+}
+
+/// Request to move a booking to another trip and/or class. Payment info
+/// is only needed if the new fare is higher than what was paid.
+#[derive(Debug, serde::Deserialize)]
+struct ChangeRequest {
+    trip: Option<TripId>,
+    class: Option<Class>,
+    payment_info: Option<PaymentInfo>,
+}
+
+async fn change_booking(
+    State(state): State<AppState>,
+    OwnedBooking(mut booking): OwnedBooking,
+    Json(request): Json<ChangeRequest>,
+) -> Result<Json<BookingView>> {
+    let trip = match request.trip {
+        Some(id) if id != booking.trip.id => state
+            .timetable
+            .find(&id)
+            .ok_or(Error::BadRequest("Unknown trip"))?,
+        _ => booking.trip.clone(),
+    };
+    let class = request.class.unwrap_or_else(|| booking.class.clone());
+    let now = Utc::now();
+    let breakdown = quote_booking(&state, &booking, &trip, &class, now)?;
+    let to = Leg {
+        price: breakdown.total()?,
+        trip,
+        class,
+    };
+
+    // Take a seat and charge any fare difference before
+    // changing, and refund the difference after
+    let fare_difference = booking.check_change(&to, now)?;
+    booking.check_passengers_for(&to.trip)?;
+    let payment_info = if fare_difference.amount > 0 {
+        let payment_info = request.payment_info.ok_or(Error::BadRequest(
+            "Provide payment info to pay the fare difference",
+        ))?;
+        Some(payment_info)
+    } else {
+        None
+    };
+
+    // The way back of a return is in the same class, so it moves along
+    let (count, wheelchairs) = (booking.seats(), booking.wheelchair_spaces());
+    let return_trip = booking
+        .return_trip
+        .clone()
+        .filter(|_| to.class != booking.class);
+    // So do the add-ons, if the trip out changes
+    let ancillaries = if to.trip.id != booking.trip.id {
+        booking.ancillaries.to_vec()
+    } else {
+        vec![]
+    };
+    let (to_trip, to_class) = (to.trip.id.clone(), to.class.clone());
+    state
+        .inventory
+        .reserve_ancillaries(&to_trip, &ancillaries)?;
+    let release_to = || {
+        state.inventory.release(&to_trip, &to_class, count, None);
+        state
+            .inventory
+            .release_wheelchair_spaces(&to_trip, &to_class, wheelchairs);
+        state.inventory.release_ancillaries(&to_trip, &ancillaries);
+    };
+    reserve_seats(&state.inventory, &to_trip, &to_class, count, wheelchairs)
+        .inspect_err(|_| state.inventory.release_ancillaries(&to_trip, &ancillaries))?;
+    if let Some(return_trip) = &return_trip {
+        reserve_seats(
+            &state.inventory,
+            &return_trip.id,
+            &to_class,
+            count,
+            wheelchairs,
+        )
+        .inspect_err(|_| release_to())?;
+    }
+    let release_new = || {
+        release_to();
+        if let Some(return_trip) = &return_trip {
+            state
+                .inventory
+                .release(&return_trip.id, &to_class, count, None);
+            state
+                .inventory
+                .release_wheelchair_spaces(&return_trip.id, &to_class, wheelchairs);
+        }
+    };
+    let charge = match payment_info {
+        Some(payment_info) => match state.payments.charge(&payment_info, fare_difference) {
+            Ok(id) => Some(Charge {
+                id,
+                amount: fare_difference,
+                at: now,
+            }),
+            Err(e) => {
+                release_new();
+                return Err(e.into());
+            }
+        },
+        None => None,
+    };
+
+    // Only change the booking if it's still confirmed, or the
+    // seats and the fare difference go back
+    let (from, seat) = (booking.leg(), booking.seat);
+    let result = booking
+        .change(to, breakdown, charge.clone(), now)
+        .map_err(Error::from)
+        .and_then(|_| {
+            Ok(state
+                .bookings
+                .update_from(BookingStatus::Confirmed, &booking)?)
+        });
+    if let Err(e) = result {
+        release_new();
+        if let Some(charge) = charge {
+            state
+                .payments
+                .refund(&charge.id, charge.amount)
+                .map_err(|_| {
+                    Error::Internal("The change failed, and the payment could not be refunded")
+                })?;
+        }
+        return Err(e);
+    }
+    state
+        .inventory
+        .release(&from.trip.id, &from.class, count, seat);
+    state
+        .inventory
+        .release_wheelchair_spaces(&from.trip.id, &from.class, wheelchairs);
+    state
+        .inventory
+        .release_ancillaries(&from.trip.id, &ancillaries);
+    if let Some(return_trip) = &return_trip {
+        state
+            .inventory
+            .release(&return_trip.id, &from.class, count, None);
+        state
+            .inventory
+            .release_wheelchair_spaces(&return_trip.id, &from.class, wheelchairs);
+    }
+
+    if fare_difference.amount < 0 {
+        let refund = Money::new(-fare_difference.amount, fare_difference.currency);
+        let result = booking.refund(state.payments.as_ref(), refund, now);
+        state.bookings.update(&booking)?;
+        result?;
+    }
+
+    Ok(Json(booking.into()))
 }
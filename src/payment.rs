@@ -0,0 +1,110 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use uuid::Uuid;
+
+use crate::types::{money::Money, payment_info::PaymentInfo};
+
+/// Identifies a charge made through a [`PaymentGateway`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct PaymentId(Uuid);
+
+/// Identifies a refund made through a [`PaymentGateway`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct RefundId(Uuid);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PaymentError {
+    #[error("Payment was declined")]
+    Declined,
+
+    #[error("Unknown payment")]
+    UnknownPayment,
+
+    #[error("Refund exceeds the amount that was charged")]
+    RefundExceedsCharge,
+}
+
+/// The payment provider that charges customers and refunds them
+pub trait PaymentGateway: Send + Sync {
+    /// Charge `amount` using the customer's payment details
+    fn charge(&self, payment_info: &PaymentInfo, amount: Money) -> Result<PaymentId, PaymentError>;
+
+    /// Refund (part of) an earlier charge
+    fn refund(&self, payment: &PaymentId, amount: Money) -> Result<RefundId, PaymentError>;
+}
+
+#[derive(Debug)]
+struct Charge {
+    amount: Money,
+    refunded: i64,
+}
+
+/// A payment gateway that doesn't move any actual money, but does keep
+/// track of what was charged and refunded
+#[derive(Debug, Default)]
+pub struct FakePaymentGateway {
+    charges: Mutex<HashMap<PaymentId, Charge>>,
+}
+
+impl PaymentGateway for FakePaymentGateway {
+    fn charge(
+        &self,
+        _payment_info: &PaymentInfo,
+        amount: Money,
+    ) -> Result<PaymentId, PaymentError> {
+        let id = PaymentId(Uuid::new_v4());
+        self.charges.lock().unwrap().insert(
+            id.clone(),
+            Charge {
+                amount,
+                refunded: 0,
+            },
+        );
+        Ok(id)
+    }
+
+    fn refund(&self, payment: &PaymentId, amount: Money) -> Result<RefundId, PaymentError> {
+        let mut charges = self.charges.lock().unwrap();
+        let charge = charges
+            .get_mut(payment)
+            .ok_or(PaymentError::UnknownPayment)?;
+
+        if amount.currency != charge.amount.currency
+            || amount.amount < 0
+            || charge.refunded + amount.amount > charge.amount.amount
+        {
+            return Err(PaymentError::RefundExceedsCharge);
+        }
+        charge.refunded += amount.amount;
+        Ok(RefundId(Uuid::new_v4()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FakePaymentGateway, PaymentError, PaymentGateway};
+    use crate::types::money::{Currency, Money};
+
+    #[test]
+    fn test_refunds_cannot_exceed_charge() {
+        let gateway = FakePaymentGateway::default();
+        let payment = gateway
+            .charge(&"1234".to_owned().into(), Money::new(1000, Currency::Eur))
+            .unwrap();
+
+        gateway
+            .refund(&payment, Money::new(600, Currency::Eur))
+            .unwrap();
+        assert!(matches!(
+            gateway.refund(&payment, Money::new(600, Currency::Eur)),
+            Err(PaymentError::RefundExceedsCharge)
+        ));
+        assert!(matches!(
+            gateway.refund(&payment, Money::new(400, Currency::Gbp)),
+            Err(PaymentError::RefundExceedsCharge)
+        ));
+        gateway
+            .refund(&payment, Money::new(400, Currency::Eur))
+            .unwrap();
+    }
+}
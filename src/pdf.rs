@@ -0,0 +1,273 @@
+use chrono::{DateTime, Utc};
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Rect,
+};
+use qrcode::QrCode;
+use takeoff_tickets::{Class, DiscountCardType};
+
+use crate::{
+    qr,
+    types::{invoice::Invoice, ticket::TicketPayload},
+};
+
+/// A4, in portrait
+const PAGE_WIDTH: Mm = Mm(210.0);
+const PAGE_HEIGHT: Mm = Mm(297.0);
+const MARGIN: f32 = 20.0;
+
+/// Writes text on a single page, from the top down. The built-in fonts are
+/// used, which means only characters in Windows-1252 can be printed, but
+/// that includes the currency symbols we need.
+struct Page {
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    /// The distance from the bottom of the page to the next line, in mm
+    y: f32,
+}
+
+/// The regular and the bold font, which are added to a document once
+/// and then used on all of its pages
+fn fonts(
+    doc: &PdfDocumentReference,
+) -> Result<(IndirectFontRef, IndirectFontRef), printpdf::Error> {
+    Ok((
+        doc.add_builtin_font(BuiltinFont::Helvetica)?,
+        doc.add_builtin_font(BuiltinFont::HelveticaBold)?,
+    ))
+}
+
+impl Page {
+    fn new(layer: PdfLayerReference, (regular, bold): &(IndirectFontRef, IndirectFontRef)) -> Self {
+        Self {
+            layer,
+            regular: regular.clone(),
+            bold: bold.clone(),
+            y: PAGE_HEIGHT.0 - MARGIN,
+        }
+    }
+
+    /// Write `columns` of text on the next line, each starting at the
+    /// given distance from the left margin
+    fn line(&mut self, size: f32, bold: bool, columns: &[(f32, &str)]) {
+        let font = if bold { &self.bold } else { &self.regular };
+        for (x, text) in columns {
+            self.layer
+                .use_text(*text, size, Mm(MARGIN + x), Mm(self.y), font);
+        }
+        // Leave some room between the lines
+        self.y -= size * 0.5;
+    }
+
+    fn skip(&mut self, mm: f32) {
+        self.y -= mm;
+    }
+
+    /// Draw `code` on the next lines, `size` mm wide, with a rectangle
+    /// for every dark module
+    fn qr_code(&mut self, code: &QrCode, size: f32) {
+        let modules = qr::modules(code);
+        let module = size / modules.len() as f32;
+        let top = self.y;
+        for (y, row) in modules.iter().enumerate() {
+            for (x, _) in row.iter().enumerate().filter(|(_, &dark)| dark) {
+                let (left, bottom) = (MARGIN + x as f32 * module, top - (y + 1) as f32 * module);
+                self.layer.add_rect(Rect::new(
+                    Mm(left),
+                    Mm(bottom),
+                    Mm(left + module),
+                    Mm(bottom + module),
+                ));
+            }
+        }
+        self.y -= size;
+    }
+}
+
+/// Render `invoice` as a single-page PDF
+pub fn invoice(invoice: &Invoice) -> Result<Vec<u8>, printpdf::Error> {
+    let title = format!("Invoice {}", invoice.number);
+    let (doc, page, layer) = PdfDocument::new(&title, PAGE_WIDTH, PAGE_HEIGHT, "Invoice");
+    let fonts = fonts(&doc)?;
+    let mut page = Page::new(doc.get_page(page).get_layer(layer), &fonts);
+
+    page.line(20.0, true, &[(0.0, &title)]);
+    page.skip(4.0);
+    let issued_at = invoice.issued_at.format("%Y-%m-%d").to_string();
+    for (label, value) in [
+        ("Date", issued_at.as_str()),
+        ("Booking reference", invoice.reference.as_str()),
+    ] {
+        page.line(10.0, false, &[(0.0, label), (45.0, value)]);
+    }
+
+    page.skip(6.0);
+    page.line(10.0, true, &[(0.0, "Billed to")]);
+    let billed_to = match &invoice.billing_address {
+        Some(address) => address.to_string(),
+        None => invoice.contact.name.to_string(),
+    };
+    for line in billed_to.lines() {
+        page.line(10.0, false, &[(0.0, line)]);
+    }
+    let email = String::from(invoice.contact.email.clone());
+    page.line(10.0, false, &[(0.0, &email)]);
+
+    // The amounts go in columns on the right
+    let columns = [95.0, 115.0, 140.0];
+    page.skip(6.0);
+    page.line(
+        10.0,
+        true,
+        &[
+            (0.0, "Description"),
+            (columns[0], "VAT"),
+            (columns[1], "Net"),
+            (columns[2], "Total"),
+        ],
+    );
+    for line in &invoice.lines {
+        let vat = format!("{}%", line.vat_percentage);
+        page.line(
+            9.0,
+            false,
+            &[
+                (0.0, &line.description),
+                (columns[0], &vat),
+                (columns[1], &line.net.to_string()),
+                (columns[2], &line.gross.to_string()),
+            ],
+        );
+    }
+
+    page.skip(4.0);
+    for summary in &invoice.vat {
+        let label = format!(
+            "VAT {}% ({}) on {}",
+            summary.vat_percentage,
+            summary.country.name(),
+            summary.net
+        );
+        page.line(
+            9.0,
+            false,
+            &[(0.0, &label), (columns[2], &summary.vat.to_string())],
+        );
+    }
+    page.skip(2.0);
+    for (label, amount, bold) in [
+        ("Total excluding VAT", invoice.total_net, false),
+        ("Total VAT", invoice.total_vat, false),
+        ("Total", invoice.total, true),
+    ] {
+        page.line(
+            10.0,
+            bold,
+            &[(0.0, label), (columns[2], &amount.to_string())],
+        );
+    }
+
+    doc.save_to_bytes()
+}
+
+/// Render `tickets` as a PDF, with a page for every ticket and its QR code
+pub fn tickets(tickets: &[(TicketPayload, QrCode)]) -> Result<Vec<u8>, printpdf::Error> {
+    let (doc, first_page, first_layer) =
+        PdfDocument::new("Tickets", PAGE_WIDTH, PAGE_HEIGHT, "Ticket");
+    let fonts = fonts(&doc)?;
+
+    for (i, (ticket, code)) in tickets.iter().enumerate() {
+        let (page, layer) = match i {
+            0 => (first_page, first_layer),
+            _ => doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, "Ticket"),
+        };
+        let mut page = Page::new(doc.get_page(page).get_layer(layer), &fonts);
+
+        let route = format!("{} - {}", ticket.origin, ticket.destination);
+        page.line(20.0, true, &[(0.0, &route)]);
+        page.skip(4.0);
+        let class = match ticket.class {
+            Class::First => "First",
+            Class::Second => "Second",
+        };
+        let trip = match ticket.trip {
+            Some(_) => "This trip only",
+            None => "Any trip on this route",
+        };
+        let format = |time: DateTime<Utc>| time.format("%Y-%m-%d %H:%M UTC").to_string();
+        let (passenger, valid_from, valid_until) = (
+            ticket.passenger.to_string(),
+            format(ticket.valid_from),
+            format(ticket.valid_until),
+        );
+        let discount_card = ticket.discount_card.map(|card| match card {
+            DiscountCardType::YoungPersonsRailcard => "16-25 Railcard, to be shown on board",
+            DiscountCardType::SeniorRailcard => "Senior Railcard, to be shown on board",
+            DiscountCardType::BahnCard25 => "BahnCard 25, to be shown on board",
+            DiscountCardType::BahnCard50 => "BahnCard 50, to be shown on board",
+        });
+        let lines = [
+            ("Passenger", Some(passenger.as_str())),
+            ("Discount card", discount_card),
+            ("Class", Some(class)),
+            ("Valid on", Some(trip)),
+            ("Valid from", Some(valid_from.as_str())),
+            ("Valid until", Some(valid_until.as_str())),
+            ("Booking reference", Some(ticket.reference.as_str())),
+        ];
+        for (label, value) in lines {
+            if let Some(value) = value {
+                page.line(10.0, false, &[(0.0, label), (45.0, value)]);
+            }
+        }
+        page.skip(6.0);
+        page.qr_code(code, 80.0);
+    }
+
+    doc.save_to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use qrcode::QrCode;
+
+    use crate::types::{
+        booking::tests::booking,
+        fare::FareEngine,
+        invoice::{Invoice, InvoiceLine, InvoiceNumber},
+        ticket,
+    };
+
+    #[test]
+    fn test_tickets() {
+        let mut booking = booking();
+        booking.passengers = serde_json::from_value(serde_json::json!([{
+            "name": "Henk",
+            "type": "adult",
+            "discount_card": {
+                "card_type": "bahncard_25",
+                "number": "4992739871234568",
+                "valid_until": "2030-12-31",
+            },
+        }]))
+        .unwrap();
+        let tickets: Vec<_> = ticket::for_booking(&booking)
+            .into_iter()
+            .map(|ticket| (ticket, QrCode::new("TAKEOFF").unwrap()))
+            .collect();
+
+        let pdf = super::tickets(&tickets).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+    }
+
+    #[test]
+    fn test_invoice() {
+        let booking = booking();
+        let lines = InvoiceLine::for_booking(&booking, &FareEngine::default()).unwrap();
+        let invoice = Invoice::new(InvoiceNumber(1), &booking, lines, Utc::now()).unwrap();
+
+        let pdf = super::invoice(&invoice).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+    }
+}
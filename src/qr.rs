@@ -0,0 +1,72 @@
+use qrcode::{Color, QrCode};
+
+/// The number of light modules around a code, which scanners need to find it
+pub const QUIET_ZONE: usize = 4;
+
+/// The modules of `code` row by row, including the quiet zone, with
+/// `true` for the dark ones
+pub fn modules(code: &QrCode) -> Vec<Vec<bool>> {
+    let width = code.width();
+    let colors = code.to_colors();
+    let size = width + 2 * QUIET_ZONE;
+    (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| {
+                    let (x, y) = (x.wrapping_sub(QUIET_ZONE), y.wrapping_sub(QUIET_ZONE));
+                    x < width && y < width && colors[y * width + x] == Color::Dark
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Render `code` as a black and white PNG, with each module
+/// `scale` pixels wide
+pub fn png(code: &QrCode, scale: usize) -> Result<Vec<u8>, png::EncodingError> {
+    let modules = modules(code);
+    let size = modules.len() * scale;
+    let pixels: Vec<u8> = modules
+        .iter()
+        .flat_map(|row| {
+            let row: Vec<u8> = row
+                .iter()
+                .flat_map(|&dark| std::iter::repeat_n(if dark { 0 } else { 255 }, scale))
+                .collect();
+            std::iter::repeat_n(row, scale).flatten()
+        })
+        .collect();
+
+    let mut image = vec![];
+    let mut encoder = png::Encoder::new(&mut image, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use qrcode::QrCode;
+
+    use super::{modules, png, QUIET_ZONE};
+
+    #[test]
+    fn test_modules() {
+        let code = QrCode::new("TAKEOFF").unwrap();
+        let modules = modules(&code);
+        assert_eq!(modules.len(), code.width() + 2 * QUIET_ZONE);
+        assert!(modules[0].iter().all(|dark| !dark));
+        // The top left corner of the finder pattern
+        assert!(modules[QUIET_ZONE][QUIET_ZONE]);
+    }
+
+    #[test]
+    fn test_png() {
+        let code = QrCode::new("TAKEOFF").unwrap();
+        let image = png(&code, 4).unwrap();
+        assert!(image.starts_with(b"\x89PNG"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use super::{BookingRepository, RepositoryError};
+use crate::types::{
+    booking::{Booking, BookingReference, BookingStatus},
+    invoice::{Invoice, InvoiceNumber},
+    ticket::TicketScan,
+    trip::TripId,
+};
+
+/// Keeps bookings in memory, losing them when the process exits. Useful for
+/// development and testing.
+#[derive(Debug, Default)]
+pub struct InMemoryBookingRepository {
+    bookings: Mutex<HashMap<BookingReference, Booking>>,
+    /// In order of their numbers
+    invoices: Mutex<Vec<Invoice>>,
+    scans: Mutex<Vec<TicketScan>>,
+}
+
+impl BookingRepository for InMemoryBookingRepository {
+    fn insert(&self, booking: &Booking) -> Result<(), RepositoryError> {
+        let mut bookings = self.bookings.lock().unwrap();
+        if bookings.contains_key(&booking.reference) {
+            return Err(RepositoryError::DuplicateReference(
+                booking.reference.clone(),
+            ));
+        }
+        bookings.insert(booking.reference.clone(), booking.clone());
+        Ok(())
+    }
+
+    fn get(&self, reference: &BookingReference) -> Result<Option<Booking>, RepositoryError> {
+        Ok(self.bookings.lock().unwrap().get(reference).cloned())
+    }
+
+    fn update(&self, booking: &Booking) -> Result<(), RepositoryError> {
+        let mut bookings = self.bookings.lock().unwrap();
+        let existing = bookings
+            .get_mut(&booking.reference)
+            .ok_or_else(|| RepositoryError::NotFound(booking.reference.clone()))?;
+        *existing = booking.clone();
+        Ok(())
+    }
+
+    fn update_from(&self, status: BookingStatus, booking: &Booking) -> Result<(), RepositoryError> {
+        let mut bookings = self.bookings.lock().unwrap();
+        let existing = bookings
+            .get_mut(&booking.reference)
+            .ok_or_else(|| RepositoryError::NotFound(booking.reference.clone()))?;
+        if existing.status != status {
+            return Err(RepositoryError::StatusChanged(booking.reference.clone()));
+        }
+        *existing = booking.clone();
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<Booking>, RepositoryError> {
+        let mut all: Vec<_> = self.bookings.lock().unwrap().values().cloned().collect();
+        all.sort_by_key(|b| b.created_at);
+        Ok(all)
+    }
+
+    fn find_by_trip(&self, trip: &TripId) -> Result<Vec<Booking>, RepositoryError> {
+        let mut found: Vec<_> = self
+            .bookings
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|b| b.trip.id == *trip || b.return_trip.as_ref().is_some_and(|t| t.id == *trip))
+            .cloned()
+            .collect();
+        found.sort_by_key(|b| b.created_at);
+        Ok(found)
+    }
+
+    fn issue_invoice(
+        &self,
+        draw_up: &dyn Fn(InvoiceNumber) -> Invoice,
+    ) -> Result<Invoice, RepositoryError> {
+        let mut invoices = self.invoices.lock().unwrap();
+        let number = invoices
+            .last()
+            .map_or(InvoiceNumber(1), |i| i.number.next());
+        let invoice = draw_up(number);
+        if invoices.iter().any(|i| i.reference == invoice.reference) {
+            return Err(RepositoryError::DuplicateInvoice(invoice.reference));
+        }
+        invoices.push(invoice.clone());
+        Ok(invoice)
+    }
+
+    fn get_invoice(
+        &self,
+        reference: &BookingReference,
+    ) -> Result<Option<Invoice>, RepositoryError> {
+        Ok(self
+            .invoices
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|i| i.reference == *reference)
+            .cloned())
+    }
+
+    fn record_scan(
+        &self,
+        ticket: &str,
+        scan: &dyn Fn(bool) -> TicketScan,
+    ) -> Result<TicketScan, RepositoryError> {
+        let mut scans = self.scans.lock().unwrap();
+        let accepted_before = scans
+            .iter()
+            .any(|s| s.ticket == ticket && s.outcome.is_accepted());
+        let scan = scan(accepted_before);
+        scans.push(scan.clone());
+        Ok(scan)
+    }
+}
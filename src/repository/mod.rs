@@ -0,0 +1,215 @@
+use crate::types::{
+    booking::{Booking, BookingReference, BookingStatus},
+    invoice::{Invoice, InvoiceNumber},
+    ticket::TicketScan,
+    trip::TripId,
+};
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::InMemoryBookingRepository;
+pub use sqlite::SqliteBookingRepository;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepositoryError {
+    #[error("A booking with reference {0} already exists")]
+    DuplicateReference(BookingReference),
+
+    #[error("There is no booking with reference {0}")]
+    NotFound(BookingReference),
+
+    #[error("Booking {0} was changed by another request")]
+    StatusChanged(BookingReference),
+
+    #[error("Booking {0} has been invoiced already")]
+    DuplicateInvoice(BookingReference),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("Could not (de)serialize booking: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Persistent storage for [`Booking`]s
+pub trait BookingRepository: Send + Sync {
+    /// Store a new booking. Fails with [`RepositoryError::DuplicateReference`]
+    /// if its reference is already taken.
+    fn insert(&self, booking: &Booking) -> Result<(), RepositoryError>;
+
+    /// Look up a booking by its reference
+    fn get(&self, reference: &BookingReference) -> Result<Option<Booking>, RepositoryError>;
+
+    /// Replace an existing booking. Fails with [`RepositoryError::NotFound`]
+    /// if there is no booking with the same reference.
+    fn update(&self, booking: &Booking) -> Result<(), RepositoryError>;
+
+    /// Replace an existing booking, provided it's still in `status`, so
+    /// that of two requests moving a booking on, only one gets through.
+    /// Fails with [`RepositoryError::StatusChanged`] if it isn't.
+    fn update_from(&self, status: BookingStatus, booking: &Booking) -> Result<(), RepositoryError>;
+
+    /// All bookings, oldest first
+    fn all(&self) -> Result<Vec<Booking>, RepositoryError>;
+
+    /// The bookings travelling on `trip`, either as the trip out or as the
+    /// way back of a return, oldest first
+    fn find_by_trip(&self, trip: &TripId) -> Result<Vec<Booking>, RepositoryError>;
+
+    /// Store the invoice that `draw_up` makes out under the next number in
+    /// sequence, so that no two invoices get the same number. Fails with
+    /// [`RepositoryError::DuplicateInvoice`] if the booking it's for has
+    /// been invoiced already.
+    fn issue_invoice(
+        &self,
+        draw_up: &dyn Fn(InvoiceNumber) -> Invoice,
+    ) -> Result<Invoice, RepositoryError>;
+
+    /// Look up the invoice for the booking with `reference`
+    fn get_invoice(&self, reference: &BookingReference)
+        -> Result<Option<Invoice>, RepositoryError>;
+
+    /// Log a scan of the ticket with the given id, with the outcome that
+    /// `scan` finds depending on whether the ticket was accepted on an
+    /// earlier scan. Checking and logging happen in one go, so that a
+    /// ticket scanned on two trains at once is only accepted once.
+    fn record_scan(
+        &self,
+        ticket: &str,
+        scan: &dyn Fn(bool) -> TicketScan,
+    ) -> Result<TicketScan, RepositoryError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{
+        BookingRepository, InMemoryBookingRepository, RepositoryError, SqliteBookingRepository,
+    };
+    use crate::types::{
+        booking::{tests::booking, Booking, BookingStatus},
+        invoice::{Invoice, InvoiceNumber},
+        ticket::TicketScan,
+    };
+    use takeoff_tickets::ScanOutcome;
+
+    fn test_repository(repository: impl BookingRepository) {
+        let (booking, other) = (booking(), booking());
+        assert!(repository.get(&booking.reference).unwrap().is_none());
+
+        repository.insert(&booking).unwrap();
+        assert_eq!(
+            repository.get(&booking.reference).unwrap(),
+            Some(booking.clone())
+        );
+
+        assert!(matches!(
+            repository.insert(&booking),
+            Err(RepositoryError::DuplicateReference(r)) if r == booking.reference
+        ));
+
+        let mut cancelled = booking.clone();
+        cancelled
+            .transition(BookingStatus::Cancelled, Utc::now())
+            .unwrap();
+        repository.update(&cancelled).unwrap();
+        assert_eq!(repository.get(&booking.reference).unwrap(), Some(cancelled));
+
+        assert!(matches!(
+            repository.update(&other),
+            Err(RepositoryError::NotFound(r)) if r == other.reference
+        ));
+
+        // Another request got to the booking first
+        let mut refund_pending = booking.clone();
+        refund_pending
+            .transition(BookingStatus::Cancelled, Utc::now())
+            .unwrap();
+        refund_pending
+            .transition(BookingStatus::RefundPending, Utc::now())
+            .unwrap();
+        assert!(matches!(
+            repository.update_from(BookingStatus::Confirmed, &refund_pending),
+            Err(RepositoryError::StatusChanged(r)) if r == booking.reference
+        ));
+        repository
+            .update_from(BookingStatus::Cancelled, &refund_pending)
+            .unwrap();
+        assert_eq!(
+            repository.get(&booking.reference).unwrap(),
+            Some(refund_pending)
+        );
+        assert!(matches!(
+            repository.update_from(BookingStatus::Confirmed, &other),
+            Err(RepositoryError::NotFound(r)) if r == other.reference
+        ));
+
+        repository.insert(&other).unwrap();
+        let found = repository.find_by_trip(&other.trip.id).unwrap();
+        assert_eq!(found, std::slice::from_ref(&other));
+        let all = repository.all().unwrap();
+        assert_eq!(
+            all.iter().map(|b| &b.reference).collect::<Vec<_>>(),
+            [&booking.reference, &other.reference]
+        );
+
+        assert!(repository.get_invoice(&other.reference).unwrap().is_none());
+        fn draw_up(booking: &Booking) -> impl Fn(InvoiceNumber) -> Invoice + '_ {
+            |number| Invoice::new(number, booking, vec![], Utc::now()).unwrap()
+        }
+        let first = repository.issue_invoice(&draw_up(&booking)).unwrap();
+        let second = repository.issue_invoice(&draw_up(&other)).unwrap();
+        assert_eq!(first.number, InvoiceNumber(1));
+        assert_eq!(second.number, InvoiceNumber(2));
+        assert_eq!(
+            repository.get_invoice(&other.reference).unwrap(),
+            Some(second)
+        );
+        assert!(matches!(
+            repository.issue_invoice(&draw_up(&booking)),
+            Err(RepositoryError::DuplicateInvoice(r)) if r == booking.reference
+        ));
+
+        let (reference, trip) = (&booking.reference, &booking.trip.id);
+        let scan = |outcome| {
+            move |accepted_before| TicketScan {
+                ticket: "ticket".to_owned(),
+                reference: reference.clone(),
+                trip: trip.clone(),
+                outcome: match accepted_before {
+                    true => ScanOutcome::Duplicate,
+                    false => outcome,
+                },
+                scanned_at: Utc::now(),
+            }
+        };
+        let wrong_train = repository
+            .record_scan("ticket", &scan(ScanOutcome::WrongTrain))
+            .unwrap();
+        assert_eq!(wrong_train.outcome, ScanOutcome::WrongTrain);
+        let first = repository
+            .record_scan("ticket", &scan(ScanOutcome::First))
+            .unwrap();
+        assert_eq!(first.outcome, ScanOutcome::First);
+        let again = repository
+            .record_scan("ticket", &scan(ScanOutcome::First))
+            .unwrap();
+        assert_eq!(again.outcome, ScanOutcome::Duplicate);
+        let other = repository
+            .record_scan("other", &scan(ScanOutcome::First))
+            .unwrap();
+        assert_eq!(other.outcome, ScanOutcome::First);
+    }
+
+    #[test]
+    fn test_in_memory_repository() {
+        test_repository(InMemoryBookingRepository::default());
+    }
+
+    #[test]
+    fn test_sqlite_repository() {
+        test_repository(SqliteBookingRepository::open(":memory:").unwrap());
+    }
+}
@@ -0,0 +1,324 @@
+use std::{path::Path, sync::Mutex};
+
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use serde_json::{json, Value};
+
+use super::{BookingRepository, RepositoryError};
+use crate::types::{
+    booking::{Booking, BookingReference, BookingStatus},
+    invoice::{Invoice, InvoiceNumber},
+    ticket::TicketScan,
+    trip::TripId,
+};
+
+/// Stores bookings in an SQLite database. The booking itself is stored as
+/// JSON, next to the columns we need to look it up by.
+#[derive(Debug)]
+pub struct SqliteBookingRepository {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteBookingRepository {
+    /// Open the database at `path`, creating it if it doesn't exist. Pass
+    /// `":memory:"` to use a database that lives in memory.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RepositoryError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS bookings (
+                reference TEXT PRIMARY KEY NOT NULL,
+                status TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                booking TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS invoices (
+                number INTEGER PRIMARY KEY NOT NULL,
+                reference TEXT UNIQUE NOT NULL,
+                invoice TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS scans (
+                id INTEGER PRIMARY KEY NOT NULL,
+                ticket TEXT NOT NULL,
+                accepted INTEGER NOT NULL,
+                scan TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS scans_ticket ON scans (ticket);",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+/// Read a booking as it was stored. Bookings made before they could be
+/// changed were paid with a single `payment` for the full price, which
+/// is the first of the `charges` now.
+fn parse_booking(booking: &str) -> Result<Booking, serde_json::Error> {
+    let mut booking: Value = serde_json::from_str(booking)?;
+    if let Some(fields) = booking.as_object_mut() {
+        if let Some(payment) = fields.remove("payment") {
+            let charge = json!({
+                "id": payment,
+                "amount": fields.get("price"),
+                "at": fields.get("created_at"),
+            });
+            fields.entry("charges").or_insert_with(|| json!([charge]));
+        }
+    }
+    serde_json::from_value(booking)
+}
+
+impl BookingRepository for SqliteBookingRepository {
+    fn insert(&self, booking: &Booking) -> Result<(), RepositoryError> {
+        let status = serde_json::to_value(booking.status)?;
+        let result = self.connection.lock().unwrap().execute(
+            "INSERT INTO bookings (reference, status, created_at, updated_at, booking)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                booking.reference.as_str(),
+                status.as_str(),
+                booking.created_at,
+                booking.updated_at,
+                serde_json::to_string(booking)?,
+            ],
+        );
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == ErrorCode::ConstraintViolation =>
+            {
+                Err(RepositoryError::DuplicateReference(
+                    booking.reference.clone(),
+                ))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get(&self, reference: &BookingReference) -> Result<Option<Booking>, RepositoryError> {
+        let booking: Option<String> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT booking FROM bookings WHERE reference = ?1",
+                params![reference.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(booking.map(|b| parse_booking(&b)).transpose()?)
+    }
+
+    fn update(&self, booking: &Booking) -> Result<(), RepositoryError> {
+        let status = serde_json::to_value(booking.status)?;
+        let updated = self.connection.lock().unwrap().execute(
+            "UPDATE bookings SET status = ?2, updated_at = ?3, booking = ?4
+                WHERE reference = ?1",
+            params![
+                booking.reference.as_str(),
+                status.as_str(),
+                booking.updated_at,
+                serde_json::to_string(booking)?,
+            ],
+        )?;
+
+        if updated == 0 {
+            return Err(RepositoryError::NotFound(booking.reference.clone()));
+        }
+        Ok(())
+    }
+
+    fn update_from(&self, status: BookingStatus, booking: &Booking) -> Result<(), RepositoryError> {
+        let (from, to) = (
+            serde_json::to_value(status)?,
+            serde_json::to_value(booking.status)?,
+        );
+        let connection = self.connection.lock().unwrap();
+        let updated = connection.execute(
+            "UPDATE bookings SET status = ?3, updated_at = ?4, booking = ?5
+                WHERE reference = ?1 AND status = ?2",
+            params![
+                booking.reference.as_str(),
+                from.as_str(),
+                to.as_str(),
+                booking.updated_at,
+                serde_json::to_string(booking)?,
+            ],
+        )?;
+
+        if updated == 0 {
+            let exists: bool = connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM bookings WHERE reference = ?1)",
+                params![booking.reference.as_str()],
+                |row| row.get(0),
+            )?;
+            return Err(match exists {
+                true => RepositoryError::StatusChanged(booking.reference.clone()),
+                false => RepositoryError::NotFound(booking.reference.clone()),
+            });
+        }
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<Booking>, RepositoryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT booking FROM bookings ORDER BY created_at")?;
+        let bookings = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(bookings
+            .iter()
+            .map(|b| parse_booking(b))
+            .collect::<Result<_, _>>()?)
+    }
+
+    fn find_by_trip(&self, trip: &TripId) -> Result<Vec<Booking>, RepositoryError> {
+        let trip = serde_json::to_value(trip)?;
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT booking FROM bookings
+                WHERE json_extract(booking, '$.trip.id') = ?1
+                    OR json_extract(booking, '$.return_trip.id') = ?1
+                ORDER BY created_at",
+        )?;
+        let bookings = statement
+            .query_map(params![trip.as_str()], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(bookings
+            .iter()
+            .map(|b| parse_booking(b))
+            .collect::<Result<_, _>>()?)
+    }
+
+    fn issue_invoice(
+        &self,
+        draw_up: &dyn Fn(InvoiceNumber) -> Invoice,
+    ) -> Result<Invoice, RepositoryError> {
+        // Holding the lock from picking the number until the invoice is
+        // stored keeps the numbers from being handed out twice
+        let connection = self.connection.lock().unwrap();
+        let last: Option<u64> =
+            connection.query_row("SELECT MAX(number) FROM invoices", [], |row| row.get(0))?;
+        let invoice = draw_up(InvoiceNumber(last.unwrap_or(0) + 1));
+        let result = connection.execute(
+            "INSERT INTO invoices (number, reference, invoice) VALUES (?1, ?2, ?3)",
+            params![
+                invoice.number.0,
+                invoice.reference.as_str(),
+                serde_json::to_string(&invoice)?,
+            ],
+        );
+
+        match result {
+            Ok(_) => Ok(invoice),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == ErrorCode::ConstraintViolation =>
+            {
+                Err(RepositoryError::DuplicateInvoice(invoice.reference))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get_invoice(
+        &self,
+        reference: &BookingReference,
+    ) -> Result<Option<Invoice>, RepositoryError> {
+        let invoice: Option<String> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT invoice FROM invoices WHERE reference = ?1",
+                params![reference.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(invoice.map(|i| serde_json::from_str(&i)).transpose()?)
+    }
+
+    fn record_scan(
+        &self,
+        ticket: &str,
+        scan: &dyn Fn(bool) -> TicketScan,
+    ) -> Result<TicketScan, RepositoryError> {
+        // Holding the lock from the lookup until the scan is stored
+        // keeps two scans at once from both being accepted
+        let connection = self.connection.lock().unwrap();
+        let accepted_before: bool = connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM scans WHERE ticket = ?1 AND accepted)",
+            params![ticket],
+            |row| row.get(0),
+        )?;
+        let scan = scan(accepted_before);
+        connection.execute(
+            "INSERT INTO scans (ticket, accepted, scan) VALUES (?1, ?2, ?3)",
+            params![
+                ticket,
+                scan.outcome.is_accepted(),
+                serde_json::to_string(&scan)?,
+            ],
+        )?;
+        Ok(scan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::params;
+    use serde_json::json;
+
+    use super::SqliteBookingRepository;
+    use crate::{
+        payment::{FakePaymentGateway, PaymentGateway},
+        repository::BookingRepository,
+        types::booking::{tests::booking, Charge},
+    };
+
+    #[test]
+    fn test_booking_with_single_payment() {
+        let repository = SqliteBookingRepository::open(":memory:").unwrap();
+        let booking = booking();
+        let payment = FakePaymentGateway::default()
+            .charge(&"1234".to_owned().into(), booking.price)
+            .unwrap();
+        let mut stored = serde_json::to_value(&booking).unwrap();
+        let fields = stored.as_object_mut().unwrap();
+        for field in ["charges", "changes", "pending_refund"] {
+            fields.remove(field);
+        }
+        fields.insert("payment".to_owned(), json!(payment));
+        repository
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO bookings (reference, status, created_at, updated_at, booking)
+                    VALUES (?1, 'confirmed', ?2, ?2, ?3)",
+                params![
+                    booking.reference.as_str(),
+                    booking.created_at,
+                    stored.to_string()
+                ],
+            )
+            .unwrap();
+
+        let stored = repository.get(&booking.reference).unwrap().unwrap();
+        assert_eq!(
+            stored.charges,
+            [Charge {
+                id: payment,
+                amount: booking.price,
+                at: booking.created_at,
+            }]
+        );
+        assert!(stored.changes.is_empty());
+    }
+}
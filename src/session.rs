@@ -1,53 +1,561 @@
-use crate::types::ticket_machine::TicketMachine;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use serde_json::Value;
+
+use crate::{
+    etag::IfMatch,
+    storage::{migrate, MigrationError, VersionedState},
+    types::ticket_machine::TicketMachine,
+};
 
 pub type Session = axum_session::Session<axum_session::SessionNullPool>;
 
 const SESSION_STATE_KEY: &str = "STATE";
 
+/// A lock per session id, held from checking the revision of the state
+/// until the new revision is stored, so that two requests can't both
+/// update the same revision. Updates of different sessions don't wait
+/// on each other.
+static STATE_LOCKS: LazyLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("Session state was reset, please start over: {0}")]
+    StateReset(#[from] MigrationError),
+
+    #[error("Could not serialize session state: {0}")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("Session store failure: {0}")]
+    Store(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Session state was changed by another request")]
+    PreconditionFailed,
+
+    #[error("Session state lock was poisoned by a failed update")]
+    LockPoisoned,
+}
+
+/// The raw key-value operations [`SessionExt`] is built upon. Implemented
+/// for [`Session`], and for whatever test doubles need to stand in for it.
+pub trait SessionStorage {
+    /// Identifies the session, so that updates of it can be serialized
+    fn id(&self) -> String;
+
+    fn load(&self, key: &str) -> Result<Option<Value>, SessionError>;
+
+    fn store(&self, key: &str, value: Value) -> Result<(), SessionError>;
+
+    fn discard(&self, key: &str) -> Result<(), SessionError>;
+}
+
+impl SessionStorage for Session {
+    fn id(&self) -> String {
+        self.get_session_id()
+    }
+
+    fn load(&self, key: &str) -> Result<Option<Value>, SessionError> {
+        Ok(self.get(key))
+    }
+
+    fn store(&self, key: &str, value: Value) -> Result<(), SessionError> {
+        self.set(key, value);
+        Ok(())
+    }
+
+    fn discard(&self, key: &str) -> Result<(), SessionError> {
+        self.remove(key);
+        Ok(())
+    }
+}
+
 pub trait SessionExt {
-    /// Get the state for this session, initializing it
-    /// using [`TicketMachine::default`] if it doesn't
-    /// exist
-    fn get_or_init_state<F>(&self, f: F) -> TicketMachine
+    /// Apply `f` to the state for this session, initializing it
+    /// using [`TicketMachine::default`] if it doesn't exist or
+    /// can't be migrated to the current version. Returns the
+    /// updated state. Fails if the state doesn't satisfy `if_match`.
+    fn get_or_init_state<F>(&self, if_match: &IfMatch, f: F) -> Result<TicketMachine, SessionError>
     where
         F: FnOnce(&mut TicketMachine);
 
     /// Update the session state if it exists, returning
-    /// the updated state
-    fn update_state<F>(&self, f: F) -> Option<TicketMachine>
+    /// the updated state. Fails if the state doesn't
+    /// satisfy `if_match`, which is checked and written
+    /// without other updates getting in between.
+    fn update_state<F>(
+        &self,
+        if_match: &IfMatch,
+        f: F,
+    ) -> Result<Option<TicketMachine>, SessionError>
     where
         F: FnOnce(&mut TicketMachine);
 
-    /// Get the current state. Returns [`None`] if
-    /// it doesn't exist for this session.
-    fn try_get_state(&self) -> Option<TicketMachine>;
+    /// Get the current state, migrating it to the current
+    /// version if needed. Returns [`None`] if it doesn't exist
+    /// for this session. If the state cannot be migrated, it
+    /// is removed from the session and an error is returned.
+    fn try_get_state(&self) -> Result<Option<TicketMachine>, SessionError>;
+
+    /// Remove the state from this session, if any
+    fn clear_state(&self) -> Result<(), SessionError>;
+
+    /// Remove the state from this session and hand it over, provided
+    /// it satisfies `if_match`. Checked and removed without other
+    /// updates getting in between, so the state can be taken only once.
+    fn take_state(&self, if_match: &IfMatch) -> Result<Option<TicketMachine>, SessionError>;
+
+    /// Put back a state that was [taken](Self::take_state), unchanged,
+    /// unless a new state was started in the meantime
+    fn put_back_state(&self, state: TicketMachine) -> Result<(), SessionError>;
 }
 
-impl SessionExt for Session {
-    fn get_or_init_state<F>(&self, f: F) -> TicketMachine
+impl<S: SessionStorage> SessionExt for S {
+    fn get_or_init_state<F>(&self, if_match: &IfMatch, f: F) -> Result<TicketMachine, SessionError>
     where
         F: FnOnce(&mut TicketMachine),
     {
-        self.try_get_state().unwrap_or_else(|| {
-            self.set(SESSION_STATE_KEY, TicketMachine::default());
-            self.try_get_state().unwrap()
-        });
+        with_state_lock(self.id(), || {
+            let state = match self.try_get_state() {
+                Ok(state) => state,
+                Err(SessionError::StateReset(_)) => None,
+                Err(e) => return Err(e),
+            };
+            if_match.check(state.as_ref().map(|s| s.revision))?;
 
-        self.update_state(f).unwrap()
+            let mut state = state.unwrap_or_default();
+            f(&mut state);
+            write_state(self, state)
+        })
     }
 
-    fn update_state<F>(&self, f: F) -> Option<TicketMachine>
+    fn update_state<F>(
+        &self,
+        if_match: &IfMatch,
+        f: F,
+    ) -> Result<Option<TicketMachine>, SessionError>
     where
         F: FnOnce(&mut TicketMachine),
     {
-        self.try_get_state().map(|mut s| {
-            f(&mut s);
-            self.set(SESSION_STATE_KEY, s);
-            self.try_get_state().unwrap()
+        with_state_lock(self.id(), || {
+            let Some(mut state) = self.try_get_state()? else {
+                return Ok(None);
+            };
+            if_match.check(Some(state.revision))?;
+
+            f(&mut state);
+            write_state(self, state).map(Some)
         })
     }
 
-    fn try_get_state(&self) -> Option<TicketMachine> {
-        self.get(SESSION_STATE_KEY)
+    fn try_get_state(&self) -> Result<Option<TicketMachine>, SessionError> {
+        let Some(payload) = self.load(SESSION_STATE_KEY)? else {
+            return Ok(None);
+        };
+
+        match migrate(payload) {
+            Ok(state) => Ok(Some(state.into())),
+            Err(e) => {
+                self.discard(SESSION_STATE_KEY)?;
+                Err(e.into())
+            }
+        }
+    }
+
+    fn clear_state(&self) -> Result<(), SessionError> {
+        self.discard(SESSION_STATE_KEY)
+    }
+
+    fn take_state(&self, if_match: &IfMatch) -> Result<Option<TicketMachine>, SessionError> {
+        with_state_lock(self.id(), || {
+            let Some(state) = self.try_get_state()? else {
+                return Ok(None);
+            };
+            if_match.check(Some(state.revision))?;
+
+            self.discard(SESSION_STATE_KEY)?;
+            Ok(Some(state))
+        })
+    }
+
+    fn put_back_state(&self, state: TicketMachine) -> Result<(), SessionError> {
+        with_state_lock(self.id(), || {
+            if self.load(SESSION_STATE_KEY)?.is_some() {
+                return Ok(());
+            }
+            let payload = serde_json::to_value(VersionedState::from(state))
+                .map_err(SessionError::Serialize)?;
+            self.store(SESSION_STATE_KEY, payload)
+        })
+    }
+}
+
+/// Run `f` while holding the lock of session `id`. The lock is
+/// forgotten again once no other request is waiting for it. A lock
+/// that was poisoned is forgotten as well, so that the session gets
+/// a fresh one on its next update.
+fn with_state_lock<T>(
+    id: String,
+    f: impl FnOnce() -> Result<T, SessionError>,
+) -> Result<T, SessionError> {
+    let lock = STATE_LOCKS
+        .lock()
+        .map_err(|_| SessionError::LockPoisoned)?
+        .entry(id.clone())
+        .or_default()
+        .clone();
+
+    let result = match lock.lock() {
+        Ok(_update) => f(),
+        Err(_) => Err(SessionError::LockPoisoned),
+    };
+
+    let mut locks = STATE_LOCKS.lock().map_err(|_| SessionError::LockPoisoned)?;
+    // One reference is held by the map, the other one is ours
+    let unused = lock.is_poisoned() || Arc::strong_count(&lock) == 2;
+    if unused && locks.get(&id).is_some_and(|l| Arc::ptr_eq(l, &lock)) {
+        locks.remove(&id);
+    }
+    result
+}
+
+/// Write `state` to the session as a new revision, handing it
+/// back if that succeeded
+fn write_state<S: SessionStorage>(
+    storage: &S,
+    mut state: TicketMachine,
+) -> Result<TicketMachine, SessionError> {
+    state.revision += 1;
+    let stored = VersionedState::from(state);
+    let payload = serde_json::to_value(&stored).map_err(SessionError::Serialize)?;
+    storage.store(SESSION_STATE_KEY, payload)?;
+    Ok(stored.state.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::{json, Value};
+
+    use super::{SessionError, SessionExt, SessionStorage, SESSION_STATE_KEY};
+    use crate::{etag::IfMatch, types::ticket_machine::TicketMachine};
+
+    /// Keeps values in memory, optionally failing on either reads or writes
+    #[derive(Default)]
+    struct FaultyStorage {
+        values: RefCell<HashMap<String, Value>>,
+        fail_load: bool,
+        fail_store: bool,
+        loads: RefCell<usize>,
+        stores: RefCell<usize>,
+    }
+
+    impl SessionStorage for FaultyStorage {
+        fn id(&self) -> String {
+            format!("{self:p}")
+        }
+
+        fn load(&self, key: &str) -> Result<Option<Value>, SessionError> {
+            *self.loads.borrow_mut() += 1;
+            if self.fail_load {
+                return Err(SessionError::Store("load failed".into()));
+            }
+            Ok(self.values.borrow().get(key).cloned())
+        }
+
+        fn store(&self, key: &str, value: Value) -> Result<(), SessionError> {
+            *self.stores.borrow_mut() += 1;
+            if self.fail_store {
+                return Err(SessionError::Store("store failed".into()));
+            }
+            self.values.borrow_mut().insert(key.to_owned(), value);
+            Ok(())
+        }
+
+        fn discard(&self, key: &str) -> Result<(), SessionError> {
+            self.values.borrow_mut().remove(key);
+            Ok(())
+        }
+    }
+
+    /// Keeps values in memory, shared between threads
+    #[derive(Default)]
+    struct SharedStorage {
+        values: Mutex<HashMap<String, Value>>,
+    }
+
+    impl SessionStorage for SharedStorage {
+        fn id(&self) -> String {
+            format!("{self:p}")
+        }
+
+        fn load(&self, key: &str) -> Result<Option<Value>, SessionError> {
+            Ok(self.values.lock().unwrap().get(key).cloned())
+        }
+
+        fn store(&self, key: &str, value: Value) -> Result<(), SessionError> {
+            self.values.lock().unwrap().insert(key.to_owned(), value);
+            Ok(())
+        }
+
+        fn discard(&self, key: &str) -> Result<(), SessionError> {
+            self.values.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    fn berlin() -> Option<crate::types::location::Location> {
+        Some("Berlin Hbf".to_owned().try_into().unwrap())
+    }
+
+    #[test]
+    fn test_single_read_modify_write() {
+        let storage = FaultyStorage::default();
+
+        let state = storage
+            .get_or_init_state(&IfMatch::Absent, |s| s.origin = berlin())
+            .unwrap();
+        assert_eq!(state.origin, berlin());
+        assert_eq!((*storage.loads.borrow(), *storage.stores.borrow()), (1, 1));
+
+        let state = storage
+            .update_state(&IfMatch::Absent, |s| s.destination = berlin())
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.destination, berlin());
+        assert_eq!((*storage.loads.borrow(), *storage.stores.borrow()), (2, 2));
+    }
+
+    #[test]
+    fn test_update_without_state() {
+        let storage = FaultyStorage::default();
+
+        assert!(storage
+            .update_state(&IfMatch::Absent, |_| ())
+            .unwrap()
+            .is_none());
+        assert_eq!(*storage.stores.borrow(), 0);
+    }
+
+    #[test]
+    fn test_store_failure() {
+        let storage = FaultyStorage {
+            fail_store: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            storage.get_or_init_state(&IfMatch::Absent, |s| s.origin = berlin()),
+            Err(SessionError::Store(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_failure() {
+        let storage = FaultyStorage {
+            fail_load: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            storage.get_or_init_state(&IfMatch::Absent, |s| s.origin = berlin()),
+            Err(SessionError::Store(_))
+        ));
+        assert!(matches!(
+            storage.update_state(&IfMatch::Absent, |s| s.origin = berlin()),
+            Err(SessionError::Store(_))
+        ));
+        assert_eq!(*storage.stores.borrow(), 0);
+    }
+
+    #[test]
+    fn test_unmigratable_state_is_reset() {
+        let storage = FaultyStorage::default();
+        storage.values.borrow_mut().insert(
+            SESSION_STATE_KEY.to_owned(),
+            json!({ "version": u32::MAX, "state": {} }),
+        );
+
+        assert!(matches!(
+            storage.try_get_state(),
+            Err(SessionError::StateReset(_))
+        ));
+        assert!(storage.try_get_state().unwrap().is_none());
+
+        storage.values.borrow_mut().insert(
+            SESSION_STATE_KEY.to_owned(),
+            json!({ "version": u32::MAX, "state": {} }),
+        );
+        let state = storage
+            .get_or_init_state(&IfMatch::Absent, |s| s.origin = berlin())
+            .unwrap();
+        assert_eq!(
+            state,
+            TicketMachine {
+                origin: berlin(),
+                revision: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_revision_precondition() {
+        let storage = FaultyStorage::default();
+
+        let state = storage.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+        assert_eq!(state.revision, 1);
+
+        let state = storage
+            .update_state(&IfMatch::Revisions(vec![1]), |s| s.origin = berlin())
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.revision, 2);
+
+        // Another request based on the first revision lost the race
+        assert!(matches!(
+            storage.update_state(&IfMatch::Revisions(vec![1]), |s| s.destination = berlin()),
+            Err(SessionError::PreconditionFailed)
+        ));
+        assert!(matches!(
+            storage.get_or_init_state(&IfMatch::Revisions(vec![1]), |s| s.origin = None),
+            Err(SessionError::PreconditionFailed)
+        ));
+        assert_eq!(storage.try_get_state().unwrap().unwrap().origin, berlin());
+        assert_eq!(*storage.stores.borrow(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_updates_of_one_revision() {
+        let storage = Arc::new(SharedStorage::default());
+        storage.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+
+        let handles: Vec<_> = (0..20)
+            .map(|_| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    storage
+                        .update_state(&IfMatch::Revisions(vec![1]), |s| s.origin = berlin())
+                        .is_ok()
+                })
+            })
+            .collect();
+        let updated = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&ok| ok)
+            .count();
+
+        assert_eq!(updated, 1);
+        assert_eq!(storage.try_get_state().unwrap().unwrap().revision, 2);
+    }
+
+    #[test]
+    fn test_sessions_update_independently() {
+        let first = FaultyStorage::default();
+        let second = FaultyStorage::default();
+        first.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+        second.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+
+        // Would never finish if all sessions shared one lock
+        first
+            .update_state(&IfMatch::Absent, |_| {
+                second
+                    .update_state(&IfMatch::Absent, |s| s.origin = berlin())
+                    .unwrap();
+            })
+            .unwrap();
+
+        assert_eq!(second.try_get_state().unwrap().unwrap().origin, berlin());
+    }
+
+    #[test]
+    fn test_poisoned_lock() {
+        let storage = Arc::new(SharedStorage::default());
+        storage.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+
+        let panicking = storage.clone();
+        std::thread::spawn(move || {
+            let _ = panicking.update_state(&IfMatch::Absent, |_| panic!("update failed"));
+        })
+        .join()
+        .unwrap_err();
+
+        assert!(matches!(
+            storage.update_state(&IfMatch::Absent, |_| ()),
+            Err(SessionError::LockPoisoned)
+        ));
+        // The session gets a fresh lock afterwards
+        assert!(storage.update_state(&IfMatch::Absent, |_| ()).is_ok());
+    }
+
+    #[test]
+    fn test_take_state() {
+        let storage = FaultyStorage::default();
+        assert!(storage.take_state(&IfMatch::Absent).unwrap().is_none());
+
+        let state = storage
+            .get_or_init_state(&IfMatch::Absent, |s| s.origin = berlin())
+            .unwrap();
+        assert!(matches!(
+            storage.take_state(&IfMatch::Revisions(vec![state.revision + 1])),
+            Err(SessionError::PreconditionFailed)
+        ));
+
+        let taken = storage
+            .take_state(&IfMatch::Revisions(vec![state.revision]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(taken, state);
+        assert!(storage.try_get_state().unwrap().is_none());
+
+        // Put back as it was, so the revision the client has still matches
+        storage.put_back_state(taken).unwrap();
+        assert_eq!(storage.try_get_state().unwrap(), Some(state));
+    }
+
+    #[test]
+    fn test_put_back_keeps_newer_state() {
+        let storage = FaultyStorage::default();
+        storage.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+        let taken = storage.take_state(&IfMatch::Absent).unwrap().unwrap();
+
+        let started = storage
+            .get_or_init_state(&IfMatch::Absent, |s| s.origin = berlin())
+            .unwrap();
+        storage.put_back_state(taken).unwrap();
+
+        assert_eq!(storage.try_get_state().unwrap(), Some(started));
+    }
+
+    #[test]
+    fn test_concurrent_takes() {
+        let storage = Arc::new(SharedStorage::default());
+        storage.get_or_init_state(&IfMatch::Absent, |_| ()).unwrap();
+
+        let handles: Vec<_> = (0..20)
+            .map(|_| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    matches!(storage.take_state(&IfMatch::Absent), Ok(Some(_)))
+                })
+            })
+            .collect();
+        let taken = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&ok| ok)
+            .count();
+
+        assert_eq!(taken, 1);
+        assert!(storage.try_get_state().unwrap().is_none());
     }
 }
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use crate::{
+    access::StaffTokens,
+    idempotency::IdempotencyStore,
+    inventory::Inventory,
+    payment::{FakePaymentGateway, PaymentGateway},
+    repository::{BookingRepository, InMemoryBookingRepository, RepositoryError},
+    tickets::TicketIssuer,
+    timetable::Timetable,
+    types::{booking::BookingStatus, cancellation::CancellationPolicy, fare::FareEngine},
+    vouchers::Vouchers,
+};
+
+/// State shared by all request handlers
+#[derive(Clone)]
+pub struct AppState {
+    pub bookings: Arc<dyn BookingRepository>,
+    pub timetable: Arc<Timetable>,
+    pub inventory: Arc<Inventory>,
+    pub idempotency: Arc<IdempotencyStore>,
+    pub payments: Arc<dyn PaymentGateway>,
+    pub cancellation_policy: Arc<CancellationPolicy>,
+    pub fares: Arc<FareEngine>,
+    pub vouchers: Arc<Vouchers>,
+    pub tickets: Arc<TicketIssuer>,
+    pub staff: Arc<StaffTokens>,
+}
+
+impl AppState {
+    pub fn new(bookings: impl BookingRepository + 'static) -> Self {
+        Self {
+            bookings: Arc::new(bookings),
+            timetable: Arc::default(),
+            inventory: Arc::default(),
+            idempotency: Arc::default(),
+            payments: Arc::new(FakePaymentGateway::default()),
+            cancellation_policy: Arc::default(),
+            fares: Arc::default(),
+            vouchers: Arc::default(),
+            tickets: Arc::default(),
+            staff: Arc::default(),
+        }
+    }
+
+    /// Count what the stored bookings have taken in the inventory, and the
+    /// vouchers they used, which are only kept track of in memory
+    pub fn restore(&self) -> Result<(), RepositoryError> {
+        for booking in self.bookings.all()? {
+            if booking.status != BookingStatus::Confirmed {
+                continue;
+            }
+            self.inventory.restore(&booking);
+            if let Some(voucher) = &booking.voucher {
+                self.vouchers.restore(&voucher.code, &booking.contact.email);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new(InMemoryBookingRepository::default())
+    }
+}
@@ -0,0 +1,379 @@
+use serde_json::Value;
+
+use crate::{
+    inventory::HoldId,
+    types::{
+        address::BillingAddress,
+        ancillary::Ancillaries,
+        class::Class,
+        customer_details::{Email, Name, PhoneNumber},
+        departure_or_arrival::DepartureOrArrival,
+        journey::JourneyType,
+        location::Location,
+        passenger::Passengers,
+        payment_info::PaymentInfo,
+        seat::SeatId,
+        ticket_machine::TicketMachine,
+        trip::TripId,
+        voucher::VoucherCode,
+    },
+};
+
+/// The version of the [`StoredTicketMachine`] layout that is currently written
+/// to the session. Bump this whenever that layout changes in a way that older
+/// payloads no longer deserialize, and append a migration to [`MIGRATIONS`].
+pub const STATE_VERSION: u32 = 9;
+
+/// Upgrades a stored payload by a single version.
+type Migration = fn(Value) -> Result<Value, MigrationError>;
+
+/// The migration at index `n` upgrades a payload of version `n` to
+/// version `n + 1`.
+const MIGRATIONS: &[Migration] = &[
+    v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9,
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("stored state has version {0}, but only versions up to {STATE_VERSION} are supported")]
+    UnsupportedVersion(u32),
+
+    #[error("stored state is malformed: {0}")]
+    Malformed(#[from] serde_json::Error),
+}
+
+/// The envelope that is written to the session, tagging the state with the
+/// version of its layout.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct VersionedState<T> {
+    pub version: u32,
+    pub state: T,
+}
+
+impl From<TicketMachine> for VersionedState<StoredTicketMachine> {
+    fn from(state: TicketMachine) -> Self {
+        Self {
+            version: STATE_VERSION,
+            state: state.into(),
+        }
+    }
+}
+
+/// Upgrade a payload read from the session to the current
+/// [`StoredTicketMachine`] layout. Payloads without a version tag predate
+/// versioning altogether, and are treated as version 0.
+pub fn migrate(payload: Value) -> Result<StoredTicketMachine, MigrationError> {
+    let VersionedState { version, mut state } = match payload {
+        Value::Object(ref obj) if obj.contains_key("version") => serde_json::from_value(payload)?,
+        state => VersionedState { version: 0, state },
+    };
+
+    if version > STATE_VERSION {
+        return Err(MigrationError::UnsupportedVersion(version));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        state = migration(state)?;
+    }
+
+    Ok(serde_json::from_value(state)?)
+}
+
+/// Before version 1, the payment info was stored in its redacted form, so
+/// there's nothing left worth keeping.
+fn v0_to_v1(mut state: Value) -> Result<Value, MigrationError> {
+    if let Some(payment_info) = state.get_mut("payment_info") {
+        *payment_info = Value::Null;
+    }
+    Ok(state)
+}
+
+/// Version 2 introduced revisions
+fn v1_to_v2(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("revision".to_owned(), 0.into());
+    }
+    Ok(state)
+}
+
+/// Version 3 introduced seat holds
+fn v2_to_v3(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("hold".to_owned(), Value::Null);
+    }
+    Ok(state)
+}
+
+/// Version 4 introduced seat selection
+fn v3_to_v4(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("seat".to_owned(), Value::Null);
+    }
+    Ok(state)
+}
+
+/// Version 5 introduced multiple passengers
+fn v4_to_v5(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("passengers".to_owned(), Value::Null);
+    }
+    Ok(state)
+}
+
+/// Version 6 introduced return journeys
+fn v5_to_v6(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("journey_type".to_owned(), "single".into());
+        obj.insert("return_time".to_owned(), Value::Null);
+        obj.insert("return_trip".to_owned(), Value::Null);
+    }
+    Ok(state)
+}
+
+/// Version 7 introduced vouchers
+fn v6_to_v7(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("voucher".to_owned(), Value::Null);
+    }
+    Ok(state)
+}
+
+/// Version 8 introduced ancillaries
+fn v7_to_v8(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("ancillaries".to_owned(), Value::Array(vec![]));
+    }
+    Ok(state)
+}
+
+/// Version 9 introduced billing addresses
+fn v8_to_v9(mut state: Value) -> Result<Value, MigrationError> {
+    if let Value::Object(ref mut obj) = state {
+        obj.insert("billing_address".to_owned(), Value::Null);
+    }
+    Ok(state)
+}
+
+/// The representation of [`TicketMachine`] as it is persisted in the session.
+/// Unlike [`crate::views::TicketMachineView`], nothing is left out or redacted
+/// here, so that a [`TicketMachine`] survives a round trip through the
+/// session store unchanged.
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct StoredTicketMachine {
+    pub revision: u64,
+    pub origin: Option<Location>,
+    pub destination: Option<Location>,
+    pub time: Option<DepartureOrArrival>,
+    pub trip: Option<TripId>,
+    pub journey_type: JourneyType,
+    pub return_time: Option<DepartureOrArrival>,
+    pub return_trip: Option<TripId>,
+    pub class: Option<Class>,
+    pub hold: Option<HoldId>,
+    pub seat: Option<SeatId>,
+    pub passengers: Option<Passengers>,
+    pub ancillaries: Ancillaries,
+    pub name: Option<Name>,
+    pub email: Option<Email>,
+    pub phone_number: Option<PhoneNumber>,
+    pub billing_address: Option<BillingAddress>,
+    pub voucher: Option<VoucherCode>,
+    pub payment_info: Option<PaymentInfo>,
+}
+
+impl From<TicketMachine> for StoredTicketMachine {
+    fn from(
+        TicketMachine {
+            revision,
+            origin,
+            destination,
+            time,
+            trip,
+            journey_type,
+            return_time,
+            return_trip,
+            class,
+            hold,
+            seat,
+            passengers,
+            ancillaries,
+            name,
+            email,
+            phone_number,
+            billing_address,
+            voucher,
+            payment_info,
+        }: TicketMachine,
+    ) -> Self {
+        Self {
+            revision,
+            origin,
+            destination,
+            time,
+            trip,
+            journey_type,
+            return_time,
+            return_trip,
+            class,
+            hold,
+            seat,
+            passengers,
+            ancillaries,
+            name,
+            email,
+            phone_number,
+            billing_address,
+            voucher,
+            payment_info,
+        }
+    }
+}
+
+impl From<StoredTicketMachine> for TicketMachine {
+    fn from(
+        StoredTicketMachine {
+            revision,
+            origin,
+            destination,
+            time,
+            trip,
+            journey_type,
+            return_time,
+            return_trip,
+            class,
+            hold,
+            seat,
+            passengers,
+            ancillaries,
+            name,
+            email,
+            phone_number,
+            billing_address,
+            voucher,
+            payment_info,
+        }: StoredTicketMachine,
+    ) -> Self {
+        Self {
+            revision,
+            origin,
+            destination,
+            time,
+            trip,
+            journey_type,
+            return_time,
+            return_trip,
+            class,
+            hold,
+            seat,
+            passengers,
+            ancillaries,
+            name,
+            email,
+            phone_number,
+            billing_address,
+            voucher,
+            payment_info,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{migrate, MigrationError, StoredTicketMachine, VersionedState, STATE_VERSION};
+    use crate::types::{location::Location, ticket_machine::TicketMachine};
+
+    #[test]
+    fn test_stored_ticket_machine_keeps_payment_info() {
+        let payment_info = "1234 5678 9012 3456".to_owned().into();
+        let stored = StoredTicketMachine::from(TicketMachine {
+            payment_info: Some(payment_info),
+            ..Default::default()
+        });
+
+        let json = serde_json::to_string(&stored).unwrap();
+        assert!(json.contains("1234 5678 9012 3456"));
+
+        let restored: StoredTicketMachine = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, stored);
+    }
+
+    /// Stations are stored by their name, as they were before they became
+    /// an enum, so states stored before then need no migration
+    #[test]
+    fn test_stations_are_stored_by_name() {
+        let stored = serde_json::to_value(StoredTicketMachine::from(TicketMachine {
+            origin: Some(Location::LondonWaterloo),
+            destination: Some(Location::AmsterdamCentraal),
+            ..Default::default()
+        }))
+        .unwrap();
+
+        assert_eq!(stored["origin"], "London Waterloo");
+        assert_eq!(stored["destination"], "Amsterdam Centraal");
+    }
+
+    #[test]
+    fn test_migrate_current_version() {
+        let payload = serde_json::to_value(VersionedState::from(TicketMachine {
+            origin: Some("Berlin Hbf".to_owned().try_into().unwrap()),
+            ..Default::default()
+        }))
+        .unwrap();
+        assert_eq!(payload["version"], STATE_VERSION);
+
+        let state = TicketMachine::from(migrate(payload).unwrap());
+        assert_eq!(
+            state,
+            TicketMachine {
+                origin: Some("Berlin Hbf".to_owned().try_into().unwrap()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_unversioned_state() {
+        let payload = json!({
+            "origin": "Paris Nord",
+            "destination": null,
+            "time": null,
+            "trip": null,
+            "class": null,
+            "name": null,
+            "email": null,
+            "phone_number": null,
+            "payment_info": "<SECRET>",
+        });
+
+        let state = TicketMachine::from(migrate(payload).unwrap());
+        assert_eq!(
+            state,
+            TicketMachine {
+                origin: Some("Paris Nord".to_owned().try_into().unwrap()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_version() {
+        let payload = json!({ "version": STATE_VERSION + 1, "state": {} });
+
+        assert!(matches!(
+            migrate(payload),
+            Err(MigrationError::UnsupportedVersion(v)) if v == STATE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_migrate_rejects_malformed_state() {
+        let payload = json!({ "version": STATE_VERSION, "state": { "origin": "Atlantis" } });
+
+        assert!(matches!(
+            migrate(payload),
+            Err(MigrationError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use takeoff_tickets::{SignedTicket, TicketError};
+
+use crate::types::ticket::TicketPayload;
+
+/// Signs the tickets that are issued. Anyone with the [`VerifyingKey`] can
+/// check that a ticket is genuine without asking us, using the
+/// `takeoff-tickets` crate.
+pub struct TicketIssuer {
+    key: SigningKey,
+}
+
+impl TicketIssuer {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key }
+    }
+
+    /// Use the key whose 32-byte secret is encoded as (standard) base64
+    pub fn from_base64(secret: &str) -> Result<Self, TicketError> {
+        let secret = STANDARD
+            .decode(secret.trim())
+            .map_err(|_| TicketError::InvalidKey)?;
+        let secret = secret.try_into().map_err(|_| TicketError::InvalidKey)?;
+        Ok(Self::new(SigningKey::from_bytes(&secret)))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    pub fn sign(&self, ticket: &TicketPayload) -> SignedTicket {
+        takeoff_tickets::sign(&self.key, ticket)
+    }
+}
+
+/// A new, random key. Tickets signed with it can't be verified after a
+/// restart, so configure a key when running in production.
+impl Default for TicketIssuer {
+    fn default() -> Self {
+        Self::new(SigningKey::from_bytes(&rand::random()))
+    }
+}
+
+impl std::fmt::Debug for TicketIssuer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TicketIssuer")
+            .field("verifying_key", &self.verifying_key())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use takeoff_tickets::verify;
+
+    use super::TicketIssuer;
+    use crate::types::{booking::tests::booking, ticket};
+
+    #[test]
+    fn test_sign() {
+        let issuer = TicketIssuer::default();
+        let ticket = ticket::for_booking(&booking()).remove(0);
+        let signed = issuer.sign(&ticket);
+        assert_eq!(verify(&issuer.verifying_key(), &signed).unwrap(), ticket);
+    }
+
+    #[test]
+    fn test_from_base64() {
+        let issuer =
+            TicketIssuer::from_base64("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=").unwrap();
+        let again =
+            TicketIssuer::from_base64("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=").unwrap();
+        assert_eq!(issuer.verifying_key(), again.verifying_key());
+        assert!(TicketIssuer::from_base64("c2hvcnQ=").is_err());
+    }
+}
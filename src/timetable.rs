@@ -0,0 +1,47 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::Utc;
+
+use crate::types::{
+    departure_or_arrival::DepartureOrArrival,
+    location::Location,
+    seat::SeatLayout,
+    trip::{Trip, TripId},
+};
+
+/// Keeps track of the trips that have been offered to customers, so that
+/// a [`TripId`] they send back can be resolved to the actual [`Trip`].
+#[derive(Debug, Default)]
+pub struct Timetable {
+    trips: Mutex<HashMap<TripId, Trip>>,
+}
+
+impl Timetable {
+    /// List the trips matching the query, as per [`Trip::list_matching`],
+    /// remembering them for later lookup. Trips that have departed can't
+    /// be booked anymore, so they're forgotten to make room.
+    pub fn list_matching(
+        &self,
+        origin: Location,
+        destination: Location,
+        time: DepartureOrArrival,
+    ) -> Vec<Trip> {
+        let trips = Trip::list_matching(origin, destination, time);
+        let mut known = self.trips.lock().unwrap();
+        let now = Utc::now();
+        known.retain(|_, trip| trip.departure > now);
+        known.extend(trips.iter().map(|t| (t.id.clone(), t.clone())));
+        trips
+    }
+
+    /// Look up a trip that was listed earlier
+    pub fn find(&self, id: &TripId) -> Option<Trip> {
+        self.trips.lock().unwrap().get(id).cloned()
+    }
+
+    /// The coaches and seats on a trip that was listed earlier. All our
+    /// trains are the same for now.
+    pub fn seat_layout(&self, id: &TripId) -> Option<SeatLayout> {
+        self.find(id).map(|_| SeatLayout::standard())
+    }
+}
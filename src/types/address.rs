@@ -0,0 +1,274 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// The countries we sell tickets in, and so accept billing addresses for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Country {
+    Nl,
+    Fr,
+    De,
+    Gb,
+}
+
+impl Country {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Country::Nl => "Netherlands",
+            Country::Fr => "France",
+            Country::De => "Germany",
+            Country::Gb => "United Kingdom",
+        }
+    }
+}
+
+/// A postal code in the format of its country, like `1012 AB` in the
+/// Netherlands or `SW1A 1AA` in the United Kingdom. Codes are normalized
+/// to upper case, with the space in the place the post expects it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(into = "String")]
+pub struct PostalCode(String);
+
+impl PostalCode {
+    /// Parse `code` as a postal code in `country`, ignoring case and spaces
+    pub fn parse(country: Country, code: &str) -> Result<Self, AddressError> {
+        static NL: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new("^[1-9][0-9]{3}[A-Z]{2}$").unwrap());
+        static FR_DE: LazyLock<Regex> = LazyLock::new(|| Regex::new("^[0-9]{5}$").unwrap());
+        static GB: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new("^[A-Z]{1,2}[0-9][A-Z0-9]?[0-9][A-Z]{2}$").unwrap());
+
+        let compact: String = code
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase();
+        let normalized = match country {
+            Country::Nl if NL.is_match(&compact) => {
+                format!("{} {}", &compact[..4], &compact[4..])
+            }
+            Country::Fr | Country::De if FR_DE.is_match(&compact) => compact,
+            // The inward code is always the last three characters
+            Country::Gb if GB.is_match(&compact) => {
+                let (outward, inward) = compact.split_at(compact.len() - 3);
+                format!("{outward} {inward}")
+            }
+            _ => return Err(AddressError::PostalCode(country, code.to_owned())),
+        };
+        Ok(Self(normalized))
+    }
+}
+
+impl From<PostalCode> for String {
+    fn from(PostalCode(code): PostalCode) -> Self {
+        code
+    }
+}
+
+impl std::fmt::Display for PostalCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A VAT identification number, starting with the two-letter prefix of the
+/// country that issued it, like `NL123456789B01`. Numbers are normalized to
+/// upper case, without spaces or dots.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct VatId(String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Error parsing VAT ID: {0}")]
+pub struct ParseVatIdError(String);
+
+impl TryFrom<String> for VatId {
+    type Error = ParseVatIdError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        static VAT_ID: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new("^[A-Z]{2}[0-9A-Z]{2,13}$").unwrap());
+
+        let id = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '.')
+            .collect::<String>()
+            .to_ascii_uppercase();
+        if !VAT_ID.is_match(&id) {
+            return Err(ParseVatIdError(s));
+        }
+        Ok(Self(id))
+    }
+}
+
+impl From<VatId> for String {
+    fn from(VatId(id): VatId) -> Self {
+        id
+    }
+}
+
+impl std::fmt::Display for VatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AddressError {
+    #[error("The {0} of the address can't be empty")]
+    Empty(&'static str),
+
+    #[error("The {0} of the address can be at most {max} characters", max = BillingAddress::MAX_LEN)]
+    TooLong(&'static str),
+
+    #[error("{1:?} is not a valid postal code in {country}", country = .0.name())]
+    PostalCode(Country, String),
+}
+
+/// The address invoices are made out to. It's optional, and mostly given by
+/// business customers.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "AddressFields")]
+pub struct BillingAddress {
+    /// The company the invoice is made out to, if not the contact
+    pub company: Option<String>,
+    /// Printed on the invoice, so that businesses can reclaim the VAT
+    pub vat_id: Option<VatId>,
+    pub street: String,
+    pub postal_code: PostalCode,
+    pub city: String,
+    pub country: Country,
+}
+
+impl BillingAddress {
+    const MAX_LEN: usize = 100;
+}
+
+#[derive(serde::Deserialize)]
+struct AddressFields {
+    #[serde(default)]
+    company: Option<String>,
+    #[serde(default)]
+    vat_id: Option<VatId>,
+    street: String,
+    postal_code: String,
+    city: String,
+    country: Country,
+}
+
+/// Trim `value`, checking that something is left that isn't too long
+fn check_line(field: &'static str, value: &str) -> Result<String, AddressError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AddressError::Empty(field));
+    }
+    if value.chars().count() > BillingAddress::MAX_LEN {
+        return Err(AddressError::TooLong(field));
+    }
+    Ok(value.to_owned())
+}
+
+impl TryFrom<AddressFields> for BillingAddress {
+    type Error = AddressError;
+
+    fn try_from(
+        AddressFields {
+            company,
+            vat_id,
+            street,
+            postal_code,
+            city,
+            country,
+        }: AddressFields,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            company: company
+                .filter(|c| !c.trim().is_empty())
+                .map(|c| check_line("company", &c))
+                .transpose()?,
+            vat_id,
+            street: check_line("street", &street)?,
+            postal_code: PostalCode::parse(country, &postal_code)?,
+            city: check_line("city", &city)?,
+            country,
+        })
+    }
+}
+
+/// The address as it's written on an envelope. In the United Kingdom, the
+/// postal code goes below the town, elsewhere it goes in front of it.
+impl std::fmt::Display for BillingAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(company) = &self.company {
+            writeln!(f, "{company}")?;
+        }
+        if let Some(vat_id) = &self.vat_id {
+            writeln!(f, "VAT ID {vat_id}")?;
+        }
+        writeln!(f, "{}", self.street)?;
+        match self.country {
+            Country::Gb => {
+                writeln!(f, "{}", self.city.to_uppercase())?;
+                writeln!(f, "{}", self.postal_code)?;
+            }
+            _ => writeln!(f, "{} {}", self.postal_code, self.city)?,
+        }
+        write!(f, "{}", self.country.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use test_case::test_case;
+
+    use super::{BillingAddress, Country, PostalCode, VatId};
+
+    #[test_case(Country::Nl, "1012ab" => Some("1012 AB".to_owned()); "dutch")]
+    #[test_case(Country::Nl, "0123 AB" => None; "dutch leading zero")]
+    #[test_case(Country::Fr, "75010" => Some("75010".to_owned()); "french")]
+    #[test_case(Country::De, "1011" => None; "german too short")]
+    #[test_case(Country::Gb, "sw1a1aa" => Some("SW1A 1AA".to_owned()); "british")]
+    #[test_case(Country::Gb, "M1 1AE" => Some("M1 1AE".to_owned()); "british short")]
+    #[test_case(Country::Gb, "1012 AB" => None; "dutch in britain")]
+    fn test_parse_postal_code(country: Country, code: &str) -> Option<String> {
+        PostalCode::parse(country, code).ok().map(String::from)
+    }
+
+    #[test_case("NL123456789B01" => true; "dutch")]
+    #[test_case("de 123.456.789" => true; "with separators")]
+    #[test_case("123456789" => false; "without prefix")]
+    fn test_parse_vat_id(id: &str) -> bool {
+        VatId::try_from(id.to_owned()).is_ok()
+    }
+
+    #[test]
+    fn test_deserialize_address() {
+        let address: BillingAddress = serde_json::from_value(json!({
+            "company": "Trains Ltd",
+            "vat_id": "gb 123 4567 89",
+            "street": " 1 Station Road ",
+            "postal_code": "se17nd",
+            "city": "London",
+            "country": "GB",
+        }))
+        .unwrap();
+        assert_eq!(
+            address.to_string(),
+            "Trains Ltd\nVAT ID GB123456789\n1 Station Road\nLONDON\nSE1 7ND\nUnited Kingdom"
+        );
+        assert_eq!(
+            serde_json::to_value(&address).unwrap()["postal_code"],
+            "SE1 7ND"
+        );
+
+        assert!(serde_json::from_value::<BillingAddress>(json!({
+            "street": "",
+            "postal_code": "1012 AB",
+            "city": "Amsterdam",
+            "country": "NL",
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use super::{location::Location, trip::Trip};
+
+/// The add-ons that can be booked along with a seat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AncillaryType {
+    Bicycle,
+    ExtraLuggage,
+    Pet,
+}
+
+impl AncillaryType {
+    pub const ALL: [AncillaryType; 3] = [
+        AncillaryType::Bicycle,
+        AncillaryType::ExtraLuggage,
+        AncillaryType::Pet,
+    ];
+
+    /// How many of these each passenger can bring
+    pub fn max_per_passenger(&self) -> u32 {
+        match self {
+            AncillaryType::Bicycle | AncillaryType::Pet => 1,
+            AncillaryType::ExtraLuggage => 2,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AncillaryError {
+    #[error("{0:?} can't be booked more than once, give a quantity instead")]
+    Duplicate(AncillaryType),
+
+    #[error("The quantity of {0:?} must be at least 1")]
+    NoQuantity(AncillaryType),
+
+    #[error("At most {1} of {0:?} can be booked per passenger")]
+    TooMany(AncillaryType, u32),
+
+    #[error("{0:?} can't be taken on the trip from {1} to {2}")]
+    NotCarried(AncillaryType, Location, Location),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Ancillary {
+    #[serde(rename = "type")]
+    pub ancillary_type: AncillaryType,
+    pub quantity: u32,
+}
+
+/// The add-ons on a booking, with each type listed once
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "Vec<Ancillary>")]
+pub struct Ancillaries(Vec<Ancillary>);
+
+impl TryFrom<Vec<Ancillary>> for Ancillaries {
+    type Error = AncillaryError;
+
+    fn try_from(ancillaries: Vec<Ancillary>) -> Result<Self, Self::Error> {
+        for (i, ancillary) in ancillaries.iter().enumerate() {
+            if ancillary.quantity == 0 {
+                return Err(AncillaryError::NoQuantity(ancillary.ancillary_type));
+            }
+            if ancillaries[..i]
+                .iter()
+                .any(|a| a.ancillary_type == ancillary.ancillary_type)
+            {
+                return Err(AncillaryError::Duplicate(ancillary.ancillary_type));
+            }
+        }
+        Ok(Self(ancillaries))
+    }
+}
+
+impl Ancillaries {
+    /// Check that `seats` passengers don't bring more than they can
+    pub fn check_passengers(&self, seats: u32) -> Result<(), AncillaryError> {
+        for ancillary in &self.0 {
+            let max = ancillary.ancillary_type.max_per_passenger() * seats;
+            if ancillary.quantity > max {
+                return Err(AncillaryError::TooMany(
+                    ancillary.ancillary_type,
+                    ancillary.ancillary_type.max_per_passenger(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all of the add-ons can be taken on `trip`
+    pub fn check_carried(&self, trip: &Trip) -> Result<(), AncillaryError> {
+        match self
+            .0
+            .iter()
+            .find(|a| !trip.carries.contains(&a.ancillary_type))
+        {
+            Some(ancillary) => Err(AncillaryError::NotCarried(
+                ancillary.ancillary_type,
+                trip.origin.clone(),
+                trip.destination.clone(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::ops::Deref for Ancillaries {
+    type Target = [Ancillary];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Ancillaries, AncillaryError, AncillaryType};
+    use crate::types::trip::Trip;
+
+    #[test]
+    fn test_deserialize_ancillaries() {
+        let ancillaries: Ancillaries = serde_json::from_value(json!([
+            { "type": "bicycle", "quantity": 1 },
+            { "type": "extra_luggage", "quantity": 2 },
+        ]))
+        .unwrap();
+        assert_eq!(ancillaries.len(), 2);
+
+        assert!(serde_json::from_value::<Ancillaries>(json!([
+            { "type": "pet", "quantity": 0 },
+        ]))
+        .is_err());
+        assert!(serde_json::from_value::<Ancillaries>(json!([
+            { "type": "pet", "quantity": 1 },
+            { "type": "pet", "quantity": 1 },
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_checks() {
+        let ancillaries: Ancillaries = serde_json::from_value(json!([
+            { "type": "bicycle", "quantity": 2 },
+        ]))
+        .unwrap();
+        assert!(matches!(
+            ancillaries.check_passengers(1),
+            Err(AncillaryError::TooMany(AncillaryType::Bicycle, 1))
+        ));
+        ancillaries.check_passengers(2).unwrap();
+
+        let departure = chrono::Utc::now();
+        let mut trip: Trip = serde_json::from_value(json!({
+            "id": uuid::Uuid::new_v4(),
+            "origin": "Amsterdam Centraal",
+            "destination": "Paris Nord",
+            "departure": departure,
+            "arrival": departure + chrono::Duration::hours(3),
+        }))
+        .unwrap();
+        assert!(matches!(
+            ancillaries.check_carried(&trip),
+            Err(AncillaryError::NotCarried(AncillaryType::Bicycle, ..))
+        ));
+        trip.carries.push(AncillaryType::Bicycle);
+        ancillaries.check_carried(&trip).unwrap();
+    }
+}
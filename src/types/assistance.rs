@@ -0,0 +1,107 @@
+use super::customer_details::Name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssistanceType {
+    /// Help getting on and off the train, with a ramp if need be
+    BoardingAssistance,
+    WheelchairSpace,
+    VisualImpairment,
+    HearingImpairment,
+}
+
+/// What a passenger brings along to get around
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MobilityAid {
+    ManualWheelchair,
+    PoweredWheelchair,
+    MobilityScooter,
+    WalkingAid,
+}
+
+impl MobilityAid {
+    /// Whether the passenger stays in it while travelling
+    fn is_wheeled(&self) -> bool {
+        !matches!(self, MobilityAid::WalkingAid)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AssistanceError {
+    #[error("Request at least one type of assistance")]
+    Empty,
+
+    #[error("A wheelchair space needs a wheelchair or mobility scooter as mobility aid")]
+    NoWheelchair,
+}
+
+/// The assistance a passenger asked for, which staff at the stations and on
+/// board are told about. Passengers who need a wheelchair space get one of
+/// the spaces in their class.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "AssistanceFields")]
+pub struct Assistance {
+    pub types: Vec<AssistanceType>,
+    pub mobility_aid: Option<MobilityAid>,
+    /// Another passenger on the same booking, who travels along to help
+    pub companion: Option<Name>,
+}
+
+#[derive(serde::Deserialize)]
+struct AssistanceFields {
+    types: Vec<AssistanceType>,
+    #[serde(default)]
+    mobility_aid: Option<MobilityAid>,
+    #[serde(default)]
+    companion: Option<Name>,
+}
+
+impl TryFrom<AssistanceFields> for Assistance {
+    type Error = AssistanceError;
+
+    fn try_from(
+        AssistanceFields {
+            types,
+            mobility_aid,
+            companion,
+        }: AssistanceFields,
+    ) -> Result<Self, Self::Error> {
+        if types.is_empty() {
+            return Err(AssistanceError::Empty);
+        }
+        if types.contains(&AssistanceType::WheelchairSpace)
+            && !mobility_aid.is_some_and(|aid| aid.is_wheeled())
+        {
+            return Err(AssistanceError::NoWheelchair);
+        }
+        Ok(Self {
+            types,
+            mobility_aid,
+            companion,
+        })
+    }
+}
+
+impl Assistance {
+    pub fn needs_wheelchair_space(&self) -> bool {
+        self.types.contains(&AssistanceType::WheelchairSpace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use test_case::test_case;
+
+    use super::Assistance;
+
+    #[test_case(json!({ "types": ["boarding_assistance"] }) => true; "boarding assistance")]
+    #[test_case(json!({ "types": [] }) => false; "nothing")]
+    #[test_case(json!({ "types": ["wheelchair_space"], "mobility_aid": "powered_wheelchair" }) => true; "wheelchair")]
+    #[test_case(json!({ "types": ["wheelchair_space"], "mobility_aid": "walking_aid" }) => false; "walking aid")]
+    #[test_case(json!({ "types": ["wheelchair_space"] }) => false; "without mobility aid")]
+    fn test_parse_assistance(assistance: serde_json::Value) -> bool {
+        serde_json::from_value::<Assistance>(assistance).is_ok()
+    }
+}
@@ -0,0 +1,821 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use crate::payment::{PaymentError, PaymentGateway, PaymentId, RefundId};
+
+use super::{
+    address::BillingAddress,
+    ancillary::Ancillaries,
+    cancellation::CancellationPolicy,
+    class::Class,
+    customer_details::{Email, Name, PhoneNumber},
+    journey::JourneyType,
+    money::{Money, MoneyError},
+    passenger::{FareCategory, Passenger, PassengerType, Passengers},
+    seat::SeatId,
+    trip::Trip,
+    voucher::VoucherCode,
+};
+
+/// A PNR-style booking reference: six characters taken from an alphabet
+/// that leaves out characters that are easily confused, like `0` and `O`.
+/// References are case-insensitive, and always stored in upper case.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BookingReference(String);
+
+impl BookingReference {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    const LEN: usize = 6;
+
+    /// Generate a new, random reference
+    pub fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let reference = (0..Self::LEN)
+            .map(|_| Self::ALPHABET[rng.gen_range(0..Self::ALPHABET.len())] as char)
+            .collect();
+        Self(reference)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Error parsing booking reference: {0}")]
+pub struct ParseBookingReferenceError(String);
+
+impl TryFrom<String> for BookingReference {
+    type Error = ParseBookingReferenceError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let reference = s.to_ascii_uppercase();
+        if reference.len() != Self::LEN || !reference.bytes().all(|b| Self::ALPHABET.contains(&b)) {
+            return Err(ParseBookingReferenceError(s));
+        }
+        Ok(Self(reference))
+    }
+}
+
+impl From<BookingReference> for String {
+    fn from(BookingReference(reference): BookingReference) -> Self {
+        reference
+    }
+}
+
+impl std::fmt::Display for BookingReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The lead contact for a booking, who need not be one of the passengers
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Contact {
+    pub name: Name,
+    pub email: Email,
+    pub phone_number: PhoneNumber,
+}
+
+/// The status of a booking. A cancelled booking goes from `Confirmed` to
+/// `Cancelled`, and if anything is to be refunded, on through
+/// `RefundPending` to `Refunded`. If the refund fails, the booking is
+/// `RefundFailed` until the refund is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookingStatus {
+    Confirmed,
+    /// Cancelled, with the refund under way
+    RefundPending,
+    /// Cancelled, but the refund couldn't be paid
+    RefundFailed,
+    Cancelled,
+    Refunded,
+}
+
+impl BookingStatus {
+    fn can_become(self, next: BookingStatus) -> bool {
+        use BookingStatus::*;
+        matches!(
+            (self, next),
+            (Confirmed, Cancelled)
+                | (Cancelled, RefundPending)
+                | (RefundPending, Refunded | RefundFailed)
+                | (RefundFailed, RefundPending)
+        )
+    }
+}
+
+/// Records when a booking entered a [`BookingStatus`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct StatusChange {
+    pub status: BookingStatus,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Booking cannot go from {from:?} to {to:?}")]
+pub struct StatusTransitionError {
+    pub from: BookingStatus,
+    pub to: BookingStatus,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CancellationError {
+    #[error(transparent)]
+    Status(#[from] StatusTransitionError),
+
+    #[error("The trip has departed already")]
+    AlreadyDeparted,
+
+    #[error("The refund could not be paid: {0}")]
+    Refund(#[from] PaymentError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChangeError {
+    #[error("Only confirmed bookings can be changed, this one is {0:?}")]
+    NotConfirmed(BookingStatus),
+
+    #[error("The trip has departed already")]
+    AlreadyDeparted,
+
+    #[error("The new trip must have the same origin and destination")]
+    DifferentRoute,
+
+    #[error("The new trip and class are the same as the current ones")]
+    Unchanged,
+
+    #[error("The new trip arrives after the return trip departs")]
+    AfterReturn,
+
+    #[error("The new trip can't take all of the add-ons that were booked")]
+    AncillariesNotCarried,
+
+    #[error("The new price is in a different currency")]
+    CurrencyMismatch,
+}
+
+/// Money that was charged to the customer
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Charge {
+    pub id: PaymentId,
+    pub amount: Money,
+    pub at: DateTime<Utc>,
+}
+
+/// Money that was paid back to the customer, from an earlier [`Charge`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Refund {
+    pub id: RefundId,
+    pub charge: PaymentId,
+    pub amount: Money,
+    pub at: DateTime<Utc>,
+}
+
+/// A voucher that was used for a booking, and the amount it took off
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct AppliedVoucher {
+    pub code: VoucherCode,
+    pub discount: Money,
+}
+
+/// What the price of a booking is made up of, as it was charged, so that
+/// it can be invoiced without quoting it again
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct PriceBreakdown {
+    /// The fare for all passengers, both ways for a return
+    pub transport: Money,
+    /// The price of each add-on, in the order of [`Booking::ancillaries`]
+    pub ancillaries: Vec<Money>,
+    /// What a voucher took off the fare and the add-ons together
+    pub discount: Money,
+}
+
+impl PriceBreakdown {
+    /// The fare and the add-ons, before the discount
+    pub fn subtotal(&self) -> Result<Money, MoneyError> {
+        self.ancillaries
+            .iter()
+            .try_fold(self.transport, |total, &price| total.checked_add(price))
+    }
+
+    /// What's to be paid
+    pub fn total(&self) -> Result<Money, MoneyError> {
+        self.subtotal()?.checked_sub(self.discount)
+    }
+}
+
+/// A trip in a class, at the price that was paid for it by all passengers
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Leg {
+    pub trip: Trip,
+    pub class: Class,
+    pub price: Money,
+}
+
+/// Records a move to another trip or class. A positive fare difference
+/// was charged to the customer, a negative one refunded.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct BookingChange {
+    pub from: Leg,
+    pub to: Leg,
+    pub fare_difference: Money,
+    pub at: DateTime<Utc>,
+}
+
+/// A trip that has been booked and paid for. Unlike
+/// [`crate::types::ticket_machine::TicketMachine`], every field is
+/// guaranteed to be there.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Booking {
+    pub reference: BookingReference,
+    #[serde(default)]
+    pub journey_type: JourneyType,
+    /// The trip out
+    pub trip: Trip,
+    /// The trip back, for a return
+    #[serde(default)]
+    pub return_trip: Option<Trip>,
+    /// Called `passenger` in bookings made before there could be several
+    #[serde(alias = "passenger")]
+    pub contact: Contact,
+    #[serde(default)]
+    pub billing_address: Option<BillingAddress>,
+    /// Empty for bookings made before passengers were recorded, which
+    /// are for a single passenger
+    #[serde(default)]
+    pub passengers: Vec<Passenger>,
+    #[serde(default)]
+    pub ancillaries: Ancillaries,
+    pub class: Class,
+    /// The specific seat that was chosen for the first passenger, if any
+    pub seat: Option<SeatId>,
+    /// What was paid, after any voucher was taken off
+    pub price: Money,
+    /// What the price is made up of. Missing from bookings made before
+    /// it was stored.
+    #[serde(default)]
+    pub breakdown: Option<PriceBreakdown>,
+    #[serde(default)]
+    pub voucher: Option<AppliedVoucher>,
+    pub status: BookingStatus,
+    /// What's left to pay back after cancelling, until it's refunded
+    #[serde(default)]
+    pub pending_refund: Option<Money>,
+    #[serde(default)]
+    pub history: Vec<StatusChange>,
+    /// Stored as a single `payment` for the price in bookings made
+    /// before they could be changed, see [`crate::repository::sqlite`]
+    #[serde(default)]
+    pub charges: Vec<Charge>,
+    #[serde(default)]
+    pub refunds: Vec<Refund>,
+    #[serde(default)]
+    pub changes: Vec<BookingChange>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Booking {
+    /// Move the booking to `status`, recording when that happened
+    pub fn transition(
+        &mut self,
+        status: BookingStatus,
+        now: DateTime<Utc>,
+    ) -> Result<(), StatusTransitionError> {
+        if !self.status.can_become(status) {
+            return Err(StatusTransitionError {
+                from: self.status,
+                to: status,
+            });
+        }
+
+        self.status = status;
+        self.history.push(StatusChange { status, at: now });
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Cancel the booking, returning the amount that should be refunded
+    /// according to `policy`. Unless there's nothing to refund, the booking
+    /// moves on from `Cancelled` to `RefundPending` until the refund has
+    /// been [settled](Self::settle_refund). Cancelling again after the
+    /// refund failed tries again.
+    pub fn cancel(
+        &mut self,
+        policy: &CancellationPolicy,
+        now: DateTime<Utc>,
+    ) -> Result<Money, CancellationError> {
+        if let (BookingStatus::RefundFailed, Some(refund)) = (self.status, self.pending_refund) {
+            self.transition(BookingStatus::RefundPending, now)?;
+            return Ok(refund);
+        }
+
+        let notice = self.trip.departure - now;
+        if notice < chrono::Duration::zero() {
+            return Err(CancellationError::AlreadyDeparted);
+        }
+
+        let refund = policy.refund(&self.class, self.price, notice);
+        self.transition(BookingStatus::Cancelled, now)?;
+        if refund.amount > 0 {
+            self.transition(BookingStatus::RefundPending, now)?;
+            self.pending_refund = Some(refund);
+        }
+        Ok(refund)
+    }
+
+    /// Pay back the refund that's pending after cancelling, moving the
+    /// booking on to `Refunded`, or to `RefundFailed` if the refund
+    /// couldn't be paid in full. What's left is kept for the next try.
+    pub fn settle_refund(
+        &mut self,
+        payments: &dyn PaymentGateway,
+        now: DateTime<Utc>,
+    ) -> Result<(), CancellationError> {
+        let (BookingStatus::RefundPending, Some(pending)) = (self.status, self.pending_refund)
+        else {
+            return Ok(());
+        };
+
+        let refunds = self.refunds.len();
+        let result = self.refund(payments, pending, now);
+        let refunded: i64 = self.refunds[refunds..]
+            .iter()
+            .map(|r| r.amount.amount)
+            .sum();
+        let status = match result {
+            Ok(()) => {
+                self.pending_refund = None;
+                BookingStatus::Refunded
+            }
+            Err(_) => {
+                self.pending_refund = Some(Money::new(pending.amount - refunded, pending.currency));
+                BookingStatus::RefundFailed
+            }
+        };
+        self.transition(status, now)?;
+        Ok(result?)
+    }
+
+    /// Pay `amount` back to the customer through `payments`, taking it
+    /// from the most recent charges first. Refunds that succeeded are
+    /// recorded, even if a later one fails.
+    pub fn refund(
+        &mut self,
+        payments: &dyn PaymentGateway,
+        amount: Money,
+        now: DateTime<Utc>,
+    ) -> Result<(), PaymentError> {
+        let mut remaining = amount.amount;
+
+        for charge in self.charges.iter().rev() {
+            if remaining <= 0 {
+                break;
+            }
+            let refunded: i64 = self
+                .refunds
+                .iter()
+                .filter(|r| r.charge == charge.id)
+                .map(|r| r.amount.amount)
+                .sum();
+            let refund = remaining.min(charge.amount.amount - refunded);
+            if refund <= 0 {
+                continue;
+            }
+
+            let refund = Money::new(refund, amount.currency);
+            let id = payments.refund(&charge.id, refund)?;
+            self.refunds.push(Refund {
+                id,
+                charge: charge.id.clone(),
+                amount: refund,
+                at: now,
+            });
+            self.updated_at = now;
+            remaining -= refund.amount;
+        }
+
+        if remaining > 0 {
+            return Err(PaymentError::RefundExceedsCharge);
+        }
+        Ok(())
+    }
+
+    /// The number of seats the booking takes up, each way
+    pub fn seats(&self) -> u32 {
+        self.passengers.len().max(1) as u32
+    }
+
+    /// The fare categories of the passengers, for pricing
+    pub fn fare_categories(&self) -> Vec<FareCategory> {
+        if self.passengers.is_empty() {
+            return vec![PassengerType::Adult.into()];
+        }
+        self.passengers
+            .iter()
+            .map(Passenger::fare_category)
+            .collect()
+    }
+
+    /// The number of wheelchair spaces the booking takes up, each way
+    pub fn wheelchair_spaces(&self) -> u32 {
+        self.passengers
+            .iter()
+            .filter(|p| p.needs_wheelchair_space())
+            .count() as u32
+    }
+
+    /// Whether any of the passengers travels on a discount card,
+    /// which they have to show on board
+    pub fn discount_card_required(&self) -> bool {
+        self.passengers.iter().any(|p| p.discount_card.is_some())
+    }
+
+    /// The trip out and the class that are currently booked
+    pub fn leg(&self) -> Leg {
+        Leg {
+            trip: self.trip.clone(),
+            class: self.class.clone(),
+            price: self.price,
+        }
+    }
+
+    /// Check that the passengers can still travel if the trip out becomes
+    /// `trip`. Their ages have to match their passenger types on the day
+    /// of the new trip, and their discount cards and travel documents have
+    /// to be valid for the whole journey. Bookings made before passengers
+    /// were recorded have none to check.
+    pub fn check_passengers_for(&self, trip: &Trip) -> crate::Result<()> {
+        let Ok(passengers) = Passengers::try_from(self.passengers.clone()) else {
+            return Ok(());
+        };
+        passengers.check_ages(trip.departure.date_naive())?;
+        let last_departure = self
+            .return_trip
+            .as_ref()
+            .map_or(trip.departure, |t| t.departure.max(trip.departure));
+        passengers.check_discount_cards(last_departure.date_naive())?;
+        if trip.crosses_border_control() {
+            passengers.check_travel_documents(last_departure.date_naive())?;
+        }
+        Ok(())
+    }
+
+    /// Check whether the booking can be changed to `to`, returning the fare
+    /// difference
+    pub fn check_change(&self, to: &Leg, now: DateTime<Utc>) -> Result<Money, ChangeError> {
+        if self.status != BookingStatus::Confirmed {
+            return Err(ChangeError::NotConfirmed(self.status));
+        }
+        if self.trip.departure < now || to.trip.departure < now {
+            return Err(ChangeError::AlreadyDeparted);
+        }
+        if to.trip.origin != self.trip.origin || to.trip.destination != self.trip.destination {
+            return Err(ChangeError::DifferentRoute);
+        }
+        if to.trip.id == self.trip.id && to.class == self.class {
+            return Err(ChangeError::Unchanged);
+        }
+        if let Some(return_trip) = &self.return_trip {
+            if to.trip.arrival > return_trip.departure {
+                return Err(ChangeError::AfterReturn);
+            }
+        }
+        if self.ancillaries.check_carried(&to.trip).is_err() {
+            return Err(ChangeError::AncillariesNotCarried);
+        }
+
+        to.price
+            .checked_sub(self.price)
+            .map_err(|_| ChangeError::CurrencyMismatch)
+    }
+
+    /// Move the booking to another trip and/or class, recording the change.
+    /// `breakdown` is what the price of the new leg is made up of. If the
+    /// new leg is more expensive, `charge` should pay for the fare
+    /// difference. If it's cheaper, it's up to the caller to [`Self::refund`]
+    /// the difference.
+    pub fn change(
+        &mut self,
+        to: Leg,
+        breakdown: PriceBreakdown,
+        charge: Option<Charge>,
+        now: DateTime<Utc>,
+    ) -> Result<Money, ChangeError> {
+        let fare_difference = self.check_change(&to, now)?;
+
+        self.changes.push(BookingChange {
+            from: self.leg(),
+            to: to.clone(),
+            fare_difference,
+            at: now,
+        });
+        self.charges.extend(charge);
+        self.trip = to.trip;
+        self.class = to.class;
+        self.seat = None;
+        self.price = to.price;
+        self.breakdown = Some(breakdown);
+        self.updated_at = now;
+
+        Ok(fare_difference)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{Duration, Utc};
+    use test_case::test_case;
+
+    use super::{
+        Booking, BookingReference, BookingStatus, CancellationError, ChangeError, Charge, Contact,
+        Leg, ParseBookingReferenceError, PriceBreakdown, StatusChange,
+    };
+    use crate::{
+        payment::{FakePaymentGateway, PaymentGateway},
+        types::{
+            ancillary::Ancillaries,
+            cancellation::CancellationPolicy,
+            class::Class,
+            departure_or_arrival::DepartureOrArrival,
+            journey::JourneyType,
+            money::{Currency, Money},
+            passenger::PassengersError,
+            trip::Trip,
+        },
+    };
+
+    /// A confirmed second class booking for a trip departing in about an hour
+    pub(crate) fn booking() -> Booking {
+        let departure = (Utc::now() + Duration::hours(1)).try_into().unwrap();
+        let trip = Trip::list_matching(
+            "Amsterdam Centraal".to_owned().try_into().unwrap(),
+            "Berlin Hbf".to_owned().try_into().unwrap(),
+            DepartureOrArrival::Departure(departure),
+        )
+        .remove(0);
+
+        Booking {
+            reference: BookingReference::generate(),
+            journey_type: JourneyType::Single,
+            trip,
+            return_trip: None,
+            contact: Contact {
+                name: serde_json::from_str(r#""Henk""#).unwrap(),
+                email: "fake@example.com".to_owned().try_into().unwrap(),
+                phone_number: "123-456".to_owned().try_into().unwrap(),
+            },
+            billing_address: None,
+            passengers: vec![],
+            ancillaries: Ancillaries::default(),
+            class: Class::Second,
+            seat: None,
+            price: Money::new(4900, Currency::Eur),
+            breakdown: None,
+            voucher: None,
+            status: BookingStatus::Confirmed,
+            pending_refund: None,
+            history: vec![StatusChange {
+                status: BookingStatus::Confirmed,
+                at: Utc::now(),
+            }],
+            charges: vec![],
+            refunds: vec![],
+            changes: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test_case("ABC234" => Ok("ABC234".to_owned()))]
+    #[test_case("abc234" => Ok("ABC234".to_owned()); "lower case")]
+    #[test_case("ABC23" => Err(()); "too short")]
+    #[test_case("ABC2345" => Err(()); "too long")]
+    #[test_case("ABC230" => Err(()); "ambiguous character")]
+    #[test_case("ÄBC234" => Err(()); "non-ASCII")]
+    fn test_parse_booking_reference(reference: &str) -> Result<String, ()> {
+        BookingReference::try_from(reference.to_owned())
+            .map(String::from)
+            .map_err(|_: ParseBookingReferenceError| ())
+    }
+
+    #[test]
+    fn test_generated_reference_is_valid() {
+        for _ in 0..100 {
+            let reference = BookingReference::generate();
+            assert_eq!(
+                BookingReference::try_from(reference.to_string()).unwrap(),
+                reference
+            );
+        }
+    }
+
+    /// Charge the booking's price through `payments`
+    fn charge(booking: &mut Booking, payments: &FakePaymentGateway) {
+        let id = payments
+            .charge(&"1234".to_owned().into(), booking.price)
+            .unwrap();
+        booking.charges.push(Charge {
+            id,
+            amount: booking.price,
+            at: Utc::now(),
+        });
+    }
+
+    #[test]
+    fn test_cancel_and_refund() {
+        let payments = FakePaymentGateway::default();
+        let mut booking = booking();
+        charge(&mut booking, &payments);
+        booking.trip.departure = Utc::now() + Duration::days(3);
+
+        let amount = booking
+            .cancel(&CancellationPolicy::default(), Utc::now())
+            .unwrap();
+        assert_eq!(amount, booking.price);
+        assert_eq!(booking.status, BookingStatus::RefundPending);
+
+        booking.settle_refund(&payments, Utc::now()).unwrap();
+        assert_eq!(booking.refunds.len(), 1);
+        assert_eq!(booking.pending_refund, None);
+        assert_eq!(
+            booking.history.iter().map(|c| c.status).collect::<Vec<_>>(),
+            [
+                BookingStatus::Confirmed,
+                BookingStatus::Cancelled,
+                BookingStatus::RefundPending,
+                BookingStatus::Refunded
+            ]
+        );
+
+        assert!(matches!(
+            booking.cancel(&CancellationPolicy::default(), Utc::now()),
+            Err(CancellationError::Status(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_refund_is_tried_again() {
+        let payments = FakePaymentGateway::default();
+        let mut booking = booking();
+        booking.trip.departure = Utc::now() + Duration::days(3);
+
+        // There's no charge to refund from yet
+        booking
+            .cancel(&CancellationPolicy::default(), Utc::now())
+            .unwrap();
+        assert!(booking.settle_refund(&payments, Utc::now()).is_err());
+        assert_eq!(booking.status, BookingStatus::RefundFailed);
+        assert_eq!(booking.pending_refund, Some(booking.price));
+
+        charge(&mut booking, &payments);
+        let amount = booking
+            .cancel(&CancellationPolicy::default(), Utc::now())
+            .unwrap();
+        assert_eq!(amount, booking.price);
+        booking.settle_refund(&payments, Utc::now()).unwrap();
+        assert_eq!(booking.status, BookingStatus::Refunded);
+    }
+
+    #[test]
+    fn test_cancel_non_refundable() {
+        let mut booking = booking();
+
+        let amount = booking
+            .cancel(&CancellationPolicy::default(), Utc::now())
+            .unwrap();
+        assert_eq!(amount.amount, 0);
+        assert_eq!(booking.status, BookingStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_cancel_after_departure() {
+        let mut booking = booking();
+        booking.trip.departure = Utc::now() - Duration::minutes(1);
+
+        assert!(matches!(
+            booking.cancel(&CancellationPolicy::default(), Utc::now()),
+            Err(CancellationError::AlreadyDeparted)
+        ));
+        assert_eq!(booking.status, BookingStatus::Confirmed);
+    }
+
+    #[test]
+    fn test_upgrade_then_downgrade() {
+        let payments = FakePaymentGateway::default();
+        let mut booking = booking();
+        charge(&mut booking, &payments);
+        let original = booking.leg();
+
+        let first_class = Leg {
+            class: Class::First,
+            price: Money::new(8900, Currency::Eur),
+            ..booking.leg()
+        };
+        let difference = booking.check_change(&first_class, Utc::now()).unwrap();
+        assert_eq!(difference, Money::new(4000, Currency::Eur));
+
+        let id = payments
+            .charge(&"1234".to_owned().into(), difference)
+            .unwrap();
+        let charge = Charge {
+            id,
+            amount: difference,
+            at: Utc::now(),
+        };
+        let breakdown = |leg: &Leg| PriceBreakdown {
+            transport: leg.price,
+            ancillaries: vec![],
+            discount: Money::new(0, Currency::Eur),
+        };
+        booking
+            .change(
+                first_class.clone(),
+                breakdown(&first_class),
+                Some(charge),
+                Utc::now(),
+            )
+            .unwrap();
+        assert_eq!(booking.class, Class::First);
+        assert_eq!(booking.price, first_class.price);
+        assert_eq!(booking.breakdown, Some(breakdown(&first_class)));
+
+        // Going back to second class refunds the difference,
+        // out of the charge for the upgrade
+        let difference = booking
+            .change(original.clone(), breakdown(&original), None, Utc::now())
+            .unwrap();
+        assert_eq!(difference, Money::new(-4000, Currency::Eur));
+        booking
+            .refund(&payments, Money::new(4000, Currency::Eur), Utc::now())
+            .unwrap();
+        assert_eq!(booking.refunds.len(), 1);
+        assert_eq!(booking.refunds[0].charge, booking.charges[1].id);
+
+        // Which leaves only the original charge to refund on cancellation
+        booking.trip.departure = Utc::now() + Duration::days(3);
+        booking
+            .cancel(&CancellationPolicy::default(), Utc::now())
+            .unwrap();
+        booking.settle_refund(&payments, Utc::now()).unwrap();
+        let refunded: i64 = booking.refunds.iter().map(|r| r.amount.amount).sum();
+        assert_eq!(refunded, 8900);
+        assert_eq!(booking.changes.len(), 2);
+    }
+
+    #[test]
+    fn test_change_rejected() {
+        let mut booking = booking();
+
+        assert!(matches!(
+            booking.check_change(&booking.leg(), Utc::now()),
+            Err(ChangeError::Unchanged)
+        ));
+
+        let mut other_route = booking.leg();
+        other_route.trip.destination = "Paris Nord".to_owned().try_into().unwrap();
+        assert!(matches!(
+            booking.check_change(&other_route, Utc::now()),
+            Err(ChangeError::DifferentRoute)
+        ));
+
+        booking
+            .transition(BookingStatus::Cancelled, Utc::now())
+            .unwrap();
+        let upgrade = Leg {
+            class: Class::First,
+            ..booking.leg()
+        };
+        assert!(matches!(
+            booking.check_change(&upgrade, Utc::now()),
+            Err(ChangeError::NotConfirmed(BookingStatus::Cancelled))
+        ));
+    }
+
+    #[test]
+    fn test_check_passengers_for() {
+        let mut booking = booking();
+        // Turns 12 in ten days, after which she's too old to be a child
+        let birthday = (Utc::now() + Duration::days(10)).date_naive();
+        let date_of_birth = birthday.checked_sub_months(chrono::Months::new(12 * 12));
+        booking.passengers = serde_json::from_value(serde_json::json!([
+            { "name": "Henk", "type": "adult" },
+            { "name": "Ingrid", "type": "child", "age": 11, "date_of_birth": date_of_birth },
+        ]))
+        .unwrap();
+        booking.check_passengers_for(&booking.trip).unwrap();
+
+        let mut later = booking.trip.clone();
+        later.departure += Duration::days(30);
+        later.arrival += Duration::days(30);
+        assert!(matches!(
+            booking.check_passengers_for(&later),
+            Err(crate::error::Error::Passengers(
+                PassengersError::ChildTooOldOn(_, 12)
+            ))
+        ));
+    }
+}
@@ -0,0 +1,85 @@
+use chrono::Duration;
+
+use super::{class::Class, money::Money};
+
+/// Grants a refund of `refund_percentage` percent of the price, for bookings
+/// in `class` that are cancelled at least `notice_hours` before departure
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct CancellationRule {
+    pub class: Class,
+    pub notice_hours: u32,
+    pub refund_percentage: u8,
+}
+
+/// Decides how much of the price is refunded when a booking is cancelled.
+/// For each class, the most generous rule that applies is used. If no rule
+/// applies, the booking is non-refundable.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct CancellationPolicy {
+    pub rules: Vec<CancellationRule>,
+}
+
+impl CancellationPolicy {
+    /// The amount to refund for a booking in `class` costing `price`,
+    /// cancelled `notice` before departure
+    pub fn refund(&self, class: &Class, price: Money, notice: Duration) -> Money {
+        let percentage = self
+            .rules
+            .iter()
+            .filter(|r| &r.class == class && notice >= Duration::hours(r.notice_hours.into()))
+            .map(|r| r.refund_percentage.min(100))
+            .max()
+            .unwrap_or(0);
+
+        Money::new(price.amount * i64::from(percentage) / 100, price.currency)
+    }
+}
+
+impl Default for CancellationPolicy {
+    fn default() -> Self {
+        let rule = |class, notice_hours, refund_percentage| CancellationRule {
+            class,
+            notice_hours,
+            refund_percentage,
+        };
+
+        Self {
+            rules: vec![
+                rule(Class::First, 24, 100),
+                rule(Class::First, 2, 50),
+                rule(Class::Second, 48, 100),
+                rule(Class::Second, 24, 25),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use test_case::test_case;
+
+    use super::CancellationPolicy;
+    use crate::types::{
+        class::Class,
+        money::{Currency, Money},
+    };
+
+    #[test_case(Class::First, 48 => 10000; "first class, full refund")]
+    #[test_case(Class::First, 24 => 10000; "first class, full refund at the boundary")]
+    #[test_case(Class::First, 3 => 5000; "first class, partial refund")]
+    #[test_case(Class::First, 1 => 0; "first class, non-refundable")]
+    #[test_case(Class::Second, 72 => 10000; "second class, full refund")]
+    #[test_case(Class::Second, 30 => 2500; "second class, partial refund")]
+    #[test_case(Class::Second, 3 => 0; "second class, non-refundable")]
+    #[test_case(Class::Second, -1 => 0; "after departure")]
+    fn test_default_policy(class: Class, notice_hours: i64) -> i64 {
+        CancellationPolicy::default()
+            .refund(
+                &class,
+                Money::new(10000, Currency::Eur),
+                Duration::hours(notice_hours),
+            )
+            .amount
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::LazyLock;
 
+use chrono::NaiveDate;
 use nutype::nutype;
 use regex::Regex;
 use validator::{Validate, ValidateRegex, ValidationErrors};
@@ -29,6 +30,12 @@ impl TryFrom<String> for Name {
     }
 }
 
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 /// This time, we're `#[derive]`ing the `validator::Validate` trait, allowing us
 /// to call `Email::validate` within the `TryFrom<String>` application.
 /// Sadly, deriving [`validator::Validate`] on tuple structs is
@@ -68,6 +75,53 @@ impl From<Email> for String {
     }
 }
 
+/// Unlike the other details, a date of birth can't be validated once and
+/// for all when it's parsed: whether it's plausible depends on the day
+/// it's checked. Stored dates of birth are read as they are, and those that
+/// customers enter are checked with [`DateOfBirth::check_on`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(from = "NaiveDate", into = "NaiveDate")]
+pub struct DateOfBirth(NaiveDate);
+
+impl DateOfBirth {
+    const MAX_AGE: u32 = 120;
+
+    /// The age in whole years on `date`
+    pub fn age_on(&self, date: NaiveDate) -> u32 {
+        date.years_since(self.0).unwrap_or(0)
+    }
+
+    /// Check that the date isn't after `today`, nor implausibly long before
+    pub fn check_on(&self, today: NaiveDate) -> Result<(), DateOfBirthError> {
+        match today.years_since(self.0) {
+            None => Err(DateOfBirthError::InFuture(self.0)),
+            Some(age) if age > Self::MAX_AGE => Err(DateOfBirthError::TooLongAgo(self.0)),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DateOfBirthError {
+    #[error("Date of birth {0} is in the future")]
+    InFuture(NaiveDate),
+
+    #[error("Date of birth {0} is more than {max} years ago", max = DateOfBirth::MAX_AGE)]
+    TooLongAgo(NaiveDate),
+}
+
+impl From<NaiveDate> for DateOfBirth {
+    fn from(date: NaiveDate) -> Self {
+        Self(date)
+    }
+}
+
+impl From<DateOfBirth> for NaiveDate {
+    fn from(DateOfBirth(date): DateOfBirth) -> Self {
+        date
+    }
+}
+
 /// This struct is transformed by the `#[nutype]` macro, which, among other
 /// things, creates a new module and places the struct inside of that. As we're
 /// unable to modify that module, that ensures that we can't instantiate the
@@ -86,9 +140,11 @@ pub struct PhoneNumber(String);
 
 #[cfg(test)]
 mod tests {
-    use super::{PhoneNumber, PhoneNumberError};
+    use chrono::{Duration, NaiveDate, Utc};
     use test_case::test_case;
 
+    use super::{DateOfBirth, PhoneNumber, PhoneNumberError};
+
     #[test_case("☎️" => Err(PhoneNumberError::RegexViolated))]
     #[test_case("0612345678" => Err(PhoneNumberError::RegexViolated))]
     #[test_case("123-456" => Ok(()))]
@@ -96,4 +152,23 @@ mod tests {
         PhoneNumber::try_new(number)?;
         Ok(())
     }
+
+    #[test_case(-1 => false; "tomorrow")]
+    #[test_case(0 => true; "today")]
+    #[test_case(365 * 30 => true; "thirty years ago")]
+    #[test_case(365 * 125 => false; "too long ago")]
+    fn test_check_date_of_birth(days_ago: i64) -> bool {
+        let today = Utc::now().date_naive();
+        DateOfBirth::from(today - Duration::days(days_ago))
+            .check_on(today)
+            .is_ok()
+    }
+
+    #[test]
+    fn test_age_on() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let date_of_birth = DateOfBirth::from(date(2010, 3, 15));
+        assert_eq!(date_of_birth.age_on(date(2022, 3, 14)), 11);
+        assert_eq!(date_of_birth.age_on(date(2022, 3, 15)), 12);
+    }
 }
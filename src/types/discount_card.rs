@@ -0,0 +1,154 @@
+use chrono::NaiveDate;
+
+/// The discount cards we accept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountCardType {
+    YoungPersonsRailcard,
+    SeniorRailcard,
+    #[serde(rename = "bahncard_25")]
+    BahnCard25,
+    #[serde(rename = "bahncard_50")]
+    BahnCard50,
+}
+
+impl DiscountCardType {
+    /// The number of digits in a card number, including the check digit
+    fn number_len(&self) -> usize {
+        match self {
+            DiscountCardType::YoungPersonsRailcard | DiscountCardType::SeniorRailcard => 10,
+            DiscountCardType::BahnCard25 | DiscountCardType::BahnCard50 => 16,
+        }
+    }
+}
+
+/// The card a passenger has to show, as it's put on their ticket
+impl From<DiscountCardType> for takeoff_tickets::DiscountCardType {
+    fn from(card_type: DiscountCardType) -> Self {
+        match card_type {
+            DiscountCardType::YoungPersonsRailcard => Self::YoungPersonsRailcard,
+            DiscountCardType::SeniorRailcard => Self::SeniorRailcard,
+            DiscountCardType::BahnCard25 => Self::BahnCard25,
+            DiscountCardType::BahnCard50 => Self::BahnCard50,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiscountCardError {
+    #[error("{0} is not a valid {1:?} number")]
+    InvalidNumber(String, DiscountCardType),
+
+    #[error("Discount card {0} expires before the day of travel")]
+    Expired(String),
+}
+
+/// A discount card held by a passenger, which they have to show on board.
+/// The number is checked for the length that goes with the card type, and
+/// against its last digit, which is a Luhn check digit.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "DiscountCardFields")]
+pub struct DiscountCard {
+    pub card_type: DiscountCardType,
+    pub number: String,
+    /// The last day the card can be used on
+    pub valid_until: NaiveDate,
+}
+
+#[derive(serde::Deserialize)]
+struct DiscountCardFields {
+    card_type: DiscountCardType,
+    number: String,
+    valid_until: NaiveDate,
+}
+
+impl TryFrom<DiscountCardFields> for DiscountCard {
+    type Error = DiscountCardError;
+
+    fn try_from(
+        DiscountCardFields {
+            card_type,
+            number,
+            valid_until,
+        }: DiscountCardFields,
+    ) -> Result<Self, Self::Error> {
+        let number: String = number.chars().filter(|c| !c.is_whitespace()).collect();
+        if number.len() != card_type.number_len() || !luhn_valid(&number) {
+            return Err(DiscountCardError::InvalidNumber(number, card_type));
+        }
+        Ok(Self {
+            card_type,
+            number,
+            valid_until,
+        })
+    }
+}
+
+impl DiscountCard {
+    /// Check that the card can still be used on `date`
+    pub fn check_valid_on(&self, date: NaiveDate) -> Result<(), DiscountCardError> {
+        if date > self.valid_until {
+            return Err(DiscountCardError::Expired(self.number.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `number` consists of digits only, the last of
+/// which is the Luhn check digit over the others
+fn luhn_valid(number: &str) -> bool {
+    let digits: Option<Vec<u32>> = number.chars().map(|c| c.to_digit(10)).collect();
+    let Some(digits) = digits else {
+        return false;
+    };
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2 == 1, d * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => d,
+        })
+        .sum();
+    !digits.is_empty() && sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use test_case::test_case;
+
+    use super::DiscountCard;
+
+    #[test_case("young_persons_railcard", "7992739875" => true; "railcard")]
+    #[test_case("young_persons_railcard", "7992 7398 75" => true; "with spaces")]
+    #[test_case("young_persons_railcard", "7992739872" => false; "wrong check digit")]
+    #[test_case("young_persons_railcard", "79927398713" => false; "too long")]
+    #[test_case("bahncard_50", "7081411234567895" => true; "bahncard")]
+    #[test_case("bahncard_50", "708141123456789X" => false; "not a number")]
+    fn test_parse_card(card_type: &str, number: &str) -> bool {
+        serde_json::from_value::<DiscountCard>(json!({
+            "card_type": card_type,
+            "number": number,
+            "valid_until": "2030-12-31",
+        }))
+        .is_ok()
+    }
+
+    #[test]
+    fn test_validity() {
+        let card: DiscountCard = serde_json::from_value(json!({
+            "card_type": "senior_railcard",
+            "number": "7992739875",
+            "valid_until": "2030-12-31",
+        }))
+        .unwrap();
+        let date = |m, d| NaiveDate::from_ymd_opt(2030, m, d).unwrap();
+        card.check_valid_on(date(12, 31)).unwrap();
+        assert!(card
+            .check_valid_on(date(12, 31).succ_opt().unwrap())
+            .is_err());
+    }
+}
@@ -0,0 +1,541 @@
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+
+use super::{
+    ancillary::{Ancillary, AncillaryType},
+    class::Class,
+    discount_card::DiscountCardType,
+    journey::JourneyType,
+    location::Location,
+    money::{Currency, ExchangeRates, Money, MoneyError},
+    passenger::{FareCategory, PassengerType},
+    trip::Trip,
+};
+
+/// A fixed fare for travelling between two stations, in either direction
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct PairFare {
+    pub stations: (Location, Location),
+    pub fare: Money,
+}
+
+/// The distance between two stations by rail, in either direction
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Distance {
+    pub stations: (Location, Location),
+    pub km: u32,
+}
+
+/// Adds `surcharge_percentage` percent to trips departing on a weekday
+/// between `from` and `until`, UTC
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct PeakPeriod {
+    pub from: NaiveTime,
+    pub until: NaiveTime,
+    pub surcharge_percentage: u8,
+}
+
+/// Takes `discount_percentage` percent off for trips booked
+/// at least `days` days before departure
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct AdvanceDiscount {
+    pub days: u32,
+    pub discount_percentage: u8,
+}
+
+/// Takes `discount_percentage` percent off the adult fare for
+/// passengers holding a card of `card_type`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct CardDiscount {
+    pub card_type: DiscountCardType,
+    pub discount_percentage: u8,
+}
+
+/// The price of taking an add-on on a single trip
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct AncillaryPrice {
+    pub ancillary_type: AncillaryType,
+    pub price: Money,
+}
+
+/// Prices trips. The base fare comes from the station pair table if the
+/// route is in there, and is otherwise worked out from the distance. That
+/// base fare is for second class, and is then adjusted for the class, for
+/// peak hours and for how far in advance the trip is booked, in that order.
+/// Finally, it's converted to the currency of the station of departure.
+/// That gives the adult fare, which other passengers pay part of. A
+/// discount card doesn't add to the discount for the passenger type: the
+/// more generous of the two is used.
+/// Returns are priced as both ways together, less a discount, and open
+/// returns as a markup on the way out. Add-ons are priced per trip, so
+/// they're paid for twice on a return.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct FareEngine {
+    pub pair_fares: Vec<PairFare>,
+    pub distances: Vec<Distance>,
+    /// Per kilometre, for routes without a pair fare
+    pub rate_per_km: Money,
+    pub minimum_fare: Money,
+    /// The first class fare, as a percentage of the second class one
+    pub first_class_percentage: u16,
+    pub peak_periods: Vec<PeakPeriod>,
+    /// The most generous discount that applies is used
+    pub advance_discounts: Vec<AdvanceDiscount>,
+    pub exchange_rates: ExchangeRates,
+    /// The youth fare, as a percentage of the adult one
+    pub youth_percentage: u16,
+    /// The senior fare, as a percentage of the adult one
+    pub senior_percentage: u16,
+    /// The child fare, as a percentage of the adult one
+    pub child_percentage: u16,
+    /// Children younger than this travel for free
+    pub free_child_age: u8,
+    /// Taken off the two ways of a return
+    pub return_discount_percentage: u8,
+    /// The open return fare, as a percentage of the fare for the way out
+    pub open_return_percentage: u16,
+    pub card_discounts: Vec<CardDiscount>,
+    pub ancillary_prices: Vec<AncillaryPrice>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FareError {
+    #[error("No fare known from {0} to {1}")]
+    UnknownRoute(Location, Location),
+
+    #[error("No price known for {0:?}")]
+    UnknownAncillary(AncillaryType),
+
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+}
+
+impl FareEngine {
+    /// The price of `trip` in `class`, when booked at `now`
+    pub fn quote(
+        &self,
+        trip: &Trip,
+        class: &Class,
+        now: DateTime<Utc>,
+    ) -> Result<Money, FareError> {
+        let base = self.base_fare(&trip.origin, &trip.destination)?;
+
+        let class_percentage = match class {
+            Class::First => i64::from(self.first_class_percentage),
+            Class::Second => 100,
+        };
+        let peak_percentage = 100 + i64::from(self.peak_surcharge(trip.departure));
+        let advance_percentage = 100 - i64::from(self.advance_discount(trip.departure, now));
+
+        let mut fare = base;
+        for percentage in [class_percentage, peak_percentage, advance_percentage] {
+            fare = fare.percentage(percentage)?;
+        }
+        let fare = self.exchange_rates.convert(fare, trip.origin.currency())?;
+        Ok(fare)
+    }
+
+    /// The total price for `passengers` travelling together on `trip`
+    /// in `class`, when booked at `now`
+    pub fn quote_group(
+        &self,
+        trip: &Trip,
+        class: &Class,
+        passengers: &[FareCategory],
+        now: DateTime<Utc>,
+    ) -> Result<Money, FareError> {
+        let adult = self.quote(trip, class, now)?;
+        let mut total = Money::new(0, adult.currency);
+        for passenger in passengers {
+            let percentage = self.passenger_percentage(passenger);
+            total = total.checked_add(adult.percentage(percentage)?)?;
+        }
+        Ok(total)
+    }
+
+    /// The total price for `passengers` travelling out on `outbound` and
+    /// back on `inbound`, or on any trip back if that's `None`
+    pub fn quote_return(
+        &self,
+        outbound: &Trip,
+        inbound: Option<&Trip>,
+        class: &Class,
+        passengers: &[FareCategory],
+        now: DateTime<Utc>,
+    ) -> Result<Money, FareError> {
+        let out = self.quote_group(outbound, class, passengers, now)?;
+        let fare = match inbound {
+            Some(inbound) => {
+                let back = self.quote_group(inbound, class, passengers, now)?;
+                // Back is quoted in the currency of the destination
+                let back = self.exchange_rates.convert(back, out.currency)?;
+                out.checked_add(back)?
+                    .percentage(100 - i64::from(self.return_discount_percentage.min(100)))?
+            }
+            None => out.percentage(i64::from(self.open_return_percentage))?,
+        };
+        Ok(fare)
+    }
+
+    /// The price of taking `ancillaries` on `trip`, and on the way
+    /// back for a return, in the currency of the trip's origin
+    pub fn quote_ancillaries(
+        &self,
+        trip: &Trip,
+        journey_type: &JourneyType,
+        ancillaries: &[Ancillary],
+    ) -> Result<Money, FareError> {
+        let mut total = Money::new(0, trip.origin.currency());
+        for ancillary in ancillaries {
+            let price = self.quote_ancillary(trip, journey_type, ancillary)?;
+            total = total.checked_add(price)?;
+        }
+        Ok(total)
+    }
+
+    /// The price of taking a single `ancillary`, as for [`Self::quote_ancillaries`]
+    pub fn quote_ancillary(
+        &self,
+        trip: &Trip,
+        journey_type: &JourneyType,
+        ancillary: &Ancillary,
+    ) -> Result<Money, FareError> {
+        let trips = match journey_type {
+            JourneyType::Single => 1,
+            JourneyType::Return | JourneyType::OpenReturn => 2,
+        };
+        let price = self
+            .ancillary_prices
+            .iter()
+            .find(|p| p.ancillary_type == ancillary.ancillary_type)
+            .ok_or(FareError::UnknownAncillary(ancillary.ancillary_type))?;
+        let price = self
+            .exchange_rates
+            .convert(price.price, trip.origin.currency())?;
+        Ok(price.percentage(i64::from(ancillary.quantity) * trips * 100)?)
+    }
+
+    fn passenger_percentage(&self, passenger: &FareCategory) -> i64 {
+        let type_percentage = match passenger.passenger_type {
+            PassengerType::Adult => 100,
+            PassengerType::Youth => i64::from(self.youth_percentage),
+            PassengerType::Senior => i64::from(self.senior_percentage),
+            PassengerType::Child { age } if age < self.free_child_age => 0,
+            PassengerType::Child { .. } => i64::from(self.child_percentage),
+        };
+        let card_percentage = passenger
+            .discount_card
+            .and_then(|card_type| {
+                self.card_discounts
+                    .iter()
+                    .find(|d| d.card_type == card_type)
+            })
+            .map_or(100, |d| 100 - i64::from(d.discount_percentage.min(100)));
+        type_percentage.min(card_percentage)
+    }
+
+    fn base_fare(&self, origin: &Location, destination: &Location) -> Result<Money, FareError> {
+        let connects = |(a, b): &(Location, Location)| {
+            (a == origin && b == destination) || (a == destination && b == origin)
+        };
+
+        if let Some(pair) = self.pair_fares.iter().find(|p| connects(&p.stations)) {
+            return Ok(pair.fare);
+        }
+
+        let distance = self
+            .distances
+            .iter()
+            .find(|d| connects(&d.stations))
+            .ok_or_else(|| FareError::UnknownRoute(origin.clone(), destination.clone()))?;
+        let fare = self.rate_per_km.percentage(i64::from(distance.km) * 100)?;
+        if fare.amount < self.minimum_fare.amount {
+            return Ok(self.minimum_fare);
+        }
+        Ok(fare)
+    }
+
+    fn peak_surcharge(&self, departure: DateTime<Utc>) -> u8 {
+        if matches!(departure.weekday(), Weekday::Sat | Weekday::Sun) {
+            return 0;
+        }
+        let time = departure.time();
+        self.peak_periods
+            .iter()
+            .filter(|p| p.from <= time && time < p.until)
+            .map(|p| p.surcharge_percentage)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn advance_discount(&self, departure: DateTime<Utc>, now: DateTime<Utc>) -> u8 {
+        let days = (departure - now).num_days();
+        self.advance_discounts
+            .iter()
+            .filter(|d| days >= i64::from(d.days))
+            .map(|d| d.discount_percentage.min(100))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Default for FareEngine {
+    fn default() -> Self {
+        use Location::*;
+
+        let eur = |amount| Money::new(amount, Currency::Eur);
+        let pair = |a, b, fare| PairFare {
+            stations: (a, b),
+            fare: eur(fare),
+        };
+        let distance = |a, b, km| Distance {
+            stations: (a, b),
+            km,
+        };
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let card_discount = |card_type, discount_percentage| CardDiscount {
+            card_type,
+            discount_percentage,
+        };
+        let ancillary_price = |ancillary_type, price| AncillaryPrice {
+            ancillary_type,
+            price: eur(price),
+        };
+
+        Self {
+            pair_fares: vec![
+                pair(AmsterdamCentraal, ParisNord, 4900),
+                pair(ParisNord, LondonWaterloo, 6900),
+            ],
+            distances: vec![
+                distance(AmsterdamCentraal, BerlinHbf, 650),
+                distance(AmsterdamCentraal, LondonWaterloo, 540),
+                distance(ParisNord, BerlinHbf, 1050),
+                distance(BerlinHbf, LondonWaterloo, 1100),
+            ],
+            rate_per_km: eur(9),
+            minimum_fare: eur(1500),
+            first_class_percentage: 180,
+            peak_periods: vec![
+                PeakPeriod {
+                    from: time(6, 30),
+                    until: time(9, 0),
+                    surcharge_percentage: 20,
+                },
+                PeakPeriod {
+                    from: time(15, 30),
+                    until: time(18, 0),
+                    surcharge_percentage: 20,
+                },
+            ],
+            advance_discounts: vec![
+                AdvanceDiscount {
+                    days: 7,
+                    discount_percentage: 15,
+                },
+                AdvanceDiscount {
+                    days: 30,
+                    discount_percentage: 30,
+                },
+            ],
+            exchange_rates: ExchangeRates::default(),
+            youth_percentage: 75,
+            senior_percentage: 70,
+            child_percentage: 50,
+            free_child_age: 4,
+            return_discount_percentage: 10,
+            open_return_percentage: 170,
+            card_discounts: vec![
+                card_discount(DiscountCardType::YoungPersonsRailcard, 34),
+                card_discount(DiscountCardType::SeniorRailcard, 34),
+                card_discount(DiscountCardType::BahnCard25, 25),
+                card_discount(DiscountCardType::BahnCard50, 50),
+            ],
+            ancillary_prices: vec![
+                ancillary_price(AncillaryType::Bicycle, 1000),
+                ancillary_price(AncillaryType::ExtraLuggage, 1500),
+                ancillary_price(AncillaryType::Pet, 800),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, NaiveDate, Utc};
+    use test_case::test_case;
+
+    use super::FareEngine;
+    use crate::types::{
+        ancillary::{Ancillary, AncillaryType},
+        class::Class,
+        discount_card::DiscountCardType,
+        journey::JourneyType,
+        passenger::{FareCategory, PassengerType, Passengers},
+        trip::Trip,
+    };
+
+    /// Noon on a Wednesday
+    fn off_peak() -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(2030, 1, 2)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc()
+    }
+
+    fn trip(origin: &str, destination: &str, departure: DateTime<Utc>) -> Trip {
+        let trip = serde_json::json!({
+            "id": uuid::Uuid::new_v4(),
+            "origin": origin,
+            "destination": destination,
+            "departure": departure,
+            "arrival": departure + Duration::hours(2),
+        });
+        serde_json::from_value(trip).unwrap()
+    }
+
+    #[test_case("Amsterdam Centraal", "Paris Nord" => 4900; "pair fare")]
+    #[test_case("Paris Nord", "Amsterdam Centraal" => 4900; "pair fare, reversed")]
+    #[test_case("Amsterdam Centraal", "Berlin Hbf" => 5850; "by distance")]
+    #[test_case("Berlin Hbf", "Amsterdam Centraal" => 5850; "by distance, reversed")]
+    #[test_case("London Waterloo", "Berlin Hbf" => 8415; "in pounds")]
+    fn test_base_fare(origin: &str, destination: &str) -> i64 {
+        let departure = off_peak();
+        FareEngine::default()
+            .quote(
+                &trip(origin, destination, departure),
+                &Class::Second,
+                departure - Duration::hours(1),
+            )
+            .unwrap()
+            .amount
+    }
+
+    #[test_case(Class::Second, 12, 0, 1 => 4900; "off peak")]
+    #[test_case(Class::First, 12, 0, 1 => 8820; "first class")]
+    #[test_case(Class::Second, 8, 0, 1 => 5880; "morning peak")]
+    #[test_case(Class::Second, 9, 0, 1 => 4900; "morning peak is over")]
+    #[test_case(Class::First, 17, 59, 1 => 10584; "first class in evening peak")]
+    #[test_case(Class::Second, 12, 0, 7 => 4165; "a week in advance")]
+    #[test_case(Class::Second, 12, 0, 45 => 3430; "well in advance")]
+    #[test_case(Class::First, 8, 0, 30 => 7409; "everything at once")]
+    fn test_adjustments(class: Class, hour: u32, minute: u32, days_ahead: i64) -> i64 {
+        let departure = off_peak()
+            .date_naive()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+            .and_utc();
+        FareEngine::default()
+            .quote(
+                &trip("Amsterdam Centraal", "Paris Nord", departure),
+                &class,
+                departure - Duration::days(days_ahead) - Duration::minutes(1),
+            )
+            .unwrap()
+            .amount
+    }
+
+    #[test]
+    fn test_no_peak_at_weekends() {
+        // A Saturday morning
+        let departure = off_peak() + Duration::days(3) - Duration::hours(4);
+        let fare = FareEngine::default()
+            .quote(
+                &trip("Amsterdam Centraal", "Paris Nord", departure),
+                &Class::Second,
+                departure - Duration::hours(1),
+            )
+            .unwrap();
+        assert_eq!(fare.amount, 4900);
+    }
+
+    #[test]
+    fn test_group_fare() {
+        let departure = off_peak();
+        let passengers: Passengers = serde_json::from_value(serde_json::json!([
+            { "name": "Henk", "type": "adult" },
+            { "name": "Ingrid", "type": "senior" },
+            { "name": "Jan", "type": "youth" },
+            { "name": "Kees", "type": "child", "age": 8 },
+            { "name": "Lotte", "type": "child", "age": 2 },
+        ]))
+        .unwrap();
+        let fare = FareEngine::default()
+            .quote_group(
+                &trip("Amsterdam Centraal", "Paris Nord", departure),
+                &Class::Second,
+                &passengers.fare_categories(),
+                departure - Duration::hours(1),
+            )
+            .unwrap();
+        // 4900 + 3430 + 3675 + 2450 + 0
+        assert_eq!(fare.amount, 14455);
+    }
+
+    #[test]
+    fn test_return_fare() {
+        let departure = off_peak();
+        let now = departure - Duration::hours(1);
+        let outbound = trip("Amsterdam Centraal", "London Waterloo", departure);
+        let inbound = trip("London Waterloo", "Amsterdam Centraal", departure);
+        let passengers = [PassengerType::Adult.into()];
+        let fares = FareEngine::default();
+
+        // €48.60 out and £41.31 back, which is €48.60 as well, less 10%
+        let fare = fares
+            .quote_return(&outbound, Some(&inbound), &Class::Second, &passengers, now)
+            .unwrap();
+        assert_eq!(fare.amount, 8748);
+        let fare = fares
+            .quote_return(&outbound, None, &Class::Second, &passengers, now)
+            .unwrap();
+        assert_eq!(fare.amount, 8262);
+    }
+
+    #[test_case(PassengerType::Adult, None => 4900; "adult")]
+    #[test_case(PassengerType::Adult, Some(DiscountCardType::BahnCard50) => 2450; "adult with card")]
+    #[test_case(PassengerType::Senior, Some(DiscountCardType::BahnCard25) => 3430; "senior discount is better")]
+    #[test_case(PassengerType::Senior, Some(DiscountCardType::SeniorRailcard) => 3234; "card is better")]
+    fn test_discount_card(
+        passenger_type: PassengerType,
+        discount_card: Option<DiscountCardType>,
+    ) -> i64 {
+        let departure = off_peak();
+        FareEngine::default()
+            .quote_group(
+                &trip("Amsterdam Centraal", "Paris Nord", departure),
+                &Class::Second,
+                &[FareCategory {
+                    passenger_type,
+                    discount_card,
+                }],
+                departure - Duration::hours(1),
+            )
+            .unwrap()
+            .amount
+    }
+
+    #[test_case("Amsterdam Centraal", JourneyType::Single => 4000; "single")]
+    #[test_case("Amsterdam Centraal", JourneyType::Return => 8000; "return")]
+    #[test_case("London Waterloo", JourneyType::Single => 3400; "in pounds")]
+    fn test_ancillaries(origin: &str, journey_type: JourneyType) -> i64 {
+        let ancillaries = [
+            Ancillary {
+                ancillary_type: AncillaryType::Bicycle,
+                quantity: 1,
+            },
+            Ancillary {
+                ancillary_type: AncillaryType::ExtraLuggage,
+                quantity: 2,
+            },
+        ];
+        FareEngine::default()
+            .quote_ancillaries(
+                &trip(origin, "Paris Nord", off_peak()),
+                &journey_type,
+                &ancillaries,
+            )
+            .unwrap()
+            .amount
+    }
+}
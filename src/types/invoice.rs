@@ -0,0 +1,447 @@
+use chrono::{DateTime, Utc};
+
+use super::{
+    address::{BillingAddress, Country},
+    booking::{Booking, BookingReference, BookingStatus, Contact, PriceBreakdown},
+    class::Class,
+    fare::{FareEngine, FareError},
+    journey::JourneyType,
+    location::Location,
+    money::{Money, MoneyError},
+};
+
+/// Invoices are numbered in sequence, without gaps, starting from 1
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+#[serde(transparent)]
+pub struct InvoiceNumber(pub u64);
+
+impl InvoiceNumber {
+    pub fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+impl std::fmt::Display for InvoiceNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:08}", self.0)
+    }
+}
+
+/// What a line on an invoice is for. Passenger transport is taxed at a
+/// reduced rate in most countries, unlike the add-ons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FareComponent {
+    Transport,
+    Ancillary,
+}
+
+impl FareComponent {
+    /// The VAT rate in `country`, as a percentage
+    pub fn vat_percentage(&self, country: Country) -> u32 {
+        match (self, country) {
+            (FareComponent::Transport, Country::Nl) => 9,
+            (FareComponent::Transport, Country::Fr) => 10,
+            (FareComponent::Transport, Country::De) => 7,
+            // Passenger transport is zero-rated in the United Kingdom
+            (FareComponent::Transport, Country::Gb) => 0,
+            (FareComponent::Ancillary, Country::Nl) => 21,
+            (FareComponent::Ancillary, Country::Fr | Country::Gb) => 20,
+            (FareComponent::Ancillary, Country::De) => 19,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvoiceError {
+    #[error("Only confirmed bookings can be invoiced")]
+    NotConfirmed,
+
+    #[error(transparent)]
+    Fare(#[from] FareError),
+
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+}
+
+/// A line on an invoice. Prices include VAT, so the net amount is
+/// what's left after taking out the VAT.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct InvoiceLine {
+    pub description: String,
+    pub component: FareComponent,
+    /// The country the VAT is due in, where the leg the line is for
+    /// starts. The way back of a return is taxed where it starts.
+    pub country: Country,
+    pub vat_percentage: u32,
+    pub net: Money,
+    pub vat: Money,
+    pub gross: Money,
+}
+
+impl InvoiceLine {
+    fn new(
+        description: String,
+        component: FareComponent,
+        country: Country,
+        gross: Money,
+    ) -> Result<Self, MoneyError> {
+        let vat_percentage = component.vat_percentage(country);
+        let vat = gross.vat_included(vat_percentage)?;
+        Ok(Self {
+            description,
+            component,
+            country,
+            vat_percentage,
+            net: gross.checked_sub(vat)?,
+            vat,
+            gross,
+        })
+    }
+
+    /// The lines for the fare, the add-ons and the voucher on `booking`,
+    /// which add up to the price that was paid. They're made out from the
+    /// [`PriceBreakdown`] of the booking, which is only quoted again with
+    /// `fares` for bookings made before it was stored. The discount of a
+    /// voucher is split over the fare and the add-ons in proportion to
+    /// their prices, as they're taxed at different rates. Everything on a
+    /// return is split evenly over the way out and the way back, which
+    /// are taxed in the countries they start in.
+    pub fn for_booking(booking: &Booking, fares: &FareEngine) -> Result<Vec<Self>, InvoiceError> {
+        if booking.status != BookingStatus::Confirmed {
+            return Err(InvoiceError::NotConfirmed);
+        }
+        let breakdown = match &booking.breakdown {
+            Some(breakdown) => breakdown.clone(),
+            None => Self::quote_breakdown(booking, fares)?,
+        };
+        let legs = legs(booking);
+        // Lines for the whole journey name the leg they're for, if it has two
+        let on_leg = |description: String, (from, to): (&Location, &Location)| match legs.len() {
+            1 => description,
+            _ => format!("{description}, {from} - {to}"),
+        };
+
+        let journey = match booking.journey_type {
+            JourneyType::Single => "Single",
+            JourneyType::Return => "Return",
+            JourneyType::OpenReturn => "Open return",
+        };
+        let class = match booking.class {
+            Class::First => "first class",
+            Class::Second => "second class",
+        };
+        let mut lines = vec![];
+        for (&(from, to), share) in legs.iter().zip(split(breakdown.transport, legs.len())?) {
+            let description = format!(
+                "{journey} {from} - {to}, {class}, {} passenger(s)",
+                booking.seats(),
+            );
+            lines.push(Self::new(
+                description,
+                FareComponent::Transport,
+                from.country(),
+                share,
+            )?);
+        }
+
+        for (ancillary, &price) in booking.ancillaries.iter().zip(&breakdown.ancillaries) {
+            for (&leg, share) in legs.iter().zip(split(price, legs.len())?) {
+                let description =
+                    format!("{:?} x {}", ancillary.ancillary_type, ancillary.quantity);
+                lines.push(Self::new(
+                    on_leg(description, leg),
+                    FareComponent::Ancillary,
+                    leg.0.country(),
+                    share,
+                )?);
+            }
+        }
+
+        if let Some(voucher) = &booking.voucher {
+            let subtotal = breakdown.subtotal()?;
+            let ancillaries = subtotal.checked_sub(breakdown.transport)?;
+            let on_ancillaries = match subtotal.amount {
+                0 => 0,
+                total => {
+                    let share = i128::from(breakdown.discount.amount)
+                        * i128::from(ancillaries.amount)
+                        / i128::from(total);
+                    i64::try_from(share).map_err(|_| MoneyError::Overflow)?
+                }
+            };
+            let on_ancillaries = Money::new(on_ancillaries, subtotal.currency);
+            let on_transport = breakdown.discount.checked_sub(on_ancillaries)?;
+            for (discount, component, description) in [
+                (
+                    on_transport,
+                    FareComponent::Transport,
+                    format!("Voucher {}", voucher.code),
+                ),
+                (
+                    on_ancillaries,
+                    FareComponent::Ancillary,
+                    format!("Voucher {} on add-ons", voucher.code),
+                ),
+            ] {
+                for (&leg, share) in legs.iter().zip(split(discount, legs.len())?) {
+                    if share.amount != 0 {
+                        lines.push(Self::new(
+                            on_leg(description.clone(), leg),
+                            component,
+                            leg.0.country(),
+                            share.checked_neg()?,
+                        )?);
+                    }
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    /// The breakdown of the price of a booking made before it was stored,
+    /// with the add-ons quoted again and the rest of the price for the fare
+    fn quote_breakdown(
+        booking: &Booking,
+        fares: &FareEngine,
+    ) -> Result<PriceBreakdown, InvoiceError> {
+        let ancillaries = booking
+            .ancillaries
+            .iter()
+            .map(|ancillary| fares.quote_ancillary(&booking.trip, &booking.journey_type, ancillary))
+            .collect::<Result<Vec<_>, _>>()?;
+        let discount = booking
+            .voucher
+            .as_ref()
+            .map_or(Money::new(0, booking.price.currency), |v| v.discount);
+        let mut transport = booking.price.checked_add(discount)?;
+        for &price in &ancillaries {
+            transport = transport.checked_sub(price)?;
+        }
+        Ok(PriceBreakdown {
+            transport,
+            ancillaries,
+            discount,
+        })
+    }
+}
+
+/// The origin and destination of each leg of `booking`: the way out, and
+/// the way back for a return. The trip back of an open return isn't
+/// known, but starts at the destination all the same.
+fn legs(booking: &Booking) -> Vec<(&Location, &Location)> {
+    let out = (&booking.trip.origin, &booking.trip.destination);
+    match booking.journey_type {
+        JourneyType::Single => vec![out],
+        JourneyType::Return | JourneyType::OpenReturn => {
+            let back = booking
+                .return_trip
+                .as_ref()
+                .map_or((out.1, out.0), |t| (&t.origin, &t.destination));
+            vec![out, back]
+        }
+    }
+}
+
+/// Split `amount` evenly into `parts`, the first one taking
+/// what doesn't divide evenly
+fn split(amount: Money, parts: usize) -> Result<Vec<Money>, MoneyError> {
+    let share = Money::new(amount.amount / parts as i64, amount.currency);
+    let first = (1..parts).try_fold(amount, |rest, _| rest.checked_sub(share))?;
+    Ok(std::iter::once(first)
+        .chain(std::iter::repeat(share).take(parts - 1))
+        .collect())
+}
+
+/// The VAT due at one rate, over all lines taxed at that rate
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct VatSummary {
+    pub country: Country,
+    pub vat_percentage: u32,
+    pub net: Money,
+    pub vat: Money,
+}
+
+/// An invoice for a booking. Once issued, an invoice never changes, and
+/// a booking gets only one.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Invoice {
+    pub number: InvoiceNumber,
+    pub reference: BookingReference,
+    pub issued_at: DateTime<Utc>,
+    pub contact: Contact,
+    pub billing_address: Option<BillingAddress>,
+    pub lines: Vec<InvoiceLine>,
+    pub vat: Vec<VatSummary>,
+    pub total_net: Money,
+    pub total_vat: Money,
+    pub total: Money,
+}
+
+impl Invoice {
+    /// Draw up invoice `number` for `booking`, with the lines from
+    /// [`InvoiceLine::for_booking`]
+    pub fn new(
+        number: InvoiceNumber,
+        booking: &Booking,
+        lines: Vec<InvoiceLine>,
+        issued_at: DateTime<Utc>,
+    ) -> Result<Self, MoneyError> {
+        let mut vat: Vec<VatSummary> = vec![];
+        for line in &lines {
+            let summary = vat
+                .iter_mut()
+                .find(|s| s.country == line.country && s.vat_percentage == line.vat_percentage);
+            match summary {
+                Some(summary) => {
+                    summary.net = summary.net.checked_add(line.net)?;
+                    summary.vat = summary.vat.checked_add(line.vat)?;
+                }
+                None => vat.push(VatSummary {
+                    country: line.country,
+                    vat_percentage: line.vat_percentage,
+                    net: line.net,
+                    vat: line.vat,
+                }),
+            }
+        }
+        let sum = |amount: fn(&InvoiceLine) -> Money| {
+            lines
+                .iter()
+                .try_fold(Money::new(0, booking.price.currency), |total, line| {
+                    total.checked_add(amount(line))
+                })
+        };
+
+        Ok(Self {
+            number,
+            reference: booking.reference.clone(),
+            issued_at,
+            contact: booking.contact.clone(),
+            billing_address: booking.billing_address.clone(),
+            total_net: sum(|l| l.net)?,
+            total_vat: sum(|l| l.vat)?,
+            total: sum(|l| l.gross)?,
+            lines,
+            vat,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Invoice, InvoiceLine, InvoiceNumber};
+    use crate::types::{
+        address::Country,
+        ancillary::Ancillaries,
+        booking::{tests::booking, AppliedVoucher, Booking, PriceBreakdown},
+        fare::FareEngine,
+        journey::JourneyType,
+        location::Location,
+        money::{Currency, Money},
+    };
+
+    fn booking_with_bicycle_and_voucher() -> Booking {
+        let mut booking = booking();
+        booking.ancillaries = serde_json::from_value::<Ancillaries>(serde_json::json!([
+            { "type": "bicycle", "quantity": 1 },
+        ]))
+        .unwrap();
+        booking.voucher = Some(AppliedVoucher {
+            code: "SUMMER24".to_owned().try_into().unwrap(),
+            discount: Money::new(600, Currency::Eur),
+        });
+        booking.price = Money::new(5400, Currency::Eur);
+        booking
+    }
+
+    #[test]
+    fn test_invoice() {
+        let mut booking = booking_with_bicycle_and_voucher();
+        // Not what the fares are now, but what was charged
+        booking.breakdown = Some(PriceBreakdown {
+            transport: Money::new(4500, Currency::Eur),
+            ancillaries: vec![Money::new(1500, Currency::Eur)],
+            discount: Money::new(600, Currency::Eur),
+        });
+
+        let lines = InvoiceLine::for_booking(&booking, &FareEngine::default()).unwrap();
+        let gross: Vec<_> = lines.iter().map(|l| l.gross.amount).collect();
+        // The voucher takes a quarter off the bicycle, like off the fare
+        assert_eq!(gross, [4500, 1500, -450, -150]);
+        let rates: Vec<_> = lines.iter().map(|l| l.vat_percentage).collect();
+        assert_eq!(rates, [9, 21, 9, 21]);
+
+        let invoice = Invoice::new(InvoiceNumber(7), &booking, lines, chrono::Utc::now()).unwrap();
+        assert_eq!(invoice.number.to_string(), "00000007");
+        assert_eq!(invoice.total, booking.price);
+        assert_eq!(invoice.vat.len(), 2);
+        // The VAT is worked out per line: 372 - 37 for the fare
+        // and its discount, and 260 - 26 for the bicycle
+        assert_eq!(invoice.vat[0].vat.amount, 335);
+        assert_eq!(invoice.vat[1].vat.amount, 234);
+        assert_eq!(invoice.total_vat.amount, 569);
+        assert_eq!(
+            invoice.total_net.amount + invoice.total_vat.amount,
+            invoice.total.amount
+        );
+    }
+
+    #[test]
+    fn test_invoice_without_breakdown() {
+        let booking = booking_with_bicycle_and_voucher();
+
+        // The bicycle is quoted again at 1000, leaving 5000 for the fare
+        let lines = InvoiceLine::for_booking(&booking, &FareEngine::default()).unwrap();
+        let gross: Vec<_> = lines.iter().map(|l| l.gross.amount).collect();
+        assert_eq!(gross, [5000, 1000, -500, -100]);
+        let invoice = Invoice::new(InvoiceNumber(7), &booking, lines, chrono::Utc::now()).unwrap();
+        assert_eq!(invoice.total, booking.price);
+    }
+
+    #[test]
+    fn test_invoice_return_across_countries() {
+        let mut booking = booking_with_bicycle_and_voucher();
+        booking.journey_type = JourneyType::Return;
+        booking.trip.destination = Location::LondonWaterloo;
+        let mut return_trip = booking.trip.clone();
+        return_trip.origin = Location::LondonWaterloo;
+        return_trip.destination = Location::AmsterdamCentraal;
+        booking.return_trip = Some(return_trip);
+        booking.breakdown = Some(PriceBreakdown {
+            transport: Money::new(9000, Currency::Eur),
+            ancillaries: vec![Money::new(2000, Currency::Eur)],
+            discount: Money::new(1100, Currency::Eur),
+        });
+        booking.price = Money::new(9900, Currency::Eur);
+
+        let lines = InvoiceLine::for_booking(&booking, &FareEngine::default()).unwrap();
+        let summary: Vec<_> = lines
+            .iter()
+            .map(|l| (l.country, l.vat_percentage, l.gross.amount))
+            .collect();
+        // The way back starts in London, where the fare is zero-rated
+        assert_eq!(
+            summary,
+            [
+                (Country::Nl, 9, 4500),
+                (Country::Gb, 0, 4500),
+                (Country::Nl, 21, 1000),
+                (Country::Gb, 20, 1000),
+                (Country::Nl, 9, -450),
+                (Country::Gb, 0, -450),
+                (Country::Nl, 21, -100),
+                (Country::Gb, 20, -100),
+            ]
+        );
+        assert!(lines[1]
+            .description
+            .starts_with("Return London Waterloo - Amsterdam"));
+
+        let invoice = Invoice::new(InvoiceNumber(8), &booking, lines, chrono::Utc::now()).unwrap();
+        assert_eq!(invoice.total, booking.price);
+        assert_eq!(invoice.vat.len(), 4);
+    }
+}
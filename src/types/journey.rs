@@ -0,0 +1,11 @@
+/// Whether the customer travels back as well. A return is booked on a
+/// specific trip each way, while an open return leaves the way back open,
+/// so that any trip back on the same route can be taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JourneyType {
+    #[default]
+    Single,
+    Return,
+    OpenReturn,
+}
@@ -1,17 +1,64 @@
+use super::{address::Country, money::Currency};
+
+/// A station served by the trains. It's written as its name, like
+/// "Amsterdam Centraal".
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
-#[serde(try_from = "String")]
-pub struct Location(String);
+#[serde(try_from = "String", into = "&'static str")]
+pub enum Location {
+    AmsterdamCentraal,
+    ParisNord,
+    BerlinHbf,
+    LondonWaterloo,
+}
 
 impl Location {
+    const ALL: [Self; 4] = [
+        Self::AmsterdamCentraal,
+        Self::ParisNord,
+        Self::BerlinHbf,
+        Self::LondonWaterloo,
+    ];
+
     pub fn is_valid_location(location: &str) -> bool {
-        const VALID_LOCATIONS: &[&str] = &[
-            "Amsterdam Centraal",
-            "Paris Nord",
-            "Berlin Hbf",
-            "London Waterloo",
-        ];
+        Self::ALL.iter().any(|station| station.name() == location)
+    }
+
+    /// The name of the station, as it's shown and parsed
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AmsterdamCentraal => "Amsterdam Centraal",
+            Self::ParisNord => "Paris Nord",
+            Self::BerlinHbf => "Berlin Hbf",
+            Self::LondonWaterloo => "London Waterloo",
+        }
+    }
+
+    /// Whether the station is in the Schengen area. Travelling between
+    /// the Schengen area and elsewhere means passing border control.
+    pub fn in_schengen_area(&self) -> bool {
+        match self {
+            Self::AmsterdamCentraal | Self::ParisNord | Self::BerlinHbf => true,
+            Self::LondonWaterloo => false,
+        }
+    }
 
-        VALID_LOCATIONS.contains(&location)
+    /// The country the station is in, where the VAT on journeys
+    /// starting here is due
+    pub fn country(&self) -> Country {
+        match self {
+            Self::AmsterdamCentraal => Country::Nl,
+            Self::ParisNord => Country::Fr,
+            Self::BerlinHbf => Country::De,
+            Self::LondonWaterloo => Country::Gb,
+        }
+    }
+
+    /// The currency tickets departing from here are sold in
+    pub fn currency(&self) -> Currency {
+        match self {
+            Self::LondonWaterloo => Currency::Gbp,
+            Self::AmsterdamCentraal | Self::ParisNord | Self::BerlinHbf => Currency::Eur,
+        }
     }
 }
 
@@ -19,10 +66,16 @@ impl TryFrom<String> for Location {
     type Error = ParseLocationError;
 
     fn try_from(s: String) -> Result<Self, Self::Error> {
-        if !Self::is_valid_location(&s) {
-            return Err(ParseLocationError(s));
-        }
-        Ok(Self(s))
+        Self::ALL
+            .into_iter()
+            .find(|station| station.name() == s)
+            .ok_or(ParseLocationError(s))
+    }
+}
+
+impl From<Location> for &'static str {
+    fn from(location: Location) -> Self {
+        location.name()
     }
 }
 
@@ -32,6 +85,6 @@ pub struct ParseLocationError(String);
 
 impl std::fmt::Display for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.name().fmt(f)
     }
 }
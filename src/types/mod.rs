@@ -1,7 +1,22 @@
+pub mod address;
+pub mod ancillary;
+pub mod assistance;
+pub mod booking;
+pub mod cancellation;
 pub mod class;
 pub mod customer_details;
 pub mod departure_or_arrival;
+pub mod discount_card;
+pub mod fare;
+pub mod invoice;
+pub mod journey;
 pub mod location;
+pub mod money;
+pub mod passenger;
 pub mod payment_info;
+pub mod seat;
+pub mod ticket;
 pub mod ticket_machine;
+pub mod travel_document;
 pub mod trip;
+pub mod voucher;
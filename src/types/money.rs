@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MoneyError {
+    #[error("Cannot combine amounts in {0:?} and {1:?}")]
+    CurrencyMismatch(Currency, Currency),
+
+    #[error("Amount is out of range")]
+    Overflow,
+
+    #[error("No exchange rate known for {0:?}")]
+    NoExchangeRate(Currency),
+}
+
+/// An amount of money, expressed in the minor unit of its [`Currency`] (e.g.
+/// cents), so that we never need floating point numbers to represent prices.
+/// Arithmetic is checked: it fails rather than mixing up currencies or
+/// overflowing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let amount = self
+            .amount
+            .checked_add(other.amount)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let amount = self
+            .amount
+            .checked_sub(other.amount)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    pub fn checked_neg(self) -> Result<Money, MoneyError> {
+        let amount = self.amount.checked_neg().ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    /// `percentage` percent of this amount, rounded as per [`Money::round`]
+    pub fn percentage(self, percentage: i64) -> Result<Money, MoneyError> {
+        let amount = i128::from(self.amount) * i128::from(percentage);
+        self.currency.round_quotient(amount, 100)
+    }
+
+    /// The VAT included in this amount, at a rate of `percentage` percent,
+    /// rounded as per [`Money::round`]
+    pub fn vat_included(self, percentage: u32) -> Result<Money, MoneyError> {
+        let amount = i128::from(self.amount) * i128::from(percentage);
+        self.currency
+            .round_quotient(amount, 100 + i128::from(percentage))
+    }
+
+    /// Round to the smallest amount that can be paid in this currency,
+    /// halves rounding up
+    pub fn round(self) -> Money {
+        let increment = self.currency.rounding_increment();
+        let remainder = self.amount.rem_euclid(increment);
+        let amount = if remainder * 2 >= increment {
+            self.amount - remainder + increment
+        } else {
+            self.amount - remainder
+        };
+        Money::new(amount, self.currency)
+    }
+
+    fn same_currency(self, other: Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(())
+    }
+
+    /// Format the amount the way customers using `locale` are used to
+    pub fn format(&self, locale: Locale) -> String {
+        let digits = self.currency.minor_digits();
+        let scale = 10u64.pow(digits);
+        let abs = self.amount.unsigned_abs();
+        let (major, minor) = (abs / scale, abs % scale);
+
+        let mut number = group_thousands(major, locale.grouping_separator());
+        if digits > 0 {
+            number.push(locale.decimal_separator());
+            number.push_str(&format!("{minor:0width$}", width = digits as usize));
+        }
+
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let symbol = self.currency.symbol();
+        let symbol_space = if symbol.chars().all(char::is_alphabetic) {
+            "\u{a0}"
+        } else {
+            ""
+        };
+        match locale {
+            Locale::EnGb => format!("{sign}{symbol}{symbol_space}{number}"),
+            Locale::NlNl => format!("{symbol}\u{a0}{sign}{number}"),
+            Locale::FrFr | Locale::DeDe => format!("{sign}{number}\u{a0}{symbol}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format(Locale::default()))
+    }
+}
+
+fn group_thousands(n: u64, separator: char) -> String {
+    let digits = n.to_string();
+    let groups: Vec<_> = digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect();
+    groups.join(separator.encode_utf8(&mut [0; 4]))
+}
+
+/// The currencies we sell tickets in or display prices in,
+/// (de)serialized as their ISO 4217 code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Eur,
+    Gbp,
+    Chf,
+}
+
+impl Currency {
+    /// The number of digits after the decimal point
+    pub fn minor_digits(&self) -> u32 {
+        match self {
+            Currency::Eur | Currency::Gbp | Currency::Chf => 2,
+        }
+    }
+
+    /// The smallest amount that can be paid, in minor units. Swiss
+    /// prices are rounded to five centimes, as there's no smaller coin.
+    pub fn rounding_increment(&self) -> i64 {
+        match self {
+            Currency::Eur | Currency::Gbp => 1,
+            Currency::Chf => 5,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Eur => "€",
+            Currency::Gbp => "£",
+            Currency::Chf => "CHF",
+        }
+    }
+
+    /// Turn `numerator / denominator` minor units into [`Money`], rounding
+    /// to the nearest minor unit first, and then as per [`Money::round`]
+    fn round_quotient(self, numerator: i128, denominator: i128) -> Result<Money, MoneyError> {
+        let quotient = numerator.div_euclid(denominator);
+        let remainder = numerator.rem_euclid(denominator);
+        let rounded = if remainder * 2 >= denominator {
+            quotient + 1
+        } else {
+            quotient
+        };
+        let amount = i64::try_from(rounded).map_err(|_| MoneyError::Overflow)?;
+        Ok(Money::new(amount, self).round())
+    }
+}
+
+/// The locales prices can be formatted for, (de)serialized
+/// as their BCP 47 language tag
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "en-GB")]
+    EnGb,
+    #[serde(rename = "nl-NL")]
+    NlNl,
+    #[serde(rename = "fr-FR")]
+    FrFr,
+    #[serde(rename = "de-DE")]
+    DeDe,
+}
+
+impl Locale {
+    fn decimal_separator(&self) -> char {
+        match self {
+            Locale::EnGb => '.',
+            Locale::NlNl | Locale::FrFr | Locale::DeDe => ',',
+        }
+    }
+
+    fn grouping_separator(&self) -> char {
+        match self {
+            Locale::EnGb => ',',
+            Locale::NlNl | Locale::DeDe => '.',
+            Locale::FrFr => '\u{202f}',
+        }
+    }
+}
+
+/// Static exchange rates, used to show prices in the customer's
+/// currency. Rates are relative to `base`, in millionths: a rate of
+/// 850_000 for GBP means that one unit of `base` buys £0.85.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ExchangeRates {
+    pub base: Currency,
+    pub rates: HashMap<Currency, i64>,
+}
+
+impl ExchangeRates {
+    const ONE: i64 = 1_000_000;
+
+    fn rate(&self, currency: Currency) -> Result<i64, MoneyError> {
+        if currency == self.base {
+            return Ok(Self::ONE);
+        }
+        self.rates
+            .get(&currency)
+            .copied()
+            .filter(|&rate| rate > 0)
+            .ok_or(MoneyError::NoExchangeRate(currency))
+    }
+
+    /// Convert `money` into `currency`, rounding as per [`Money::round`]
+    pub fn convert(&self, money: Money, currency: Currency) -> Result<Money, MoneyError> {
+        if money.currency == currency {
+            return Ok(money);
+        }
+        let (from, to) = (self.rate(money.currency)?, self.rate(currency)?);
+        let digits = |c: Currency| 10i128.pow(c.minor_digits());
+
+        let numerator = i128::from(money.amount) * i128::from(to) * digits(currency);
+        let denominator = i128::from(from) * digits(money.currency);
+        currency.round_quotient(numerator, denominator)
+    }
+}
+
+impl Default for ExchangeRates {
+    fn default() -> Self {
+        Self {
+            base: Currency::Eur,
+            rates: HashMap::from([(Currency::Gbp, 850_000), (Currency::Chf, 940_000)]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{Currency, ExchangeRates, Locale, Money, MoneyError};
+
+    #[test]
+    fn test_arithmetic() {
+        let eur = |amount| Money::new(amount, Currency::Eur);
+
+        assert_eq!(eur(150).checked_add(eur(-200)).unwrap(), eur(-50));
+        assert_eq!(eur(150).checked_sub(eur(200)).unwrap(), eur(-50));
+        assert!(matches!(
+            eur(1).checked_add(Money::new(1, Currency::Gbp)),
+            Err(MoneyError::CurrencyMismatch(Currency::Eur, Currency::Gbp))
+        ));
+        assert!(matches!(
+            eur(i64::MAX).checked_add(eur(1)),
+            Err(MoneyError::Overflow)
+        ));
+        assert!(matches!(
+            eur(i64::MIN).checked_neg(),
+            Err(MoneyError::Overflow)
+        ));
+    }
+
+    #[test_case(Currency::Eur, 999, 15 => 150; "rounds half up")]
+    #[test_case(Currency::Eur, 1001, 15 => 150; "rounds down")]
+    #[test_case(Currency::Eur, -999, 15 => -150; "negative")]
+    #[test_case(Currency::Chf, 1010, 15 => 150; "to five centimes")]
+    #[test_case(Currency::Chf, 1000, 17 => 170; "already round")]
+    fn test_percentage(currency: Currency, amount: i64, percentage: i64) -> i64 {
+        Money::new(amount, currency)
+            .percentage(percentage)
+            .unwrap()
+            .amount
+    }
+
+    #[test_case(10900, 9 => 900; "dutch fare")]
+    #[test_case(1000, 21 => 174; "rounded")]
+    #[test_case(-500, 21 => -87; "discount")]
+    #[test_case(6900, 0 => 0; "zero-rated")]
+    fn test_vat_included(amount: i64, percentage: u32) -> i64 {
+        Money::new(amount, Currency::Eur)
+            .vat_included(percentage)
+            .unwrap()
+            .amount
+    }
+
+    #[test_case(Money::new(123456, Currency::Eur), Locale::EnGb => "€1,234.56")]
+    #[test_case(Money::new(-5, Currency::Gbp), Locale::EnGb => "-£0.05")]
+    #[test_case(Money::new(123456, Currency::Chf), Locale::EnGb => "CHF\u{a0}1,234.56")]
+    #[test_case(Money::new(123456, Currency::Eur), Locale::NlNl => "€\u{a0}1.234,56")]
+    #[test_case(Money::new(-100, Currency::Eur), Locale::NlNl => "€\u{a0}-1,00")]
+    #[test_case(Money::new(123456789, Currency::Eur), Locale::FrFr => "1\u{202f}234\u{202f}567,89\u{a0}€")]
+    #[test_case(Money::new(100000, Currency::Eur), Locale::DeDe => "1.000,00\u{a0}€")]
+    fn test_format(money: Money, locale: Locale) -> String {
+        money.format(locale)
+    }
+
+    #[test_case(Money::new(10000, Currency::Eur), Currency::Gbp => 8500; "from base")]
+    #[test_case(Money::new(8500, Currency::Gbp), Currency::Eur => 10000; "to base")]
+    #[test_case(Money::new(1000, Currency::Gbp), Currency::Chf => 1105; "between others")]
+    #[test_case(Money::new(1000, Currency::Eur), Currency::Eur => 1000; "same currency")]
+    fn test_convert(money: Money, currency: Currency) -> i64 {
+        let money = ExchangeRates::default().convert(money, currency).unwrap();
+        assert_eq!(money.currency, currency);
+        money.amount
+    }
+
+    #[test]
+    fn test_convert_without_rate() {
+        let rates = ExchangeRates {
+            rates: Default::default(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            rates.convert(Money::new(100, Currency::Eur), Currency::Gbp),
+            Err(MoneyError::NoExchangeRate(Currency::Gbp))
+        ));
+    }
+}
@@ -0,0 +1,466 @@
+use chrono::NaiveDate;
+
+use super::{
+    assistance::Assistance,
+    customer_details::{DateOfBirth, DateOfBirthError, Name},
+    discount_card::{DiscountCard, DiscountCardError, DiscountCardType},
+    travel_document::{MissingDetails, Nationality, TravelDocument, TravelDocumentError},
+};
+
+/// Children under this age travel as a child, and as youths or adults
+/// from then on
+pub const CHILD_AGE_LIMIT: u8 = 12;
+
+/// The number of passengers that can be booked together
+pub const MAX_PASSENGERS: usize = 9;
+
+/// Passengers travel as an adult or senior from this age on
+pub const ADULT_AGE: u32 = 18;
+
+/// Passengers under this age can't travel without an adult
+pub const UNACCOMPANIED_MINOR_AGE: u32 = 12;
+
+/// Decides the fare a passenger pays. Children are (de)serialized along with
+/// their age, as that decides whether they pay at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PassengerType {
+    Adult,
+    Youth,
+    Senior,
+    Child { age: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Passenger {
+    pub name: Name,
+    #[serde(flatten)]
+    pub passenger_type: PassengerType,
+    #[serde(default)]
+    pub discount_card: Option<DiscountCard>,
+    #[serde(default)]
+    pub assistance: Option<Assistance>,
+    /// Required on routes that cross border control, along
+    /// with the date of birth and the travel document
+    #[serde(default)]
+    pub nationality: Option<Nationality>,
+    #[serde(default)]
+    pub date_of_birth: Option<DateOfBirth>,
+    #[serde(default)]
+    pub travel_document: Option<TravelDocument>,
+}
+
+impl Passenger {
+    pub fn needs_wheelchair_space(&self) -> bool {
+        self.assistance
+            .as_ref()
+            .is_some_and(Assistance::needs_wheelchair_space)
+    }
+
+    /// The age on `date`, taken from the date of birth when it's
+    /// given, and from the age of a child otherwise
+    pub fn age_on(&self, date: NaiveDate) -> Option<u32> {
+        match (self.date_of_birth, self.passenger_type) {
+            (Some(date_of_birth), _) => Some(date_of_birth.age_on(date)),
+            (None, PassengerType::Child { age }) => Some(age.into()),
+            (None, _) => None,
+        }
+    }
+
+    /// Whether the passenger can look after minors on `date`
+    fn is_adult_on(&self, date: NaiveDate) -> bool {
+        match self.age_on(date) {
+            Some(age) => age >= ADULT_AGE,
+            None => matches!(
+                self.passenger_type,
+                PassengerType::Adult | PassengerType::Senior
+            ),
+        }
+    }
+
+    /// The type of the passenger and their discount card, for pricing
+    pub fn fare_category(&self) -> FareCategory {
+        FareCategory {
+            passenger_type: self.passenger_type,
+            discount_card: self.discount_card.as_ref().map(|c| c.card_type),
+        }
+    }
+}
+
+/// What decides the fare a single passenger pays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FareCategory {
+    pub passenger_type: PassengerType,
+    pub discount_card: Option<DiscountCardType>,
+}
+
+impl From<PassengerType> for FareCategory {
+    fn from(passenger_type: PassengerType) -> Self {
+        Self {
+            passenger_type,
+            discount_card: None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PassengersError {
+    #[error("At least one passenger is needed")]
+    Empty,
+
+    #[error("At most {MAX_PASSENGERS} passengers can be booked together")]
+    TooMany,
+
+    #[error("Passengers aged {0} or older can't travel as a child")]
+    ChildTooOld(u8),
+
+    #[error("The companion {0} must be one of the other passengers")]
+    UnknownCompanion(Name),
+
+    #[error("{0} is {1} on the day of travel, too old to travel as a child")]
+    ChildTooOldOn(Name, u32),
+
+    #[error("{0} is {1} on the day of travel, too young to travel as an adult or senior")]
+    TooYoung(Name, u32),
+
+    #[error("{0} is under {UNACCOMPANIED_MINOR_AGE} and can't travel without an adult")]
+    UnaccompaniedMinor(Name),
+
+    #[error("The date of birth of {0} is invalid: {1}")]
+    DateOfBirth(Name, DateOfBirthError),
+}
+
+/// The passengers travelling on a booking, validated on deserialization
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "Vec<Passenger>")]
+pub struct Passengers(Vec<Passenger>);
+
+impl TryFrom<Vec<Passenger>> for Passengers {
+    type Error = PassengersError;
+
+    fn try_from(passengers: Vec<Passenger>) -> Result<Self, Self::Error> {
+        if passengers.is_empty() {
+            return Err(PassengersError::Empty);
+        }
+        if passengers.len() > MAX_PASSENGERS {
+            return Err(PassengersError::TooMany);
+        }
+        for passenger in &passengers {
+            if let PassengerType::Child { age } = passenger.passenger_type {
+                if age >= CHILD_AGE_LIMIT {
+                    return Err(PassengersError::ChildTooOld(age));
+                }
+            }
+            let companion = passenger
+                .assistance
+                .as_ref()
+                .and_then(|a| a.companion.as_ref());
+            if let Some(companion) = companion {
+                if *companion == passenger.name || !passengers.iter().any(|p| p.name == *companion)
+                {
+                    return Err(PassengersError::UnknownCompanion(companion.clone()));
+                }
+            }
+        }
+        Ok(Self(passengers))
+    }
+}
+
+impl Passengers {
+    /// A single adult
+    pub fn adult(name: Name) -> Self {
+        Self(vec![Passenger {
+            name,
+            passenger_type: PassengerType::Adult,
+            discount_card: None,
+            assistance: None,
+            nationality: None,
+            date_of_birth: None,
+            travel_document: None,
+        }])
+    }
+
+    /// The number of seats these passengers need
+    pub fn seats(&self) -> u32 {
+        self.0.len() as u32
+    }
+
+    /// The fare categories of the passengers, for pricing
+    pub fn fare_categories(&self) -> Vec<FareCategory> {
+        self.0.iter().map(Passenger::fare_category).collect()
+    }
+
+    /// The number of wheelchair spaces these passengers need
+    pub fn wheelchair_spaces(&self) -> u32 {
+        self.0.iter().filter(|p| p.needs_wheelchair_space()).count() as u32
+    }
+
+    /// Check that every passenger has given the details needed to pass
+    /// border control, listing all that are missing, and that their travel
+    /// documents are still valid on `date`
+    pub fn check_travel_documents(&self, date: NaiveDate) -> Result<(), TravelDocumentError> {
+        let missing: Vec<_> = self
+            .0
+            .iter()
+            .filter_map(|p| {
+                let fields: Vec<_> = [
+                    ("nationality", p.nationality.is_none()),
+                    ("date_of_birth", p.date_of_birth.is_none()),
+                    ("travel_document", p.travel_document.is_none()),
+                ]
+                .into_iter()
+                .filter_map(|(field, missing)| missing.then_some(field))
+                .collect();
+                (!fields.is_empty()).then(|| MissingDetails {
+                    passenger: p.name.clone(),
+                    fields,
+                })
+            })
+            .collect();
+        if !missing.is_empty() {
+            return Err(TravelDocumentError::Missing(missing));
+        }
+
+        for passenger in &self.0 {
+            if passenger
+                .travel_document
+                .as_ref()
+                .is_some_and(|d| d.expires_on < date)
+            {
+                return Err(TravelDocumentError::Expired(passenger.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the dates of birth that were given are plausible on
+    /// `today`, when the passengers are entered
+    pub fn check_dates_of_birth(&self, today: NaiveDate) -> Result<(), PassengersError> {
+        for passenger in &self.0 {
+            if let Some(date_of_birth) = passenger.date_of_birth {
+                date_of_birth
+                    .check_on(today)
+                    .map_err(|e| PassengersError::DateOfBirth(passenger.name.clone(), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the ages of the passengers on `date`, the day of travel,
+    /// match their passenger types, and that minors travel with an adult
+    pub fn check_ages(&self, date: NaiveDate) -> Result<(), PassengersError> {
+        for passenger in &self.0 {
+            let Some(age) = passenger.date_of_birth.map(|d| d.age_on(date)) else {
+                continue;
+            };
+            match passenger.passenger_type {
+                PassengerType::Child { .. } if age >= CHILD_AGE_LIMIT.into() => {
+                    return Err(PassengersError::ChildTooOldOn(passenger.name.clone(), age));
+                }
+                PassengerType::Adult | PassengerType::Senior if age < ADULT_AGE => {
+                    return Err(PassengersError::TooYoung(passenger.name.clone(), age));
+                }
+                _ => {}
+            }
+        }
+
+        if !self.0.iter().any(|p| p.is_adult_on(date)) {
+            let minor = self.0.iter().find(|p| {
+                p.age_on(date)
+                    .is_some_and(|age| age < UNACCOMPANIED_MINOR_AGE)
+            });
+            if let Some(minor) = minor {
+                return Err(PassengersError::UnaccompaniedMinor(minor.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every discount card can still be used on `date`
+    pub fn check_discount_cards(&self, date: NaiveDate) -> Result<(), DiscountCardError> {
+        self.0
+            .iter()
+            .filter_map(|p| p.discount_card.as_ref())
+            .try_for_each(|card| card.check_valid_on(date))
+    }
+}
+
+impl std::ops::Deref for Passengers {
+    type Target = [Passenger];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use super::{PassengerType, Passengers, PassengersError};
+    use crate::types::{discount_card::DiscountCardType, travel_document::TravelDocumentError};
+
+    #[test]
+    fn test_deserialize_passengers() {
+        let passengers: Passengers = serde_json::from_value(json!([
+            { "name": "Henk", "type": "adult" },
+            { "name": "Ingrid", "type": "child", "age": 7 },
+        ]))
+        .unwrap();
+        assert_eq!(passengers.seats(), 2);
+        assert_eq!(
+            passengers
+                .fare_categories()
+                .iter()
+                .map(|c| c.passenger_type)
+                .collect::<Vec<_>>(),
+            [PassengerType::Adult, PassengerType::Child { age: 7 }]
+        );
+
+        assert!(serde_json::from_value::<Passengers>(json!([])).is_err());
+        assert!(serde_json::from_value::<Passengers>(json!([
+            { "name": "Ingrid", "type": "child", "age": 12 },
+        ]))
+        .is_err());
+        assert!(serde_json::from_value::<Passengers>(json!([
+            { "name": "Ingrid", "type": "child" },
+        ]))
+        .is_err());
+        let too_many = vec![json!({ "name": "Henk", "type": "adult" }); 10];
+        assert!(serde_json::from_value::<Passengers>(json!(too_many)).is_err());
+    }
+
+    #[test]
+    fn test_passenger_with_discount_card() {
+        let card = json!({
+            "card_type": "senior_railcard",
+            "number": "7992739875",
+            "valid_until": "2030-12-31",
+        });
+        let passengers: Passengers = serde_json::from_value(json!([
+            { "name": "Henk", "type": "senior", "discount_card": card },
+        ]))
+        .unwrap();
+        assert_eq!(
+            passengers.fare_categories()[0].discount_card,
+            Some(DiscountCardType::SeniorRailcard)
+        );
+
+        let mut card = card;
+        card["number"] = json!("7992739872");
+        assert!(serde_json::from_value::<Passengers>(json!([
+            { "name": "Henk", "type": "senior", "discount_card": card },
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_companion() {
+        let passengers = |companion: &str| {
+            serde_json::from_value::<Passengers>(json!([
+                {
+                    "name": "Henk",
+                    "type": "adult",
+                    "assistance": {
+                        "types": ["wheelchair_space"],
+                        "mobility_aid": "manual_wheelchair",
+                        "companion": companion,
+                    },
+                },
+                { "name": "Ingrid", "type": "adult" },
+            ]))
+        };
+        assert_eq!(passengers("Ingrid").unwrap().wheelchair_spaces(), 1);
+        assert!(passengers("Henk").is_err());
+        assert!(passengers("Jan").is_err());
+    }
+
+    #[test]
+    fn test_travel_documents() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let travel = date(2030, 6, 1);
+        let mut passengers: Passengers = serde_json::from_value(json!([
+            {
+                "name": "Henk",
+                "type": "adult",
+                "nationality": "nl",
+                "date_of_birth": "1980-01-01",
+                "travel_document": {
+                    "document_type": "passport",
+                    "number": "NX12345A",
+                    "expires_on": "2030-06-01",
+                },
+            },
+            { "name": "Ingrid", "type": "adult", "nationality": "NL" },
+        ]))
+        .unwrap();
+
+        let err = passengers.check_travel_documents(travel).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("Missing: Ingrid (date_of_birth, travel_document)"));
+
+        passengers.0.pop();
+        passengers.check_travel_documents(travel).unwrap();
+        assert!(matches!(
+            passengers.check_travel_documents(travel.succ_opt().unwrap()),
+            Err(TravelDocumentError::Expired(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_ages() {
+        let travel = NaiveDate::from_ymd_opt(2030, 6, 1).unwrap();
+        let passengers = |passengers: serde_json::Value| -> Passengers {
+            serde_json::from_value(passengers).unwrap()
+        };
+
+        let child = json!({ "name": "Ingrid", "type": "child", "age": 8 });
+        assert!(matches!(
+            passengers(json!([child])).check_ages(travel),
+            Err(PassengersError::UnaccompaniedMinor(_))
+        ));
+        passengers(json!([{ "name": "Henk", "type": "adult" }, child]))
+            .check_ages(travel)
+            .unwrap();
+
+        // Turns 18 in 2030, so not an adult yet on the day of travel
+        let teenager = json!({ "name": "Henk", "type": "adult", "date_of_birth": "2012-06-02" });
+        assert!(matches!(
+            passengers(json!([teenager])).check_ages(travel),
+            Err(PassengersError::TooYoung(_, 17))
+        ));
+        let youth = json!({ "name": "Henk", "type": "youth", "date_of_birth": "2012-06-02" });
+        assert!(matches!(
+            passengers(json!([youth, child])).check_ages(travel),
+            Err(PassengersError::UnaccompaniedMinor(_))
+        ));
+        passengers(json!([youth])).check_ages(travel).unwrap();
+
+        let grown_up =
+            json!({ "name": "Ingrid", "type": "child", "age": 8, "date_of_birth": "2015-01-01" });
+        assert!(matches!(
+            passengers(json!([{ "name": "Henk", "type": "adult" }, grown_up])).check_ages(travel),
+            Err(PassengersError::ChildTooOldOn(_, 15))
+        ));
+    }
+
+    #[test]
+    fn test_check_dates_of_birth() {
+        let today = NaiveDate::from_ymd_opt(2030, 6, 1).unwrap();
+        // Stored dates of birth are read whatever day it is
+        let passengers: Passengers = serde_json::from_value(json!([
+            { "name": "Henk", "type": "adult", "date_of_birth": "1980-01-01" },
+            { "name": "Ingrid", "type": "child", "age": 0, "date_of_birth": "2030-06-02" },
+        ]))
+        .unwrap();
+        assert!(matches!(
+            passengers.check_dates_of_birth(today),
+            Err(PassengersError::DateOfBirth(..))
+        ));
+        passengers
+            .check_dates_of_birth(today.succ_opt().unwrap())
+            .unwrap();
+    }
+}
@@ -1,5 +1,8 @@
+/// Payment details as entered by the customer. The [`std::fmt::Display`] and
+/// [`std::fmt::Debug`] implementations never reveal the contents, but
+/// serialization does, as the complete value needs to be stored in the
+/// session. Use [`crate::views::TicketMachineView`] to send it to a client.
 #[derive(Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
-#[serde(into = "String")]
 pub struct PaymentInfo(String);
 
 impl std::fmt::Display for PaymentInfo {
@@ -16,36 +19,40 @@ impl std::fmt::Debug for PaymentInfo {
     }
 }
 
-impl From<PaymentInfo> for String {
-    fn from(p: PaymentInfo) -> Self {
-        p.to_string()
-    }
-}
-
-impl TryFrom<String> for PaymentInfo {
-    type Error = std::convert::Infallible;
-
-    fn try_from(s: String) -> Result<Self, Self::Error> {
-        // IRL you'd do input validation here
-        Ok(Self(s))
+impl From<String> for PaymentInfo {
+    fn from(s: String) -> Self {
+        // IRL you'd do input validation here, and implement `TryFrom` instead
+        Self(s)
     }
 }
 
 #[tokio::test]
 async fn test_payment_details_debug_impl() {
-    use crate::types::ticket_machine::TicketMachine;
+    use crate::types::{
+        ancillary::Ancillaries, journey::JourneyType, ticket_machine::TicketMachine,
+    };
     use std::fmt::Write;
 
     let ticket_machine = TicketMachine {
+        revision: 0,
         origin: None,
         destination: None,
         time: None,
         trip: None,
+        journey_type: JourneyType::Single,
+        return_time: None,
+        return_trip: None,
         class: None,
+        hold: None,
+        seat: None,
+        passengers: None,
+        ancillaries: Ancillaries::default(),
         name: None,
         email: None,
         phone_number: None,
-        payment_info: Some("💰💰💰".to_owned().try_into().unwrap()),
+        billing_address: None,
+        voucher: None,
+        payment_info: Some("💰💰💰".to_owned().into()),
     };
     let mut dbg_output = String::new();
     write!(&mut dbg_output, "{ticket_machine:?}").unwrap();
@@ -53,9 +60,11 @@ async fn test_payment_details_debug_impl() {
     assert_eq!(
         dbg_output,
         concat!(
-            "TicketMachine { origin: None, destination: None, time: None, ",
-            "trip: None, class: None, name: None, email: None, ",
-            r#"phone_number: None, payment_info: Some(PaymentInfo("<SECRET>")) }"#
+            "TicketMachine { revision: 0, origin: None, destination: None, time: None, ",
+            "trip: None, journey_type: Single, return_time: None, return_trip: None, ",
+            "class: None, hold: None, seat: None, passengers: None, ",
+            "ancillaries: Ancillaries([]), name: None, email: None, ",
+            r#"phone_number: None, billing_address: None, voucher: None, payment_info: Some(PaymentInfo("<SECRET>")) }"#
         )
     )
 }
@@ -0,0 +1,160 @@
+use super::class::Class;
+
+/// Identifies a seat on a train by its coach and seat number, as
+/// printed on the coach and above the seat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct SeatId {
+    pub coach: u8,
+    pub seat: u16,
+}
+
+impl std::fmt::Display for SeatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "coach {}, seat {}", self.coach, self.seat)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeatAttribute {
+    Window,
+    Aisle,
+    Table,
+    QuietZone,
+    WheelchairSpace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Seat {
+    pub id: SeatId,
+    pub attributes: Vec<SeatAttribute>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Coach {
+    pub number: u8,
+    pub class: Class,
+    pub seats: Vec<Seat>,
+}
+
+/// The coaches of a train, and the seats in each of them
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct SeatLayout {
+    pub coaches: Vec<Coach>,
+}
+
+impl SeatLayout {
+    /// The layout of the trains we run: two first class coaches with rows
+    /// of three seats, followed by five second class coaches with rows of
+    /// four. The first rows of each coach have tables, the last coach of
+    /// each class is a quiet zone, and the first coach of each class has
+    /// room for a wheelchair.
+    pub fn standard() -> Self {
+        let first = (1..=2).map(|n| Self::coach(n, Class::First, 10, 3));
+        let second = (3..=7).map(|n| Self::coach(n, Class::Second, 15, 4));
+        Self {
+            coaches: first.chain(second).collect(),
+        }
+    }
+
+    fn coach(number: u8, class: Class, rows: u16, seats_per_row: u16) -> Coach {
+        const TABLE_ROWS: u16 = 2;
+
+        let quiet = matches!(number, 2 | 7);
+        let wheelchair = matches!(number, 1 | 3);
+
+        let seats = (0..rows)
+            .flat_map(|row| (0..seats_per_row).map(move |pos| (row, pos)))
+            .map(|(row, pos)| {
+                let mut attributes = vec![];
+                if pos == 0 || pos == seats_per_row - 1 {
+                    attributes.push(SeatAttribute::Window);
+                } else {
+                    attributes.push(SeatAttribute::Aisle);
+                }
+                if row < TABLE_ROWS {
+                    attributes.push(SeatAttribute::Table);
+                }
+                if quiet {
+                    attributes.push(SeatAttribute::QuietZone);
+                }
+                if wheelchair && row == rows - 1 && pos == 0 {
+                    attributes.push(SeatAttribute::WheelchairSpace);
+                }
+
+                Seat {
+                    id: SeatId {
+                        coach: number,
+                        seat: row * seats_per_row + pos + 1,
+                    },
+                    attributes,
+                }
+            })
+            .collect();
+
+        Coach {
+            number,
+            class,
+            seats,
+        }
+    }
+
+    /// Look up a seat, along with the class it's in
+    pub fn find(&self, id: &SeatId) -> Option<(&Seat, &Class)> {
+        self.coaches
+            .iter()
+            .filter(|c| c.number == id.coach)
+            .flat_map(|c| c.seats.iter().map(move |s| (s, &c.class)))
+            .find(|(s, _)| s.id == *id)
+    }
+
+    /// The number of seats in `class`
+    pub fn capacity(&self, class: &Class) -> u32 {
+        self.coaches
+            .iter()
+            .filter(|c| c.class == *class)
+            .map(|c| c.seats.len() as u32)
+            .sum()
+    }
+
+    /// The number of seats in `class` with room for a wheelchair
+    pub fn wheelchair_spaces(&self, class: &Class) -> u32 {
+        self.coaches
+            .iter()
+            .filter(|c| c.class == *class)
+            .flat_map(|c| &c.seats)
+            .filter(|s| s.attributes.contains(&SeatAttribute::WheelchairSpace))
+            .count() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SeatAttribute, SeatId, SeatLayout};
+    use crate::types::class::Class;
+
+    #[test]
+    fn test_standard_layout() {
+        let layout = SeatLayout::standard();
+        assert_eq!(layout.capacity(&Class::First), 60);
+        assert_eq!(layout.capacity(&Class::Second), 300);
+        assert_eq!(layout.wheelchair_spaces(&Class::First), 1);
+
+        let (seat, class) = layout.find(&SeatId { coach: 1, seat: 1 }).unwrap();
+        assert_eq!(class, &Class::First);
+        assert_eq!(
+            seat.attributes,
+            [SeatAttribute::Window, SeatAttribute::Table]
+        );
+
+        let (seat, class) = layout.find(&SeatId { coach: 7, seat: 42 }).unwrap();
+        assert_eq!(class, &Class::Second);
+        assert_eq!(
+            seat.attributes,
+            [SeatAttribute::Aisle, SeatAttribute::QuietZone]
+        );
+
+        assert!(layout.find(&SeatId { coach: 1, seat: 31 }).is_none());
+        assert!(layout.find(&SeatId { coach: 8, seat: 1 }).is_none());
+    }
+}
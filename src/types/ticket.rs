@@ -0,0 +1,136 @@
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use takeoff_tickets::{DiscountCardType, ScanOutcome};
+
+use super::{
+    booking::{Booking, BookingReference},
+    class::Class,
+    customer_details::Name,
+    journey::JourneyType,
+    trip::{Trip, TripId},
+};
+
+pub use takeoff_tickets::TicketPayload;
+
+/// How long before departure a ticket can be used, to get on board
+pub const VALID_BEFORE_DEPARTURE: Duration = Duration::hours(1);
+/// How long after arrival a ticket can be used, to get out of the station
+pub const VALID_AFTER_ARRIVAL: Duration = Duration::hours(1);
+/// How long the way back of an open return can be taken, from the
+/// departure of the trip out
+pub const OPEN_RETURN_VALIDITY: Duration = Duration::days(30);
+
+/// The tickets for `booking`: one for each passenger on each trip
+pub fn for_booking(booking: &Booking) -> Vec<TicketPayload> {
+    // Bookings made before passengers were recorded are for the contact
+    let passengers: Vec<_> = match booking.passengers.as_slice() {
+        [] => vec![(booking.contact.name.clone(), None)],
+        passengers => passengers
+            .iter()
+            .map(|p| {
+                let card = p.discount_card.as_ref();
+                (
+                    p.name.clone(),
+                    card.map(|c| DiscountCardType::from(c.card_type)),
+                )
+            })
+            .collect(),
+    };
+    let trip = &booking.trip;
+    let ticket = |(passenger, discount_card): &(Name, Option<DiscountCardType>), trip: &Trip| {
+        TicketPayload {
+            reference: booking.reference.to_string(),
+            trip: Some(trip.id.clone().into()),
+            origin: trip.origin.to_string(),
+            destination: trip.destination.to_string(),
+            passenger: passenger.to_string(),
+            class: match booking.class {
+                Class::First => takeoff_tickets::Class::First,
+                Class::Second => takeoff_tickets::Class::Second,
+            },
+            discount_card: *discount_card,
+            valid_from: trip.departure - VALID_BEFORE_DEPARTURE,
+            valid_until: trip.arrival + VALID_AFTER_ARRIVAL,
+        }
+    };
+
+    let mut tickets: Vec<TicketPayload> = vec![];
+    for passenger in &passengers {
+        tickets.push(ticket(passenger, trip));
+        match (&booking.journey_type, &booking.return_trip) {
+            (JourneyType::Return, Some(return_trip)) => {
+                tickets.push(ticket(passenger, return_trip));
+            }
+            (JourneyType::OpenReturn, _) => tickets.push(TicketPayload {
+                trip: None,
+                origin: trip.destination.to_string(),
+                destination: trip.origin.to_string(),
+                valid_from: trip.arrival,
+                valid_until: trip.departure + OPEN_RETURN_VALIDITY,
+                ..ticket(passenger, trip)
+            }),
+            _ => {}
+        }
+    }
+    // The payload only has whole seconds, so leave out the rest for
+    // the tickets to be the same after they're verified
+    for ticket in &mut tickets {
+        ticket.valid_from = ticket.valid_from.trunc_subsecs(0);
+        ticket.valid_until = ticket.valid_until.trunc_subsecs(0);
+    }
+    tickets
+}
+
+/// A scan of a ticket by a conductor, as it's logged
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TicketScan {
+    /// The [`takeoff_tickets::SignedTicket::id`] of the ticket
+    pub ticket: String,
+    pub reference: BookingReference,
+    /// The trip it was scanned on
+    pub trip: TripId,
+    pub outcome: ScanOutcome,
+    pub scanned_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use takeoff_tickets::DiscountCardType;
+
+    use super::for_booking;
+    use crate::types::{booking::tests::booking, journey::JourneyType};
+
+    #[test]
+    fn test_for_booking() {
+        let mut booking = booking();
+        let card = json!({
+            "card_type": "senior_railcard",
+            "number": "7992739875",
+            "valid_until": "2030-12-31",
+        });
+        booking.passengers = serde_json::from_value(json!([
+            { "name": "Henk", "type": "senior", "discount_card": card },
+            { "name": "Ingrid", "type": "child", "age": 7 },
+        ]))
+        .unwrap();
+        let tickets = for_booking(&booking);
+        assert_eq!(tickets.len(), 2);
+        assert_eq!(tickets[1].passenger, "Ingrid");
+        // Only the passenger with the card has to show it
+        assert_eq!(
+            tickets[0].discount_card,
+            Some(DiscountCardType::SeniorRailcard)
+        );
+        assert_eq!(tickets[1].discount_card, None);
+        assert!(tickets[0].is_valid_at(booking.trip.departure));
+        assert!(!tickets[0].is_valid_at(booking.trip.arrival + chrono::Duration::hours(2)));
+
+        booking.journey_type = JourneyType::OpenReturn;
+        let tickets = for_booking(&booking);
+        assert_eq!(tickets.len(), 4);
+        assert_eq!(tickets[1].trip, None);
+        assert_eq!(tickets[1].origin, booking.trip.destination.to_string());
+        assert_eq!(tickets[1].discount_card, tickets[0].discount_card);
+    }
+}
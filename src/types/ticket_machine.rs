@@ -1,30 +1,307 @@
+use chrono::{DateTime, Utc};
+
+use crate::error::Error;
+use crate::inventory::{HoldId, Inventory};
+use crate::payment::PaymentGateway;
+use crate::timetable::Timetable;
 use crate::types::location::Location;
+use crate::vouchers::Vouchers;
 use crate::Result;
 
 use super::{
+    address::BillingAddress,
+    ancillary::Ancillaries,
+    booking::{
+        AppliedVoucher, Booking, BookingReference, BookingStatus, Charge, Contact, PriceBreakdown,
+        StatusChange,
+    },
     class::Class,
     customer_details::{Email, Name, PhoneNumber},
     departure_or_arrival::DepartureOrArrival,
+    fare::FareEngine,
+    journey::JourneyType,
+    money::Money,
+    passenger::{FareCategory, PassengerType, Passengers},
     payment_info::PaymentInfo,
-    trip::TripId,
+    seat::SeatId,
+    trip::{Trip, TripId},
+    voucher::VoucherCode,
 };
 
-#[derive(Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+/// The state of the booking process. This type is not (de)serializable by
+/// itself: see [`crate::storage::StoredTicketMachine`] for the representation
+/// kept in the session, and [`crate::views::TicketMachineView`] for the one
+/// sent to clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct TicketMachine {
+    /// Incremented on every update, so that concurrent
+    /// updates can be detected
+    pub revision: u64,
     pub origin: Option<Location>,
     pub destination: Option<Location>,
     pub time: Option<DepartureOrArrival>,
     pub trip: Option<TripId>,
+    pub journey_type: JourneyType,
+    /// When to travel back, for a return
+    pub return_time: Option<DepartureOrArrival>,
+    pub return_trip: Option<TripId>,
     pub class: Option<Class>,
+    /// The seats that are set aside while the booking is completed
+    pub hold: Option<HoldId>,
+    pub seat: Option<SeatId>,
+    /// A single adult, named after the contact, if not given
+    pub passengers: Option<Passengers>,
+    /// Taken along on the trip out, and on the way back for a return
+    pub ancillaries: Ancillaries,
+    /// The name of the lead contact
     pub name: Option<Name>,
     pub email: Option<Email>,
     pub phone_number: Option<PhoneNumber>,
+    /// Needed only for invoices, so it's optional
+    pub billing_address: Option<BillingAddress>,
+    pub voucher: Option<VoucherCode>,
     pub payment_info: Option<PaymentInfo>,
 }
 
 impl TicketMachine {
-    pub fn book(&self) -> Result<()> {
+    /// The selected trip out, and the trip back for a return, provided
+    /// they're still in the [`Timetable`]
+    pub fn trips(&self, timetable: &Timetable) -> Result<(Trip, Option<Trip>)> {
+        let trip = self
+            .trip
+            .as_ref()
+            .ok_or(Error::BadRequest("Select a trip first"))?;
+        let trip = timetable
+            .find(trip)
+            .ok_or(Error::BadRequest("Selected trip is no longer available"))?;
+        let return_trip = match self.journey_type {
+            JourneyType::Return => {
+                let return_trip = self
+                    .return_trip
+                    .as_ref()
+                    .ok_or(Error::BadRequest("Select a return trip first"))?;
+                let return_trip = timetable.find(return_trip).ok_or(Error::BadRequest(
+                    "Selected return trip is no longer available",
+                ))?;
+                if return_trip.origin != trip.destination || return_trip.destination != trip.origin
+                {
+                    return Err(Error::BadRequest("The return trip must go the other way"));
+                }
+                if return_trip.departure < trip.arrival {
+                    return Err(Error::BadRequest(
+                        "The return trip departs before the trip out arrives",
+                    ));
+                }
+                Some(return_trip)
+            }
+            JourneyType::Single | JourneyType::OpenReturn => None,
+        };
+        Ok((trip, return_trip))
+    }
+
+    /// The price of the selected trips in the selected class for all
+    /// passengers and their add-ons, as quoted by `fares`, before any
+    /// voucher is taken off
+    pub fn quote(&self, timetable: &Timetable, fares: &FareEngine) -> Result<Money> {
+        let (trip, return_trip) = self.trips(timetable)?;
+        let class = self
+            .class
+            .as_ref()
+            .ok_or(Error::BadRequest("Set class first"))?;
+        let breakdown = self.breakdown(fares, &trip, return_trip.as_ref(), class, Utc::now())?;
+        Ok(breakdown.subtotal()?)
+    }
+
+    /// The fare categories of the passengers, or of a single
+    /// adult if they haven't been given
+    pub fn fare_categories(&self) -> Vec<FareCategory> {
+        self.passengers.as_ref().map_or_else(
+            || vec![PassengerType::Adult.into()],
+            Passengers::fare_categories,
+        )
+    }
+
+    /// The fare and the price of each add-on, with nothing taken off yet
+    fn breakdown(
+        &self,
+        fares: &FareEngine,
+        trip: &Trip,
+        return_trip: Option<&Trip>,
+        class: &Class,
+        now: DateTime<Utc>,
+    ) -> Result<PriceBreakdown> {
+        let types = self.fare_categories();
+        let transport = match self.journey_type {
+            JourneyType::Single => fares.quote_group(trip, class, &types, now)?,
+            JourneyType::Return | JourneyType::OpenReturn => {
+                fares.quote_return(trip, return_trip, class, &types, now)?
+            }
+        };
+        let ancillaries = self
+            .ancillaries
+            .iter()
+            .map(|ancillary| fares.quote_ancillary(trip, &self.journey_type, ancillary))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(PriceBreakdown {
+            transport,
+            ancillaries,
+            discount: Money::new(0, transport.currency),
+        })
+    }
+
+    /// Turn the collected details into a [`Booking`], provided they're
+    /// complete and the selected trips are still in the [`Timetable`]. The
+    /// seats held in `inventory` are sold, or any seats that are left if none
+    /// were held, along with seats on the trip back for a return, and any
+    /// wheelchair spaces and room for add-ons both ways. The customer is
+    /// charged the price quoted by `fares` for all passengers and add-ons,
+    /// less the discount of any voucher redeemed with `vouchers`, through
+    /// `payments`. If anything fails, the seats, the spaces, the add-ons and
+    /// the voucher go back to how they were.
+    pub fn book(
+        self,
+        timetable: &Timetable,
+        inventory: &Inventory,
+        fares: &FareEngine,
+        vouchers: &Vouchers,
+        payments: &dyn PaymentGateway,
+    ) -> Result<Booking> {
+        let now = Utc::now();
+        let (trip, return_trip) = self.trips(timetable)?;
+        let class = self
+            .class
+            .clone()
+            .ok_or(Error::BadRequest("Set class first"))?;
+        let mut breakdown = self.breakdown(fares, &trip, return_trip.as_ref(), &class, now)?;
+        let price = breakdown.subtotal()?;
+        let contact = Contact {
+            name: self.name.ok_or(Error::BadRequest("Set name first"))?,
+            email: self.email.ok_or(Error::BadRequest("Set email first"))?,
+            phone_number: self
+                .phone_number
+                .ok_or(Error::BadRequest("Set phone_number first"))?,
+        };
+        let passengers = self
+            .passengers
+            .unwrap_or_else(|| Passengers::adult(contact.name.clone()));
+        passengers.check_ages(trip.departure.date_naive())?;
+        // Cards have to be valid for the whole journey
+        let last_departure = return_trip.as_ref().unwrap_or(&trip).departure;
+        passengers.check_discount_cards(last_departure.date_naive())?;
+        self.ancillaries.check_passengers(passengers.seats())?;
+        let trips: Vec<&Trip> = std::iter::once(&trip).chain(&return_trip).collect();
+        for trip in &trips {
+            self.ancillaries.check_carried(trip)?;
+        }
+        if trips.iter().any(|trip| trip.crosses_border_control()) {
+            passengers.check_travel_documents(last_departure.date_naive())?;
+        }
+        let payment_info = self
+            .payment_info
+            .ok_or(Error::BadRequest("Provide payment info first"))?;
+
+        let voucher = match self.voucher {
+            Some(code) => {
+                let voucher = vouchers.redeem(&code, &trips, &class, &contact.email, now)?;
+                let discount = voucher.discount(price, &fares.exchange_rates);
+                let discount =
+                    discount.inspect_err(|_| vouchers.unredeem(&code, &contact.email))?;
+                Some(AppliedVoucher { code, discount })
+            }
+            None => None,
+        };
+        let undo_voucher = || {
+            if let Some(voucher) = &voucher {
+                vouchers.unredeem(&voucher.code, &contact.email);
+            }
+        };
+        if let Some(voucher) = &voucher {
+            breakdown.discount = voucher.discount;
+        }
+        let price = breakdown.total().inspect_err(|_| undo_voucher())?;
+
+        let wheelchairs = passengers.wheelchair_spaces();
+        let release_extras = |trips: &[&Trip]| {
+            for trip in trips {
+                inventory.release_ancillaries(&trip.id, &self.ancillaries);
+                inventory.release_wheelchair_spaces(&trip.id, &class, wheelchairs);
+            }
+        };
+        for (i, trip) in trips.iter().enumerate() {
+            inventory
+                .reserve_wheelchair_spaces(&trip.id, &class, wheelchairs)
+                .and_then(|_| {
+                    inventory
+                        .reserve_ancillaries(&trip.id, &self.ancillaries)
+                        .inspect_err(|_| {
+                            inventory.release_wheelchair_spaces(&trip.id, &class, wheelchairs)
+                        })
+                })
+                .inspect_err(|_| {
+                    undo_voucher();
+                    release_extras(&trips[..i]);
+                })?;
+        }
+        let undo_extras = || {
+            undo_voucher();
+            release_extras(&trips);
+        };
+
+        let count = passengers.seats();
+        if let Some(return_trip) = &return_trip {
+            inventory
+                .reserve(&return_trip.id, &class, count)
+                .inspect_err(|_| undo_extras())?;
+        }
+        let release_return = || {
+            undo_extras();
+            if let Some(return_trip) = &return_trip {
+                inventory.release(&return_trip.id, &class, count, None);
+            }
+        };
+        let seat = match &self.hold {
+            Some(hold) => inventory.convert_hold(hold, now),
+            None => inventory.reserve(&trip.id, &class, count).map(|_| None),
+        }
+        .inspect_err(|_| release_return())?;
+        let payment = payments.charge(&payment_info, price).inspect_err(|_| {
+            release_return();
+            match self.hold {
+                Some(hold) => inventory.revert_sale(hold, &trip.id, &class, count, seat, now),
+                None => inventory.release(&trip.id, &class, count, None),
+            }
+        })?;
+
         println!("🚂 Trip booked! Choo choo!");
-        Ok(())
+        Ok(Booking {
+            reference: BookingReference::generate(),
+            journey_type: self.journey_type,
+            trip,
+            return_trip,
+            contact,
+            billing_address: self.billing_address,
+            passengers: passengers.to_vec(),
+            ancillaries: self.ancillaries,
+            price,
+            breakdown: Some(breakdown),
+            voucher,
+            class,
+            seat,
+            status: BookingStatus::Confirmed,
+            pending_refund: None,
+            history: vec![StatusChange {
+                status: BookingStatus::Confirmed,
+                at: now,
+            }],
+            charges: vec![Charge {
+                id: payment,
+                amount: price,
+                at: now,
+            }],
+            refunds: vec![],
+            changes: vec![],
+            created_at: now,
+            updated_at: now,
+        })
     }
 }
@@ -0,0 +1,136 @@
+use chrono::NaiveDate;
+
+use super::customer_details::Name;
+
+/// A nationality, as an ISO 3166-1 alpha-2 country code like `NL`.
+/// Codes are always stored in upper case.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Nationality(String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Error parsing nationality, expected a two-letter country code: {0}")]
+pub struct ParseNationalityError(String);
+
+impl TryFrom<String> for Nationality {
+    type Error = ParseNationalityError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let code = s.trim().to_ascii_uppercase();
+        if code.len() != 2 || !code.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err(ParseNationalityError(s));
+        }
+        Ok(Self(code))
+    }
+}
+
+impl From<Nationality> for String {
+    fn from(Nationality(code): Nationality) -> Self {
+        code
+    }
+}
+
+/// The number of a passport or identity card, in upper case
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DocumentNumber(String);
+
+impl DocumentNumber {
+    const MIN_LEN: usize = 5;
+    const MAX_LEN: usize = 20;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Error parsing document number: {0}")]
+pub struct ParseDocumentNumberError(String);
+
+impl TryFrom<String> for DocumentNumber {
+    type Error = ParseDocumentNumberError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let number = s.trim().to_ascii_uppercase();
+        if !(Self::MIN_LEN..=Self::MAX_LEN).contains(&number.len())
+            || !number.bytes().all(|b| b.is_ascii_alphanumeric())
+        {
+            return Err(ParseDocumentNumberError(s));
+        }
+        Ok(Self(number))
+    }
+}
+
+impl From<DocumentNumber> for String {
+    fn from(DocumentNumber(number): DocumentNumber) -> Self {
+        number
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentType {
+    Passport,
+    IdentityCard,
+}
+
+/// The passport or identity card a passenger travels on
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TravelDocument {
+    pub document_type: DocumentType,
+    pub number: DocumentNumber,
+    pub expires_on: NaiveDate,
+}
+
+/// The details a passenger still has to provide to travel
+/// on a route with border control
+#[derive(Debug)]
+pub struct MissingDetails {
+    pub passenger: Name,
+    pub fields: Vec<&'static str>,
+}
+
+impl std::fmt::Display for MissingDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.passenger, self.fields.join(", "))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TravelDocumentError {
+    #[error("This route crosses border control, so travel document details are required. Missing: {}", list(.0))]
+    Missing(Vec<MissingDetails>),
+
+    #[error("The travel document of {0} expires before the day of travel")]
+    Expired(Name),
+}
+
+fn list(missing: &[MissingDetails]) -> String {
+    missing
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{DocumentNumber, Nationality};
+
+    #[test_case("nl" => Ok("NL".to_owned()); "lower case")]
+    #[test_case("NLD" => Err(()); "alpha-3")]
+    #[test_case("N1" => Err(()); "digit")]
+    fn test_parse_nationality(code: &str) -> Result<String, ()> {
+        Nationality::try_from(code.to_owned())
+            .map(String::from)
+            .map_err(|_| ())
+    }
+
+    #[test_case("nx12345a" => Ok("NX12345A".to_owned()); "lower case")]
+    #[test_case("1234" => Err(()); "too short")]
+    #[test_case("NX-12345" => Err(()); "punctuation")]
+    fn test_parse_document_number(number: &str) -> Result<String, ()> {
+        DocumentNumber::try_from(number.to_owned())
+            .map(String::from)
+            .map_err(|_| ())
+    }
+}
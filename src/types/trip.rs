@@ -1,11 +1,19 @@
 use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 
-use super::{departure_or_arrival::DepartureOrArrival, location::Location};
+use super::{
+    ancillary::AncillaryType, departure_or_arrival::DepartureOrArrival, location::Location,
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
 pub struct TripId(Uuid);
 
+impl From<TripId> for Uuid {
+    fn from(id: TripId) -> Self {
+        id.0
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub struct Trip {
     pub id: TripId,
@@ -13,9 +21,18 @@ pub struct Trip {
     pub destination: Location,
     pub departure: DateTime<Utc>,
     pub arrival: DateTime<Utc>,
+    /// The add-ons that can be taken on this trip
+    #[serde(default)]
+    pub carries: Vec<AncillaryType>,
 }
 
 impl Trip {
+    /// Whether passengers pass border control, where their names are
+    /// checked against their travel documents
+    pub fn crosses_border_control(&self) -> bool {
+        self.origin.in_schengen_area() != self.destination.in_schengen_area()
+    }
+
     pub fn list_matching(
         origin: Location,
         destiniation: Location,
@@ -28,17 +45,30 @@ impl Trip {
             DepartureOrArrival::Arrival(t) => DateTime::<Utc>::from(t) + Duration::hours(-2),
         };
 
+        // Only every other train has room for bicycles, and pets
+        // can't be taken through the Channel Tunnel
+        let pets = origin.in_schengen_area() && destiniation.in_schengen_area();
+
         std::iter::repeat_with(|| Trip {
             id: TripId(Uuid::new_v4()),
             origin: origin.clone(),
             destination: destiniation.clone(),
             departure,
             arrival: departure + Duration::hours(2),
+            carries: vec![],
         })
         .enumerate()
         .map(|(i, trip)| Trip {
             departure: trip.departure + Duration::hours(i as i64),
             arrival: trip.arrival + Duration::hours(i as i64),
+            carries: AncillaryType::ALL
+                .into_iter()
+                .filter(|t| match t {
+                    AncillaryType::Bicycle => i % 2 == 0,
+                    AncillaryType::ExtraLuggage => true,
+                    AncillaryType::Pet => pets,
+                })
+                .collect(),
             ..trip
         })
         .filter(|t| Utc::now() < t.departure)
@@ -0,0 +1,237 @@
+use chrono::{DateTime, Utc};
+
+use super::{
+    class::Class,
+    location::Location,
+    money::{ExchangeRates, Money, MoneyError},
+    trip::Trip,
+};
+
+/// A promotion code as customers type it in. Codes are case-insensitive,
+/// and always stored in upper case.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct VoucherCode(String);
+
+impl VoucherCode {
+    const MIN_LEN: usize = 4;
+    const MAX_LEN: usize = 20;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Error parsing voucher code: {0}")]
+pub struct ParseVoucherCodeError(String);
+
+impl TryFrom<String> for VoucherCode {
+    type Error = ParseVoucherCodeError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let code = s.trim().to_ascii_uppercase();
+        if !(Self::MIN_LEN..=Self::MAX_LEN).contains(&code.len())
+            || !code.bytes().all(|b| b.is_ascii_alphanumeric())
+        {
+            return Err(ParseVoucherCodeError(s));
+        }
+        Ok(Self(code))
+    }
+}
+
+impl From<VoucherCode> for String {
+    fn from(VoucherCode(code): VoucherCode) -> Self {
+        code
+    }
+}
+
+impl std::fmt::Display for VoucherCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// What a voucher takes off the price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Discount {
+    Percentage(u8),
+    /// Converted to the currency of the price if need be
+    Fixed(Money),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VoucherError {
+    #[error("Unknown voucher code {0}")]
+    Unknown(VoucherCode),
+
+    #[error("This voucher isn't valid yet")]
+    NotYetValid,
+
+    #[error("This voucher has expired")]
+    Expired,
+
+    #[error("This voucher has been used up")]
+    UsedUp,
+
+    #[error("You have used this voucher as often as you can already")]
+    UsedUpByCustomer,
+
+    #[error("This voucher isn't valid from {0} to {1}")]
+    Route(Location, Location),
+
+    #[error("This voucher isn't valid in {0:?} class")]
+    Class(Class),
+
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+}
+
+/// A promotion. Routes are valid in either direction, and a voucher
+/// without routes or classes is valid on any route or in any class.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Voucher {
+    pub code: VoucherCode,
+    pub discount: Discount,
+    pub valid_from: DateTime<Utc>,
+    pub valid_until: DateTime<Utc>,
+    /// How often the voucher can be used in total
+    pub max_uses: Option<u32>,
+    /// How often the voucher can be used by each customer,
+    /// as told apart by their email address
+    pub max_uses_per_customer: Option<u32>,
+    pub routes: Vec<(Location, Location)>,
+    pub classes: Vec<Class>,
+}
+
+impl Voucher {
+    /// Check whether the voucher can be used for `trip` in `class` at `now`.
+    /// Usage limits are left to [`crate::vouchers::Vouchers`].
+    pub fn check(
+        &self,
+        trip: &Trip,
+        class: &Class,
+        now: DateTime<Utc>,
+    ) -> Result<(), VoucherError> {
+        if now < self.valid_from {
+            return Err(VoucherError::NotYetValid);
+        }
+        if now >= self.valid_until {
+            return Err(VoucherError::Expired);
+        }
+        let connects = |(a, b): &(Location, Location)| {
+            (*a == trip.origin && *b == trip.destination)
+                || (*a == trip.destination && *b == trip.origin)
+        };
+        if !self.routes.is_empty() && !self.routes.iter().any(connects) {
+            return Err(VoucherError::Route(
+                trip.origin.clone(),
+                trip.destination.clone(),
+            ));
+        }
+        if !self.classes.is_empty() && !self.classes.contains(class) {
+            return Err(VoucherError::Class(class.clone()));
+        }
+        Ok(())
+    }
+
+    /// The amount taken off `price`, which is never more than the price
+    pub fn discount(&self, price: Money, rates: &ExchangeRates) -> Result<Money, VoucherError> {
+        let discount = match self.discount {
+            Discount::Percentage(percentage) => price.percentage(i64::from(percentage.min(100)))?,
+            Discount::Fixed(amount) => rates.convert(amount, price.currency)?,
+        };
+        Ok(Money::new(
+            discount.amount.clamp(0, price.amount.max(0)),
+            price.currency,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use test_case::test_case;
+
+    use super::{Discount, Voucher, VoucherCode, VoucherError};
+    use crate::types::{
+        class::Class,
+        money::{Currency, ExchangeRates, Money},
+        trip::Trip,
+    };
+
+    fn voucher(discount: Discount) -> Voucher {
+        Voucher {
+            code: "SPRING".to_owned().try_into().unwrap(),
+            discount,
+            valid_from: Utc::now() - Duration::days(1),
+            valid_until: Utc::now() + Duration::days(1),
+            max_uses: None,
+            max_uses_per_customer: None,
+            routes: vec![(
+                "Paris Nord".to_owned().try_into().unwrap(),
+                "Amsterdam Centraal".to_owned().try_into().unwrap(),
+            )],
+            classes: vec![Class::Second],
+        }
+    }
+
+    fn trip(origin: &str, destination: &str) -> Trip {
+        let departure = Utc::now() + Duration::days(2);
+        let trip = serde_json::json!({
+            "id": uuid::Uuid::new_v4(),
+            "origin": origin,
+            "destination": destination,
+            "departure": departure,
+            "arrival": departure + Duration::hours(3),
+        });
+        serde_json::from_value(trip).unwrap()
+    }
+
+    #[test_case("spring10" => Ok("SPRING10".to_owned()); "lower case")]
+    #[test_case(" SPRING10 " => Ok("SPRING10".to_owned()); "surrounding whitespace")]
+    #[test_case("SPR" => Err(()); "too short")]
+    #[test_case("SPRING-10" => Err(()); "punctuation")]
+    fn test_parse_voucher_code(code: &str) -> Result<String, ()> {
+        VoucherCode::try_from(code.to_owned())
+            .map(String::from)
+            .map_err(|_| ())
+    }
+
+    #[test]
+    fn test_check() {
+        let voucher = voucher(Discount::Percentage(10));
+        let now = Utc::now();
+        let trip = trip("Amsterdam Centraal", "Paris Nord");
+
+        voucher.check(&trip, &Class::Second, now).unwrap();
+        assert!(matches!(
+            voucher.check(&trip, &Class::First, now),
+            Err(VoucherError::Class(Class::First))
+        ));
+        assert!(matches!(
+            voucher.check(
+                &self::trip("Amsterdam Centraal", "Berlin Hbf"),
+                &Class::Second,
+                now
+            ),
+            Err(VoucherError::Route(..))
+        ));
+        assert!(matches!(
+            voucher.check(&trip, &Class::Second, now - Duration::days(2)),
+            Err(VoucherError::NotYetValid)
+        ));
+        assert!(matches!(
+            voucher.check(&trip, &Class::Second, now + Duration::days(2)),
+            Err(VoucherError::Expired)
+        ));
+    }
+
+    #[test_case(Discount::Percentage(10), Money::new(4900, Currency::Eur) => 490; "percentage")]
+    #[test_case(Discount::Fixed(Money::new(1000, Currency::Eur)), Money::new(4900, Currency::Eur) => 1000; "fixed")]
+    #[test_case(Discount::Fixed(Money::new(1000, Currency::Eur)), Money::new(4900, Currency::Gbp) => 850; "converted")]
+    #[test_case(Discount::Fixed(Money::new(9900, Currency::Eur)), Money::new(4900, Currency::Eur) => 4900; "no more than the price")]
+    fn test_discount(discount: Discount, price: Money) -> i64 {
+        voucher(discount)
+            .discount(price, &ExchangeRates::default())
+            .unwrap()
+            .amount
+    }
+}
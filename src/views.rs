@@ -0,0 +1,421 @@
+use chrono::{DateTime, Utc};
+use takeoff_tickets::{ScanOutcome, SignedTicket};
+
+use crate::types::{
+    address::BillingAddress,
+    ancillary::{Ancillaries, AncillaryType},
+    assistance::Assistance,
+    booking::{
+        AppliedVoucher, Booking, BookingChange, BookingReference, BookingStatus, Contact, Refund,
+        StatusChange,
+    },
+    class::Class,
+    customer_details::{Email, Name, PhoneNumber},
+    departure_or_arrival::DepartureOrArrival,
+    journey::JourneyType,
+    location::Location,
+    money::Money,
+    passenger::{Passenger, Passengers},
+    seat::{SeatAttribute, SeatId},
+    ticket::TicketPayload,
+    ticket_machine::TicketMachine,
+    trip::{Trip, TripId},
+    voucher::VoucherCode,
+};
+
+/// The representation of [`TicketMachine`] that is sent to clients. Anything
+/// sensitive is redacted here, which makes this type unsuitable for storing
+/// state: use [`crate::storage::StoredTicketMachine`] for that. The revision
+/// of the state is left out, as it's sent in the `ETag` header instead.
+#[derive(Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TicketMachineView {
+    pub origin: Option<Location>,
+    pub destination: Option<Location>,
+    pub time: Option<DepartureOrArrival>,
+    pub trip: Option<TripId>,
+    pub journey_type: JourneyType,
+    pub return_time: Option<DepartureOrArrival>,
+    pub return_trip: Option<TripId>,
+    pub class: Option<Class>,
+    pub seat: Option<SeatId>,
+    pub passengers: Option<Passengers>,
+    pub ancillaries: Ancillaries,
+    pub name: Option<Name>,
+    pub email: Option<Email>,
+    pub phone_number: Option<PhoneNumber>,
+    pub billing_address: Option<BillingAddress>,
+    pub voucher: Option<VoucherCode>,
+    /// Only ever contains the redacted form of the payment info
+    pub payment_info: Option<String>,
+}
+
+impl From<TicketMachine> for TicketMachineView {
+    fn from(
+        TicketMachine {
+            revision: _,
+            origin,
+            destination,
+            time,
+            trip,
+            journey_type,
+            return_time,
+            return_trip,
+            class,
+            hold: _,
+            seat,
+            passengers,
+            ancillaries,
+            name,
+            email,
+            phone_number,
+            billing_address,
+            voucher,
+            payment_info,
+        }: TicketMachine,
+    ) -> Self {
+        Self {
+            origin,
+            destination,
+            time,
+            trip,
+            journey_type,
+            return_time,
+            return_trip,
+            class,
+            seat,
+            passengers,
+            ancillaries,
+            name,
+            email,
+            phone_number,
+            billing_address,
+            voucher,
+            payment_info: payment_info.map(|p| p.to_string()),
+        }
+    }
+}
+
+/// The representation of a [`Booking`] that is sent to clients. The
+/// charges and the price breakdown are for internal use only, and left out.
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct BookingView {
+    pub reference: BookingReference,
+    pub journey_type: JourneyType,
+    pub trip: Trip,
+    pub return_trip: Option<Trip>,
+    pub contact: Contact,
+    pub billing_address: Option<BillingAddress>,
+    pub passengers: Vec<Passenger>,
+    pub ancillaries: Ancillaries,
+    /// Whether any passenger has to show their discount card on board
+    pub discount_card_required: bool,
+    pub class: Class,
+    pub seat: Option<SeatId>,
+    pub price: Money,
+    pub voucher: Option<AppliedVoucher>,
+    pub status: BookingStatus,
+    pub pending_refund: Option<Money>,
+    pub history: Vec<StatusChange>,
+    pub refunds: Vec<Refund>,
+    pub changes: Vec<BookingChange>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Booking> for BookingView {
+    fn from(booking: Booking) -> Self {
+        let discount_card_required = booking.discount_card_required();
+        let Booking {
+            reference,
+            journey_type,
+            trip,
+            return_trip,
+            contact,
+            billing_address,
+            passengers,
+            ancillaries,
+            class,
+            seat,
+            price,
+            breakdown: _,
+            voucher,
+            status,
+            pending_refund,
+            history,
+            charges: _,
+            refunds,
+            changes,
+            created_at,
+            updated_at,
+        } = booking;
+        Self {
+            reference,
+            journey_type,
+            trip,
+            return_trip,
+            contact,
+            billing_address,
+            passengers,
+            ancillaries,
+            discount_card_required,
+            class,
+            seat,
+            price,
+            voucher,
+            status,
+            pending_refund,
+            history,
+            refunds,
+            changes,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// The state after a voucher was entered, along with what it takes off
+/// the price for the trips, class and passengers selected so far
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct VoucherView {
+    #[serde(flatten)]
+    pub state: TicketMachineView,
+    pub price: Money,
+    pub discount: Money,
+    pub discounted_price: Money,
+}
+
+/// An add-on that can be taken on all of the selected trips, with the
+/// room that's left for it and its price per unit, both ways for a return
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct AncillaryOptionView {
+    #[serde(rename = "type")]
+    pub ancillary_type: AncillaryType,
+    pub available: u32,
+    pub price: Money,
+    /// The price in the currency and format the customer asked for
+    pub display_price: String,
+}
+
+/// A [`Trip`] as listed to clients, along with the price and the
+/// number of seats that are still for sale in each class
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TripView {
+    #[serde(flatten)]
+    pub trip: Trip,
+    pub availability: Vec<Availability>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Availability {
+    pub class: Class,
+    pub seats: u32,
+    pub price: Money,
+    /// The price in the currency and format the customer asked for
+    pub display_price: String,
+}
+
+/// A coach in the seat map of a trip, showing which seats are still free
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct CoachView {
+    pub number: u8,
+    pub class: Class,
+    pub seats: Vec<SeatView>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct SeatView {
+    pub id: SeatId,
+    pub attributes: Vec<SeatAttribute>,
+    pub available: bool,
+}
+
+/// A passenger who asked for assistance, as exported for the staff at
+/// the stations and on board of a trip
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct AssistanceRequestView {
+    pub reference: BookingReference,
+    pub origin: Location,
+    pub destination: Location,
+    pub departure: DateTime<Utc>,
+    pub class: Class,
+    pub passenger: Name,
+    pub assistance: Assistance,
+    /// Of the lead contact on the booking
+    pub phone_number: PhoneNumber,
+}
+
+impl AssistanceRequestView {
+    /// The requests that were made on `booking` for `trip`
+    pub fn for_trip(booking: &Booking, trip: &Trip) -> Vec<Self> {
+        booking
+            .passengers
+            .iter()
+            .filter_map(|p| Some((p, p.assistance.clone()?)))
+            .map(|(passenger, assistance)| Self {
+                reference: booking.reference.clone(),
+                origin: trip.origin.clone(),
+                destination: trip.destination.clone(),
+                departure: trip.departure,
+                class: booking.class.clone(),
+                passenger: passenger.name.clone(),
+                assistance,
+                phone_number: booking.contact.phone_number.clone(),
+            })
+            .collect()
+    }
+
+    /// Render `requests` as CSV, with a header row. Lists of
+    /// values are separated by semicolons. Values that a spreadsheet
+    /// would take for a formula are prefixed with an apostrophe.
+    pub fn to_csv(requests: &[Self]) -> String {
+        // Most values are enums, written the way they're serialized
+        fn value(value: impl serde::Serialize) -> String {
+            match serde_json::to_value(value) {
+                Ok(serde_json::Value::String(s)) => s,
+                Ok(serde_json::Value::Null) | Err(_) => String::new(),
+                Ok(value) => value.to_string(),
+            }
+        }
+        fn field(mut value: String) -> String {
+            if value.starts_with(['=', '+', '-', '@', '\t']) {
+                value.insert(0, '\'');
+            }
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value
+            }
+        }
+
+        let mut csv = String::from(concat!(
+            "reference,origin,destination,departure,class,passenger,",
+            "assistance,mobility_aid,companion,phone_number\n"
+        ));
+        for request in requests {
+            let assistance = &request.assistance;
+            let row = [
+                request.reference.to_string(),
+                request.origin.to_string(),
+                request.destination.to_string(),
+                request.departure.to_rfc3339(),
+                value(&request.class),
+                request.passenger.to_string(),
+                assistance
+                    .types
+                    .iter()
+                    .map(value)
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                value(assistance.mobility_aid),
+                assistance
+                    .companion
+                    .as_ref()
+                    .map_or_else(String::new, ToString::to_string),
+                value(&request.phone_number),
+            ];
+            let row: Vec<_> = row.into_iter().map(field).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// A trip and class a [`Booking`] can be changed to, and what the
+/// change would cost. A negative fare difference is refunded.
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ChangeOptionView {
+    pub trip: Trip,
+    pub class: Class,
+    pub price: Money,
+    pub fare_difference: Money,
+    /// The fare difference in the currency and format the customer asked for
+    pub display_fare_difference: String,
+}
+
+/// A ticket of a booking, along with the signed form that's put in its
+/// QR code
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TicketView {
+    pub ticket: TicketPayload,
+    pub signed: SignedTicket,
+}
+
+/// What the scan of a ticket found, along with what the ticket says
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ScanView {
+    pub outcome: ScanOutcome,
+    pub ticket: TicketPayload,
+}
+
+/// The public key that ticket signatures are verified with, as base64
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TicketKeyView {
+    pub public_key: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AssistanceRequestView, TicketMachineView};
+    use crate::types::ticket_machine::TicketMachine;
+
+    #[test]
+    fn test_view_redacts_payment_info() {
+        let payment_info = "1234 5678 9012 3456".to_owned().into();
+        let view = TicketMachineView::from(TicketMachine {
+            payment_info: Some(payment_info),
+            ..Default::default()
+        });
+
+        let json = serde_json::to_value(&view).unwrap();
+        assert_eq!(json["payment_info"], "<SECRET>");
+    }
+
+    #[test]
+    fn test_assistance_csv() {
+        let request: AssistanceRequestView = serde_json::from_value(serde_json::json!({
+            "reference": "ABC234",
+            "origin": "Amsterdam Centraal",
+            "destination": "Paris Nord",
+            "departure": "2030-01-02T12:00:00Z",
+            "class": "second",
+            "passenger": "Henk \"de Vries\"",
+            "assistance": {
+                "types": ["boarding_assistance", "visual_impairment"],
+            },
+            "phone_number": "123-456",
+        }))
+        .unwrap();
+
+        let csv = AssistanceRequestView::to_csv(&[request]);
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            concat!(
+                "ABC234,Amsterdam Centraal,Paris Nord,2030-01-02T12:00:00+00:00,second,",
+                r#""Henk ""de Vries""",boarding_assistance;visual_impairment,,,123-456"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_assistance_csv_formulas() {
+        let request: AssistanceRequestView = serde_json::from_value(serde_json::json!({
+            "reference": "ABC234",
+            "origin": "Amsterdam Centraal",
+            "destination": "Paris Nord",
+            "departure": "2030-01-02T12:00:00Z",
+            "class": "second",
+            "passenger": "=HYPERLINK(\"http://example.com\")",
+            "assistance": {
+                "types": ["boarding_assistance"],
+                "companion": "@Ingrid\r",
+            },
+            "phone_number": "123-456",
+        }))
+        .unwrap();
+
+        let csv = AssistanceRequestView::to_csv(&[request]);
+        assert!(csv.contains(r#","'=HYPERLINK(""http://example.com"")","#));
+        assert!(csv.contains(",\"'@Ingrid\r\","));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::types::{
+    class::Class,
+    customer_details::Email,
+    trip::Trip,
+    voucher::{Discount, Voucher, VoucherCode, VoucherError},
+};
+
+#[derive(Debug)]
+struct Issued {
+    voucher: Voucher,
+    uses: u32,
+    /// Keyed by email address, in lower case
+    uses_by_customer: HashMap<String, u32>,
+}
+
+impl Issued {
+    fn check_uses(&self, email: Option<&Email>) -> Result<(), VoucherError> {
+        if self.voucher.max_uses.is_some_and(|max| self.uses >= max) {
+            return Err(VoucherError::UsedUp);
+        }
+        let used = email.map_or(0, |email| {
+            self.uses_by_customer
+                .get(&customer_key(email))
+                .copied()
+                .unwrap_or(0)
+        });
+        if self
+            .voucher
+            .max_uses_per_customer
+            .is_some_and(|max| used >= max)
+        {
+            return Err(VoucherError::UsedUpByCustomer);
+        }
+        Ok(())
+    }
+}
+
+fn customer_key(email: &Email) -> String {
+    String::from(email.clone()).to_lowercase()
+}
+
+/// Keeps track of the vouchers that were issued, and how often they were
+/// used. Like [`crate::inventory::Inventory`], usage is checked and counted
+/// under a single lock, so a voucher can't be used more often than allowed.
+#[derive(Debug)]
+pub struct Vouchers {
+    issued: Mutex<HashMap<VoucherCode, Issued>>,
+}
+
+impl Vouchers {
+    pub fn new(vouchers: impl IntoIterator<Item = Voucher>) -> Self {
+        let this = Self {
+            issued: Mutex::default(),
+        };
+        for voucher in vouchers {
+            this.issue(voucher);
+        }
+        this
+    }
+
+    /// Make `voucher` available for use, replacing any voucher
+    /// with the same code. Its usage starts over.
+    pub fn issue(&self, voucher: Voucher) {
+        self.issued.lock().unwrap().insert(
+            voucher.code.clone(),
+            Issued {
+                voucher,
+                uses: 0,
+                uses_by_customer: HashMap::new(),
+            },
+        );
+    }
+
+    /// Check whether `code` can be used for `trips`, the ways of a journey,
+    /// in `class` at `now`. The limit per customer is only checked if their
+    /// `email` is known.
+    pub fn check(
+        &self,
+        code: &VoucherCode,
+        trips: &[&Trip],
+        class: &Class,
+        email: Option<&Email>,
+        now: DateTime<Utc>,
+    ) -> Result<Voucher, VoucherError> {
+        let issued = self.issued.lock().unwrap();
+        let issued = issued
+            .get(code)
+            .ok_or_else(|| VoucherError::Unknown(code.clone()))?;
+        for trip in trips {
+            issued.voucher.check(trip, class, now)?;
+        }
+        issued.check_uses(email)?;
+        Ok(issued.voucher.clone())
+    }
+
+    /// Use `code` for a booking by the customer with `email`, provided
+    /// it's still valid as per [`Vouchers::check`]
+    pub fn redeem(
+        &self,
+        code: &VoucherCode,
+        trips: &[&Trip],
+        class: &Class,
+        email: &Email,
+        now: DateTime<Utc>,
+    ) -> Result<Voucher, VoucherError> {
+        let mut issued = self.issued.lock().unwrap();
+        let issued = issued
+            .get_mut(code)
+            .ok_or_else(|| VoucherError::Unknown(code.clone()))?;
+        for trip in trips {
+            issued.voucher.check(trip, class, now)?;
+        }
+        issued.check_uses(Some(email))?;
+
+        issued.uses += 1;
+        *issued
+            .uses_by_customer
+            .entry(customer_key(email))
+            .or_default() += 1;
+        Ok(issued.voucher.clone())
+    }
+
+    /// Count a use of `code` by the customer with `email`, for bookings that
+    /// were made before a restart. Uses of vouchers that are no longer
+    /// issued are ignored.
+    pub fn restore(&self, code: &VoucherCode, email: &Email) {
+        let mut issued = self.issued.lock().unwrap();
+        if let Some(issued) = issued.get_mut(code) {
+            issued.uses += 1;
+            *issued
+                .uses_by_customer
+                .entry(customer_key(email))
+                .or_default() += 1;
+        }
+    }
+
+    /// Undo [`Vouchers::redeem`], for when the booking fell through
+    pub fn unredeem(&self, code: &VoucherCode, email: &Email) {
+        let mut issued = self.issued.lock().unwrap();
+        if let Some(issued) = issued.get_mut(code) {
+            issued.uses = issued.uses.saturating_sub(1);
+            if let Some(uses) = issued.uses_by_customer.get_mut(&customer_key(email)) {
+                *uses = uses.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl Default for Vouchers {
+    /// A welcome discount of 10%, once per customer
+    fn default() -> Self {
+        Self::new([Voucher {
+            code: "WELCOME10".to_owned().try_into().unwrap(),
+            discount: Discount::Percentage(10),
+            valid_from: DateTime::UNIX_EPOCH,
+            valid_until: Utc::now() + Duration::days(365),
+            max_uses: None,
+            max_uses_per_customer: Some(1),
+            routes: vec![],
+            classes: vec![],
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use super::Vouchers;
+    use crate::types::{
+        class::Class,
+        customer_details::Email,
+        trip::Trip,
+        voucher::{Discount, Voucher, VoucherError},
+    };
+
+    fn trip() -> Trip {
+        let departure = Utc::now() + Duration::days(2);
+        let trip = serde_json::json!({
+            "id": uuid::Uuid::new_v4(),
+            "origin": "Amsterdam Centraal",
+            "destination": "Paris Nord",
+            "departure": departure,
+            "arrival": departure + Duration::hours(3),
+        });
+        serde_json::from_value(trip).unwrap()
+    }
+
+    fn email(email: &str) -> Email {
+        email.to_owned().try_into().unwrap()
+    }
+
+    fn voucher(max_uses: Option<u32>, max_uses_per_customer: Option<u32>) -> Voucher {
+        Voucher {
+            code: "SPRING".to_owned().try_into().unwrap(),
+            discount: Discount::Percentage(10),
+            valid_from: Utc::now() - Duration::days(1),
+            valid_until: Utc::now() + Duration::days(1),
+            max_uses,
+            max_uses_per_customer,
+            routes: vec![],
+            classes: vec![],
+        }
+    }
+
+    fn vouchers(max_uses: Option<u32>, max_uses_per_customer: Option<u32>) -> Vouchers {
+        Vouchers::new([voucher(max_uses, max_uses_per_customer)])
+    }
+
+    #[test]
+    fn test_usage_per_customer() {
+        let vouchers = vouchers(None, Some(1));
+        let code = "spring".to_owned().try_into().unwrap();
+        let (trip, now) = (trip(), Utc::now());
+
+        vouchers
+            .redeem(
+                &code,
+                &[&trip],
+                &Class::Second,
+                &email("henk@example.com"),
+                now,
+            )
+            .unwrap();
+        assert!(matches!(
+            vouchers.check(
+                &code,
+                &[&trip],
+                &Class::Second,
+                Some(&email("HENK@example.com")),
+                now
+            ),
+            Err(VoucherError::UsedUpByCustomer)
+        ));
+        // Others can still use it, and so can Henk if his booking fell through
+        vouchers
+            .check(
+                &code,
+                &[&trip],
+                &Class::Second,
+                Some(&email("ingrid@example.com")),
+                now,
+            )
+            .unwrap();
+        vouchers.unredeem(&code, &email("henk@example.com"));
+        vouchers
+            .redeem(
+                &code,
+                &[&trip],
+                &Class::Second,
+                &email("henk@example.com"),
+                now,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_route_of_every_trip() {
+        let vouchers = Vouchers::new([Voucher {
+            routes: vec![(
+                "Amsterdam Centraal".to_owned().try_into().unwrap(),
+                "Paris Nord".to_owned().try_into().unwrap(),
+            )],
+            ..voucher(None, None)
+        }]);
+        let code = "SPRING".to_owned().try_into().unwrap();
+        let (trip, now) = (trip(), Utc::now());
+        let mut return_trip = trip.clone();
+        return_trip.origin = "Berlin Hbf".to_owned().try_into().unwrap();
+
+        vouchers
+            .check(&code, &[&trip], &Class::Second, None, now)
+            .unwrap();
+        assert!(matches!(
+            vouchers.check(&code, &[&trip, &return_trip], &Class::Second, None, now),
+            Err(VoucherError::Route(..))
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_redemptions() {
+        let vouchers = Arc::new(vouchers(Some(10), None));
+        let trip = trip();
+
+        let handles: Vec<_> = (0..50)
+            .map(|n| {
+                let (vouchers, trip) = (vouchers.clone(), trip.clone());
+                std::thread::spawn(move || {
+                    let code = "SPRING".to_owned().try_into().unwrap();
+                    let email = email(&format!("customer{n}@example.com"));
+                    vouchers
+                        .redeem(&code, &[&trip], &Class::Second, &email, Utc::now())
+                        .is_ok()
+                })
+            })
+            .collect();
+        let redeemed = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&ok| ok)
+            .count();
+
+        assert_eq!(redeemed, 10);
+    }
+}
@@ -1,19 +1,53 @@
 use std::{borrow::Cow, sync::LazyLock};
 
 use axum::http::HeaderValue;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use reqwest::Body;
 use serde::Serialize;
 use serde_json::json;
-use takeoff::types::{
-    class::Class, departure_or_arrival::DepartureOrArrival, location::Location,
-    ticket_machine::TicketMachine, trip::Trip,
+use takeoff::{
+    types::{
+        ancillary::AncillaryType, booking::BookingStatus, class::Class,
+        departure_or_arrival::DepartureOrArrival, invoice::Invoice, journey::JourneyType,
+        location::Location, money::Currency,
+    },
+    views::{
+        AncillaryOptionView, AssistanceRequestView, BookingView, ChangeOptionView, CoachView,
+        ScanView, TicketKeyView, TicketMachineView, TicketView, TripView, VoucherView,
+    },
 };
+use takeoff_tickets::{ScanOutcome, Train, Verifier};
 use test_case::test_case;
 use url::Url;
 
 static BASE_URL: LazyLock<Url> = LazyLock::new(|| Url::parse("http://localhost:3000/").unwrap());
 
+/// The token the server under test accepts from operator staff
+fn operator_token() -> String {
+    std::env::var("TAKEOFF_OPERATOR_TOKEN")
+        .expect("Set TAKEOFF_OPERATOR_TOKEN to the operator token of the server")
+}
+
+/// A client that authenticates as a conductor, with the token the
+/// server under test accepts from them
+fn conductor_client() -> reqwest::Client {
+    let token = std::env::var("TAKEOFF_CONDUCTOR_TOKEN")
+        .expect("Set TAKEOFF_CONDUCTOR_TOKEN to the conductor token of the server");
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+    );
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
 fn http_client() -> reqwest::Client {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -73,6 +107,16 @@ fn json_bytes(s: impl Serialize) -> Cow<'static, [u8]> {
     serde_json::to_vec(&s).unwrap().into()
 }
 
+/// The path to `rest` of `booking`, proving that it's ours with the
+/// email address of the contact
+fn booking_path(booking: &BookingView, rest: &str) -> String {
+    format!(
+        "/bookings/{}{rest}?email={}",
+        booking.reference,
+        String::from(booking.contact.email.clone())
+    )
+}
+
 #[test_case(json_bytes("Amsterdam") => panics ""; "Non-existent station")]
 #[test_case(json_bytes("🚂-🛒-🛒-🛒") => panics ""; "Emojional roller coaster")]
 #[test_case([0xE0, 0x80, 0x80].as_slice().into() => panics "" ; "Non-UTF-8 sequence")]
@@ -81,7 +125,8 @@ fn json_bytes(s: impl Serialize) -> Cow<'static, [u8]> {
 #[tokio::test]
 async fn test_set_origin(origin: Cow<'static, [u8]>) {
     let origin = origin.to_vec();
-    let body: TicketMachine = send_post_request(&http_client(), "/origin", origin.clone()).await;
+    let body: TicketMachineView =
+        send_post_request(&http_client(), "/origin", origin.clone()).await;
 
     let origin: String = serde_json::from_slice(&origin).expect(
         "The request should have failed at this point as `origin` was not valid JSON anyway",
@@ -90,7 +135,7 @@ async fn test_set_origin(origin: Cow<'static, [u8]>) {
 
     assert_eq!(
         body,
-        TicketMachine {
+        TicketMachineView {
             origin: Some(origin),
             ..Default::default()
         }
@@ -102,7 +147,7 @@ async fn test_hiding_payment_details() {
     let client = http_client();
     let origin = json!("Amsterdam Centraal");
     // Set up the session
-    let _: TicketMachine =
+    let _: TicketMachineView =
         send_post_request(&client, "/origin", serde_json::to_vec(&origin).unwrap()).await;
 
     // Totally not _my_ credit card
@@ -112,16 +157,17 @@ async fn test_hiding_payment_details() {
         "exp": "12/34",
     })
     .to_string();
-    // Deserialize into a Value, so that we can skip any input validation on
-    // the test side.
-    let state: serde_json::Value = send_post_request(
-        &client,
-        "/book_trip",
-        serde_json::to_vec(&payment_info).unwrap(),
-    )
-    .await;
+    let res = client
+        .post(BASE_URL.join("/book_trip").unwrap())
+        .body(serde_json::to_vec(&payment_info).unwrap())
+        .send()
+        .await
+        .unwrap();
 
-    assert_eq!(state["payment_info"], "<SECRET>");
+    // The booking details are incomplete, but even so, the
+    // payment details should not be echoed back
+    assert!(res.status().is_client_error());
+    assert!(!res.text().await.unwrap().contains("1234 5678 9012 3456"));
 }
 
 enum DepartureOrArrivalBytes {
@@ -138,6 +184,7 @@ enum DepartureOrArrivalBytes {
     json_bytes("Henk"),
     json_bytes("fake@example.com"),
     json_bytes("123-456"),
+    json_bytes(json!([with_travel_document(json!({ "name": "Henk", "type": "adult" }))])),
     json_bytes(serde_json::to_string(&json!({
         "card_number": "1234 5678 9012 3456",
         "cvc": "123",
@@ -153,6 +200,7 @@ enum DepartureOrArrivalBytes {
     json_bytes("Henk"),
     json_bytes("fake@example.com"),
     json_bytes("123-456"),
+    json_bytes(json!([with_travel_document(json!({ "name": "Henk", "type": "adult" }))])),
     json_bytes(serde_json::to_string(&json!({
         "card_number": "1234 5678 9012 3456",
         "cvc": "123",
@@ -160,6 +208,7 @@ enum DepartureOrArrivalBytes {
     })).unwrap())
     ; "Valid flow with arrival time")]
 #[tokio::test]
+#[allow(clippy::too_many_arguments)]
 async fn complete_flow(
     origin: Cow<'static, [u8]>,
     destination: Cow<'static, [u8]>,
@@ -169,25 +218,26 @@ async fn complete_flow(
     name: Cow<'static, [u8]>,
     email: Cow<'static, [u8]>,
     phone_number: Cow<'static, [u8]>,
+    passengers: Cow<'static, [u8]>,
     payment_details: Cow<'static, [u8]>,
 ) {
     let client = http_client();
-    let state: TicketMachine = send_post_request(&client, "/origin", origin.to_vec()).await;
+    let state: TicketMachineView = send_post_request(&client, "/origin", origin.to_vec()).await;
     let expected_origin = Some(serde_json::from_slice(&origin).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
             origin: expected_origin.clone(),
             ..Default::default()
         }
     );
 
-    let state: TicketMachine =
+    let state: TicketMachineView =
         send_post_request(&client, "/destination", destination.to_vec()).await;
     let expected_destination = Some(serde_json::from_slice(&destination).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
             origin: expected_origin.clone(),
             destination: expected_destination.clone(),
             ..Default::default()
@@ -196,14 +246,14 @@ async fn complete_flow(
 
     let expected_time = match time {
         DepartureOrArrivalBytes::Departure(departure) => {
-            let state: TicketMachine =
+            let state: TicketMachineView =
                 send_post_request(&client, "/departure", departure.to_vec()).await;
             let expected_departure = Some(DepartureOrArrival::Departure(
                 serde_json::from_slice(&departure).unwrap(),
             ));
             assert_eq!(
                 state,
-                TicketMachine {
+                TicketMachineView {
                     origin: expected_origin.clone(),
                     destination: expected_destination.clone(),
                     time: expected_departure.clone(),
@@ -213,14 +263,14 @@ async fn complete_flow(
             expected_departure
         }
         DepartureOrArrivalBytes::Arrival(arrival) => {
-            let state: TicketMachine =
+            let state: TicketMachineView =
                 send_post_request(&client, "/arrival", arrival.to_vec()).await;
             let expected_arrival = Some(DepartureOrArrival::Arrival(
                 serde_json::from_slice(&arrival).unwrap(),
             ));
             assert_eq!(
                 state,
-                TicketMachine {
+                TicketMachineView {
                     origin: expected_origin.clone(),
                     destination: expected_destination.clone(),
                     time: expected_arrival.clone(),
@@ -231,13 +281,16 @@ async fn complete_flow(
         }
     };
 
-    let trips: Vec<Trip> = send_get_request(&client, "/trips").await;
-    let trip = trip.unwrap_or(serde_json::to_vec(&trips[0].id).unwrap().into());
-    let state: TicketMachine = send_post_request(&client, "/trip", trip.to_vec()).await;
+    let trips: Vec<TripView> = send_get_request(&client, "/trips").await;
+    assert!(trips
+        .iter()
+        .all(|t| t.availability.len() == 2 && t.availability.iter().all(|a| a.seats > 0)));
+    let trip = trip.unwrap_or(serde_json::to_vec(&trips[0].trip.id).unwrap().into());
+    let state: TicketMachineView = send_post_request(&client, "/trip", trip.to_vec()).await;
     let expected_trip = Some(serde_json::from_slice(&trip).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
             origin: expected_origin.clone(),
             destination: expected_destination.clone(),
             time: expected_time.clone(),
@@ -246,11 +299,11 @@ async fn complete_flow(
         }
     );
 
-    let state: TicketMachine = send_post_request(&client, "/class", class.to_vec()).await;
+    let state: TicketMachineView = send_post_request(&client, "/class", class.to_vec()).await;
     let expected_class = Some(serde_json::from_slice(&class).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
             origin: expected_origin.clone(),
             destination: expected_destination.clone(),
             time: expected_time.clone(),
@@ -260,11 +313,11 @@ async fn complete_flow(
         }
     );
 
-    let state: TicketMachine = send_post_request(&client, "/name", name.to_vec()).await;
+    let state: TicketMachineView = send_post_request(&client, "/name", name.to_vec()).await;
     let expected_name = Some(serde_json::from_slice(&name).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
             origin: expected_origin.clone(),
             destination: expected_destination.clone(),
             time: expected_time.clone(),
@@ -275,11 +328,11 @@ async fn complete_flow(
         }
     );
 
-    let state: TicketMachine = send_post_request(&client, "/email", email.to_vec()).await;
+    let state: TicketMachineView = send_post_request(&client, "/email", email.to_vec()).await;
     let expected_email = Some(serde_json::from_slice(&email).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
             origin: expected_origin.clone(),
             destination: expected_destination.clone(),
             time: expected_time.clone(),
@@ -291,12 +344,31 @@ async fn complete_flow(
         }
     );
 
-    let state: TicketMachine =
+    let state: TicketMachineView =
         send_post_request(&client, "/phone_number", phone_number.to_vec()).await;
     let expected_phone_number = Some(serde_json::from_slice(&phone_number).unwrap());
     assert_eq!(
         state,
-        TicketMachine {
+        TicketMachineView {
+            origin: expected_origin.clone(),
+            destination: expected_destination.clone(),
+            time: expected_time.clone(),
+            trip: expected_trip.clone(),
+            class: expected_class.clone(),
+            name: expected_name.clone(),
+            email: expected_email.clone(),
+            phone_number: expected_phone_number.clone(),
+            ..Default::default()
+        }
+    );
+
+    // London is outside the Schengen area, so passengers need documents
+    let state: TicketMachineView =
+        send_post_request(&client, "/passengers", passengers.to_vec()).await;
+    let expected_passengers = Some(serde_json::from_slice(&passengers).unwrap());
+    assert_eq!(
+        state,
+        TicketMachineView {
             origin: expected_origin.clone(),
             destination: expected_destination.clone(),
             time: expected_time.clone(),
@@ -305,9 +377,1033 @@ async fn complete_flow(
             name: expected_name.clone(),
             email: expected_email.clone(),
             phone_number: expected_phone_number.clone(),
+            passengers: expected_passengers,
             ..Default::default()
         }
     );
 
-    let _: TicketMachine = send_post_request(&client, "/book_trip", payment_details.to_vec()).await;
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", payment_details.to_vec()).await;
+    assert_eq!(Some(booking.trip.id.clone()), expected_trip);
+    assert_eq!(Some(booking.class.clone()), expected_class);
+    assert_eq!(Some(booking.contact.name.clone()), expected_name);
+    assert!(booking.passengers[0].travel_document.is_some());
+    assert_eq!(booking.status, BookingStatus::Confirmed);
+
+    let fetched: BookingView = send_get_request(&client, &booking_path(&booking, "")).await;
+    assert_eq!(fetched, booking);
+
+    // The reference alone doesn't give away the booking
+    let path = format!("/bookings/{}", booking.reference);
+    let res = client
+        .get(BASE_URL.join(&path).unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let path = format!("{path}?email=someone.else@example.com");
+    let res = client
+        .get(BASE_URL.join(&path).unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Booking clears the session
+    let res = client
+        .get(BASE_URL.join("/trips").unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_unknown_booking() {
+    let res = http_client()
+        .get(
+            BASE_URL
+                .join("/bookings/ABC234?email=fake@example.com")
+                .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+/// Go through all steps up to, but not including, booking the trip.
+/// Returns the trip that was selected.
+async fn prepare_booking(client: &reqwest::Client) -> TripView {
+    let steps = [
+        ("/origin", json!("Amsterdam Centraal")),
+        ("/destination", json!("London Waterloo")),
+        ("/departure", json!(Utc::now() + Duration::minutes(30))),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView = send_post_request(client, path, json_bytes(body).to_vec()).await;
+    }
+
+    let mut trips: Vec<TripView> = send_get_request(client, "/trips").await;
+    let steps = [
+        ("/trip", json!(trips[0].trip.id)),
+        ("/class", json!(Class::Second)),
+        ("/name", json!("Henk")),
+        ("/email", json!("fake@example.com")),
+        ("/phone_number", json!("123-456")),
+        (
+            "/passengers",
+            json!([with_travel_document(
+                json!({ "name": "Henk", "type": "adult" })
+            )]),
+        ),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView = send_post_request(client, path, json_bytes(body).to_vec()).await;
+    }
+
+    trips.remove(0)
+}
+
+/// `passenger` with the details needed to pass border control on the
+/// way to London, where they're not given already. Children are born as
+/// long ago as their `age` says.
+fn with_travel_document(mut passenger: serde_json::Value) -> serde_json::Value {
+    let age = passenger["age"].as_i64().unwrap_or(40);
+    let today = Utc::now().date_naive();
+    let details = json!({
+        "nationality": "NL",
+        "date_of_birth": today - Duration::days(365 * age + 100),
+        "travel_document": {
+            "document_type": "identity_card",
+            "number": "IH12345",
+            "expires_on": today + Duration::days(365),
+        },
+    });
+    let serde_json::Value::Object(details) = details else {
+        unreachable!()
+    };
+    let passenger_details = passenger.as_object_mut().unwrap();
+    for (field, value) in details {
+        passenger_details.entry(field).or_insert(value);
+    }
+    passenger
+}
+
+async fn send_idempotent_request(
+    client: &reqwest::Client,
+    path: &str,
+    key: &str,
+    body: impl Serialize,
+) -> reqwest::Response {
+    client
+        .post(BASE_URL.join(path).unwrap())
+        .header("Idempotency-Key", key)
+        .body(json_bytes(body).to_vec())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_idempotent_booking() {
+    let client = http_client();
+    prepare_booking(&client).await;
+
+    let key = uuid::Uuid::new_v4().to_string();
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+
+    let res = send_idempotent_request(&client, "/book_trip", &key, &payment_info).await;
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    let booking: BookingView = res.json().await.unwrap();
+
+    // Submitting again replays the original response, even though the
+    // session was cleared by the first submission
+    let res = send_idempotent_request(&client, "/book_trip", &key, &payment_info).await;
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    assert_eq!(res.headers()["idempotent-replayed"], "true");
+    let replayed: BookingView = res.json().await.unwrap();
+    assert_eq!(replayed, booking);
+
+    // Reusing the key for a different request is an error
+    let other_payment_info = json!({ "card_number": "6543 2109 8765 4321" }).to_string();
+    let res = send_idempotent_request(&client, "/book_trip", &key, &other_payment_info).await;
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Someone else using the same key gets their own response, and
+    // errors aren't stored, so they can try again once they're ready
+    let other_client = http_client();
+    let res = send_idempotent_request(&other_client, "/book_trip", &key, &payment_info).await;
+    assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(!res.headers().contains_key("idempotent-replayed"));
+    prepare_booking(&other_client).await;
+    let res = send_idempotent_request(&other_client, "/book_trip", &key, &payment_info).await;
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    let other: BookingView = res.json().await.unwrap();
+    assert_ne!(other.reference, booking.reference);
+}
+
+#[tokio::test]
+async fn test_concurrent_state_updates() {
+    let client = http_client();
+    let send = |path: &'static str, etag: Option<HeaderValue>, body: serde_json::Value| {
+        let mut req = client
+            .post(BASE_URL.join(path).unwrap())
+            .body(json_bytes(body).to_vec());
+        if let Some(etag) = etag {
+            req = req.header("If-Match", etag);
+        }
+        req.send()
+    };
+
+    let res = send("/origin", None, json!("Amsterdam Centraal"))
+        .await
+        .unwrap();
+    let first_etag = res.headers()["etag"].clone();
+
+    // One tab sets the destination based on the first revision
+    let res = send(
+        "/destination",
+        Some(first_etag.clone()),
+        json!("Paris Nord"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    let second_etag = res.headers()["etag"].clone();
+    assert_ne!(first_etag, second_etag);
+
+    // The other tab is still on the first revision
+    let res = send("/destination", Some(first_etag), json!("Berlin Hbf"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+    let res = send(
+        "/departure",
+        Some(second_etag),
+        json!(Utc::now() + Duration::hours(1)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    let state: TicketMachineView = res.json().await.unwrap();
+    assert_eq!(
+        state.destination,
+        Some("Paris Nord".to_owned().try_into().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_booking() {
+    let client = http_client();
+    prepare_booking(&client).await;
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+
+    // The trip departs within the hour, so there's nothing to refund
+    let path = booking_path(&booking, "/cancel");
+    let cancelled: BookingView = send_post_request(&client, &path, vec![]).await;
+    assert_eq!(cancelled.status, BookingStatus::Cancelled);
+    assert!(cancelled.refunds.is_empty());
+    assert_eq!(
+        cancelled
+            .history
+            .iter()
+            .map(|c| c.status)
+            .collect::<Vec<_>>(),
+        [BookingStatus::Confirmed, BookingStatus::Cancelled]
+    );
+
+    let res = client
+        .post(BASE_URL.join(&path).unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_change_booking() {
+    let client = http_client();
+    prepare_booking(&client).await;
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+
+    let path = booking_path(&booking, "/alternatives");
+    let options: Vec<ChangeOptionView> = send_get_request(&client, &path).await;
+    let later = options
+        .iter()
+        .find(|o| o.trip.id != booking.trip.id && o.class == Class::First)
+        .unwrap();
+    assert_eq!(
+        later.fare_difference.amount,
+        later.price.amount - booking.price.amount
+    );
+
+    // Upgrading costs extra, which can't be paid without payment info
+    let path = booking_path(&booking, "/change");
+    let res = send_idempotent_request(
+        &client,
+        &path,
+        &uuid::Uuid::new_v4().to_string(),
+        json!({ "trip": later.trip.id, "class": "first" }),
+    )
+    .await;
+    assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let changed: BookingView = send_post_request(
+        &client,
+        &path,
+        json_bytes(json!({
+            "trip": later.trip.id,
+            "class": "first",
+            "payment_info": payment_info,
+        }))
+        .to_vec(),
+    )
+    .await;
+    assert_eq!(changed.trip, later.trip);
+    assert_eq!(changed.price, later.price);
+    assert_eq!(changed.changes.len(), 1);
+    assert_eq!(changed.changes[0].from.trip, booking.trip);
+
+    // Going back to second class refunds the difference
+    let changed: BookingView = send_post_request(
+        &client,
+        &path,
+        json_bytes(json!({ "class": "second" })).to_vec(),
+    )
+    .await;
+    assert_eq!(changed.price, booking.price);
+    assert_eq!(changed.refunds.len(), 1);
+    assert_eq!(
+        changed.refunds[0].amount.amount,
+        later.fare_difference.amount
+    );
+}
+
+#[tokio::test]
+async fn test_select_seat() {
+    let client = http_client();
+    let trip = prepare_booking(&client).await;
+
+    let path = format!("/trips/{}/seats", json!(trip.trip.id).as_str().unwrap());
+    let coaches: Vec<CoachView> = send_get_request(&client, &path).await;
+    assert_eq!(coaches.len(), 7);
+    assert!(coaches.iter().flat_map(|c| &c.seats).all(|s| s.available));
+
+    // Second class was selected, so first class seats are off limits
+    let first_class = coaches.iter().find(|c| c.class == Class::First).unwrap();
+    let res = client
+        .post(BASE_URL.join("/seat").unwrap())
+        .body(json_bytes(first_class.seats[0].id).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let second_class = coaches.iter().find(|c| c.class == Class::Second).unwrap();
+    let seat = second_class.seats[0].id;
+    let state: TicketMachineView =
+        send_post_request(&client, "/seat", json_bytes(seat).to_vec()).await;
+    assert_eq!(state.seat, Some(seat));
+
+    let coaches: Vec<CoachView> = send_get_request(&client, &path).await;
+    let taken: Vec<_> = coaches
+        .iter()
+        .flat_map(|c| &c.seats)
+        .filter(|s| !s.available)
+        .map(|s| s.id)
+        .collect();
+    assert_eq!(taken, [seat]);
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.seat, Some(seat));
+
+    // The price is the one that was listed with the trip
+    let listed = trip
+        .availability
+        .iter()
+        .find(|a| a.class == Class::Second)
+        .unwrap();
+    assert_eq!(booking.price, listed.price);
+}
+
+#[tokio::test]
+async fn test_display_currency() {
+    let client = http_client();
+    prepare_booking(&client).await;
+
+    // Prices are quoted in euros when departing from Amsterdam,
+    // but can be shown in other currencies
+    let trips: Vec<TripView> = send_get_request(&client, "/trips?currency=GBP").await;
+    let availability = &trips[0].availability[0];
+    assert_eq!(availability.price.currency, Currency::Eur);
+    assert!(availability.display_price.starts_with('£'));
+
+    let trips: Vec<TripView> = send_get_request(&client, "/trips?locale=de-DE").await;
+    assert!(trips[0].availability[0].display_price.ends_with('€'));
+}
+
+#[tokio::test]
+async fn test_multiple_passengers() {
+    let client = http_client();
+    let trip = prepare_booking(&client).await;
+
+    let passengers = json!([
+        with_travel_document(json!({ "name": "Henk", "type": "adult" })),
+        with_travel_document(json!({ "name": "Ingrid", "type": "child", "age": 8 })),
+        with_travel_document(json!({ "name": "Jan", "type": "child", "age": 2 })),
+    ]);
+    let state: TicketMachineView =
+        send_post_request(&client, "/passengers", json_bytes(&passengers).to_vec()).await;
+    assert_eq!(state.passengers.as_ref().map(|p| p.len()), Some(3));
+
+    // Children have to be younger than 12
+    let res = client
+        .post(BASE_URL.join("/passengers").unwrap())
+        .body(json_bytes(json!([{ "name": "Kees", "type": "child", "age": 12 }])).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.passengers.len(), 3);
+    assert_eq!(booking.contact.name, booking.passengers[0].name);
+
+    // Children pay half, and toddlers travel for free
+    let listed = trip
+        .availability
+        .iter()
+        .find(|a| a.class == Class::Second)
+        .unwrap();
+    assert_eq!(
+        booking.price.amount,
+        listed.price.amount + (listed.price.amount + 1) / 2
+    );
+}
+
+#[tokio::test]
+async fn test_discount_card() {
+    let client = http_client();
+    let trip = prepare_booking(&client).await;
+
+    let passengers = |valid_until: DateTime<Utc>| {
+        json!([with_travel_document(json!({
+            "name": "Henk",
+            "type": "adult",
+            "discount_card": {
+                "card_type": "bahncard_50",
+                "number": "7081 4112 3456 7895",
+                "valid_until": valid_until.date_naive(),
+            },
+        }))])
+    };
+    // The card has to be valid on the day of travel
+    let _: TicketMachineView = send_post_request(
+        &client,
+        "/passengers",
+        json_bytes(passengers(trip.trip.departure - Duration::days(1))).to_vec(),
+    )
+    .await;
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let res = client
+        .post(BASE_URL.join("/book_trip").unwrap())
+        .body(json_bytes(&payment_info).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let _: TicketMachineView = send_post_request(
+        &client,
+        "/passengers",
+        json_bytes(passengers(trip.trip.departure + Duration::days(365))).to_vec(),
+    )
+    .await;
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert!(booking.discount_card_required);
+    let listed = trip
+        .availability
+        .iter()
+        .find(|a| a.class == Class::Second)
+        .unwrap();
+    assert_eq!(booking.price.amount, (listed.price.amount + 1) / 2);
+}
+
+#[tokio::test]
+async fn test_ancillaries() {
+    let client = http_client();
+    let trip = prepare_booking(&client).await;
+    assert!(trip.trip.carries.contains(&AncillaryType::Bicycle));
+
+    // Pets can't go through the Channel Tunnel
+    let options: Vec<AncillaryOptionView> = send_get_request(&client, "/ancillaries").await;
+    let types: Vec<_> = options.iter().map(|o| o.ancillary_type).collect();
+    assert_eq!(types, [AncillaryType::Bicycle, AncillaryType::ExtraLuggage]);
+    for (ancillaries, status) in [
+        (
+            json!([{ "type": "pet", "quantity": 1 }]),
+            reqwest::StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            json!([{ "type": "bicycle", "quantity": 2 }]),
+            reqwest::StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+    ] {
+        let res = client
+            .post(BASE_URL.join("/ancillaries").unwrap())
+            .body(json_bytes(ancillaries).to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), status);
+    }
+
+    let ancillaries = json!([
+        { "type": "bicycle", "quantity": 1 },
+        { "type": "extra_luggage", "quantity": 2 },
+    ]);
+    let state: TicketMachineView =
+        send_post_request(&client, "/ancillaries", json_bytes(&ancillaries).to_vec()).await;
+    assert_eq!(state.ancillaries.len(), 2);
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.ancillaries.len(), 2);
+    let listed = trip
+        .availability
+        .iter()
+        .find(|a| a.class == Class::Second)
+        .unwrap();
+    assert_eq!(
+        booking.price.amount,
+        listed.price.amount + options[0].price.amount + 2 * options[1].price.amount
+    );
+}
+
+#[tokio::test]
+async fn test_assistance() {
+    let client = http_client();
+    let trip = prepare_booking(&client).await;
+    let passengers = json!([
+        with_travel_document(json!({
+            "name": "Henk",
+            "type": "adult",
+            "assistance": {
+                "types": ["boarding_assistance", "wheelchair_space"],
+                "mobility_aid": "manual_wheelchair",
+                "companion": "Ingrid",
+            },
+        })),
+        with_travel_document(json!({ "name": "Ingrid", "type": "adult" })),
+    ]);
+    let _: TicketMachineView =
+        send_post_request(&client, "/passengers", json_bytes(&passengers).to_vec()).await;
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert!(booking.passengers[0].assistance.is_some());
+
+    // That was the only wheelchair space in second class
+    let other = http_client();
+    let steps = [
+        ("/origin", json!("Amsterdam Centraal")),
+        ("/destination", json!("London Waterloo")),
+        ("/departure", json!(Utc::now() + Duration::minutes(30))),
+        ("/trip", json!(trip.trip.id)),
+        ("/class", json!(Class::Second)),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView = send_post_request(&other, path, json_bytes(body).to_vec()).await;
+    }
+    let res = other
+        .post(BASE_URL.join("/passengers").unwrap())
+        .body(json_bytes(&passengers).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::CONFLICT);
+
+    let trip_id = json!(trip.trip.id);
+    let path = format!("/operator/trips/{}/assistance", trip_id.as_str().unwrap());
+    let res = client
+        .get(BASE_URL.join(&path).unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let requests: Vec<AssistanceRequestView> = client
+        .get(BASE_URL.join(&path).unwrap())
+        .bearer_auth(operator_token())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].reference, booking.reference);
+    let csv = client
+        .get(BASE_URL.join(&format!("{path}?format=csv")).unwrap())
+        .bearer_auth(operator_token())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(csv.lines().count(), 2);
+    assert!(csv.contains("boarding_assistance;wheelchair_space,manual_wheelchair,Ingrid"));
+}
+
+#[tokio::test]
+async fn test_return_journey() {
+    let client = http_client();
+    let steps = [
+        ("/origin", json!("Amsterdam Centraal")),
+        ("/destination", json!("London Waterloo")),
+        ("/departure", json!(Utc::now() + Duration::minutes(30))),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView =
+            send_post_request(&client, path, json_bytes(body).to_vec()).await;
+    }
+    let trips: Vec<TripView> = send_get_request(&client, "/trips").await;
+    let outbound = &trips[0].trip;
+    let _: TicketMachineView =
+        send_post_request(&client, "/trip", json_bytes(json!(outbound.id)).to_vec()).await;
+
+    // A single has no way back
+    let return_departure = json_bytes(json!(outbound.arrival + Duration::hours(2))).to_vec();
+    let res = client
+        .post(BASE_URL.join("/return_departure").unwrap())
+        .body(return_departure.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let _: TicketMachineView = send_post_request(
+        &client,
+        "/journey_type",
+        json_bytes(json!("return")).to_vec(),
+    )
+    .await;
+    let _: TicketMachineView =
+        send_post_request(&client, "/return_departure", return_departure).await;
+
+    let return_trips: Vec<TripView> = send_get_request(&client, "/return_trips").await;
+    assert!(!return_trips.is_empty());
+    assert!(return_trips.iter().all(|t| {
+        t.trip.origin == outbound.destination && t.trip.departure >= outbound.arrival
+    }));
+    let inbound = &return_trips[0];
+    let steps = [
+        ("/return_trip", json!(inbound.trip.id)),
+        ("/class", json!(Class::Second)),
+        ("/name", json!("Henk")),
+        ("/email", json!("fake@example.com")),
+        ("/phone_number", json!("123-456")),
+        (
+            "/passengers",
+            json!([with_travel_document(
+                json!({ "name": "Henk", "type": "adult" })
+            )]),
+        ),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView =
+            send_post_request(&client, path, json_bytes(body).to_vec()).await;
+    }
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.journey_type, JourneyType::Return);
+    assert_eq!(
+        booking.return_trip.map(|t| t.id),
+        Some(inbound.trip.id.clone())
+    );
+
+    // The price is the return fare that was listed with the trip back
+    let listed = inbound
+        .availability
+        .iter()
+        .find(|a| a.class == Class::Second)
+        .unwrap();
+    assert_eq!(booking.price, listed.price);
+}
+
+#[tokio::test]
+async fn test_voucher() {
+    let email = json!(format!("{}@example.com", uuid::Uuid::new_v4()));
+    let client = http_client();
+    let trip = prepare_booking(&client).await;
+    let _: TicketMachineView =
+        send_post_request(&client, "/email", json_bytes(&email).to_vec()).await;
+
+    let res = client
+        .post(BASE_URL.join("/voucher").unwrap())
+        .body(json_bytes("NOSUCHCODE").to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Codes are case-insensitive
+    let voucher: VoucherView =
+        send_post_request(&client, "/voucher", json_bytes("welcome10").to_vec()).await;
+    let listed = trip
+        .availability
+        .iter()
+        .find(|a| a.class == Class::Second)
+        .unwrap();
+    assert_eq!(voucher.price, listed.price);
+    assert_eq!(voucher.discount.amount, (listed.price.amount + 5) / 10);
+    assert_eq!(
+        voucher.discounted_price.amount,
+        voucher.price.amount - voucher.discount.amount
+    );
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.price, voucher.discounted_price);
+    assert_eq!(booking.voucher.as_ref().unwrap().discount, voucher.discount);
+
+    // The welcome voucher can only be used once per customer
+    let client = http_client();
+    prepare_booking(&client).await;
+    let _: TicketMachineView =
+        send_post_request(&client, "/email", json_bytes(&email).to_vec()).await;
+    let res = client
+        .post(BASE_URL.join("/voucher").unwrap())
+        .body(json_bytes("WELCOME10").to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::CONFLICT);
+
+    // Unless the booking it was used for is cancelled
+    let path = booking_path(&booking, "/cancel");
+    let _: BookingView = send_post_request(&client, &path, vec![]).await;
+    let _: VoucherView =
+        send_post_request(&client, "/voucher", json_bytes("WELCOME10").to_vec()).await;
+}
+
+#[tokio::test]
+async fn test_travel_documents() {
+    let client = http_client();
+    let steps = [
+        ("/origin", json!("Amsterdam Centraal")),
+        ("/destination", json!("London Waterloo")),
+        ("/departure", json!(Utc::now() + Duration::minutes(30))),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView =
+            send_post_request(&client, path, json_bytes(body).to_vec()).await;
+    }
+    let trips: Vec<TripView> = send_get_request(&client, "/trips").await;
+    let steps = [
+        ("/trip", json!(trips[0].trip.id)),
+        ("/class", json!(Class::Second)),
+        ("/name", json!("Henk")),
+        ("/email", json!("fake@example.com")),
+        ("/phone_number", json!("123-456")),
+        (
+            "/passengers",
+            json!([
+                { "name": "Henk", "type": "adult", "nationality": "NL" },
+                { "name": "Ingrid", "type": "adult" },
+            ]),
+        ),
+    ];
+    for (path, body) in steps {
+        let _: TicketMachineView =
+            send_post_request(&client, path, json_bytes(body).to_vec()).await;
+    }
+
+    // London is outside the Schengen area, so all details are required
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let res = client
+        .post(BASE_URL.join("/book_trip").unwrap())
+        .body(json_bytes(&payment_info).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let message = res.text().await.unwrap();
+    assert!(message.contains("Henk (date_of_birth, travel_document)"));
+    assert!(message.contains("Ingrid (nationality, date_of_birth, travel_document)"));
+
+    let passenger = |name: &str, number: &str| {
+        json!({
+            "name": name,
+            "type": "adult",
+            "nationality": "NL",
+            "date_of_birth": "1980-01-01",
+            "travel_document": {
+                "document_type": "identity_card",
+                "number": number,
+                "expires_on": Utc::now().date_naive() + Duration::days(365),
+            },
+        })
+    };
+    let passengers = json!([passenger("Henk", "IH12345"), passenger("Ingrid", "IH67890")]);
+    let _: TicketMachineView =
+        send_post_request(&client, "/passengers", json_bytes(&passengers).to_vec()).await;
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert!(booking.passengers[1].travel_document.is_some());
+}
+
+#[tokio::test]
+async fn test_unaccompanied_minor() {
+    let client = http_client();
+    prepare_booking(&client).await;
+
+    // A date of birth in the future is rejected right away
+    let tomorrow = Utc::now().date_naive() + Duration::days(1);
+    let res = client
+        .post(BASE_URL.join("/passengers").unwrap())
+        .body(
+            json_bytes(json!([{ "name": "Henk", "type": "adult", "date_of_birth": tomorrow }]))
+                .to_vec(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert!(res.status().is_client_error());
+
+    let ingrid = with_travel_document(json!({ "name": "Ingrid", "type": "child", "age": 9 }));
+    let _: TicketMachineView =
+        send_post_request(&client, "/passengers", json_bytes(json!([ingrid])).to_vec()).await;
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let res = client
+        .post(BASE_URL.join("/book_trip").unwrap())
+        .body(json_bytes(&payment_info).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(res.text().await.unwrap().contains("without an adult"));
+
+    let henk = with_travel_document(
+        json!({ "name": "Henk", "type": "adult", "date_of_birth": "1980-01-01" }),
+    );
+    let _: TicketMachineView = send_post_request(
+        &client,
+        "/passengers",
+        json_bytes(json!([henk, ingrid])).to_vec(),
+    )
+    .await;
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.passengers.len(), 2);
+}
+
+#[tokio::test]
+async fn test_billing_address() {
+    let client = http_client();
+    prepare_booking(&client).await;
+
+    let address = |postal_code: &str| {
+        json!({
+            "company": "Treinen BV",
+            "street": "Stationsplein 1",
+            "postal_code": postal_code,
+            "city": "Amsterdam",
+            "country": "NL",
+        })
+    };
+    let res = client
+        .post(BASE_URL.join("/billing_address").unwrap())
+        .body(json_bytes(address("SW1A 1AA")).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let state: TicketMachineView = send_post_request(
+        &client,
+        "/billing_address",
+        json_bytes(address("1012ab")).to_vec(),
+    )
+    .await;
+    let billing_address = state.billing_address.unwrap();
+    assert_eq!(billing_address.postal_code.to_string(), "1012 AB");
+
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    assert_eq!(booking.billing_address, Some(billing_address));
+}
+
+#[tokio::test]
+async fn test_invoice() {
+    let client = http_client();
+    prepare_booking(&client).await;
+    let address = json!({
+        "company": "Treinen BV",
+        "vat_id": "NL123456789B01",
+        "street": "Stationsplein 1",
+        "postal_code": "1012 AB",
+        "city": "Amsterdam",
+        "country": "NL",
+    });
+    let _: TicketMachineView =
+        send_post_request(&client, "/billing_address", json_bytes(&address).to_vec()).await;
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+
+    let path = booking_path(&booking, "/invoice");
+    let invoice: Invoice = send_get_request(&client, &path).await;
+    assert_eq!(invoice.reference, booking.reference);
+    assert_eq!(invoice.total, booking.price);
+    assert_eq!(invoice.lines[0].vat_percentage, 9);
+    assert_eq!(
+        invoice.total_net.amount + invoice.total_vat.amount,
+        invoice.total.amount
+    );
+
+    // The invoice is issued only once
+    let again: Invoice = send_get_request(&client, &path).await;
+    assert_eq!(again, invoice);
+
+    let res = client
+        .get(BASE_URL.join(&format!("{path}&format=pdf")).unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.headers()["content-type"], "application/pdf");
+    assert!(res.bytes().await.unwrap().starts_with(b"%PDF"));
+}
+
+#[tokio::test]
+async fn test_ticket() {
+    let client = http_client();
+    prepare_booking(&client).await;
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+
+    let path = booking_path(&booking, "/ticket");
+    let tickets: Vec<TicketView> = send_get_request(&client, &format!("{path}&format=json")).await;
+    assert_eq!(tickets.len(), 1);
+    assert_eq!(tickets[0].ticket.reference, booking.reference.as_str());
+    assert_eq!(tickets[0].signed.as_str().split('.').count(), 2);
+
+    for (format, content_type, magic) in [
+        ("png", "image/png", &b"\x89PNG"[..]),
+        ("pdf", "application/pdf", &b"%PDF"[..]),
+    ] {
+        let res = client
+            .get(BASE_URL.join(&format!("{path}&format={format}")).unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["content-type"], content_type);
+        assert!(res.bytes().await.unwrap().starts_with(magic));
+    }
+}
+
+#[tokio::test]
+async fn test_verify_ticket() {
+    let client = http_client();
+    prepare_booking(&client).await;
+    let payment_info = json!({ "card_number": "1234 5678 9012 3456" }).to_string();
+    let booking: BookingView =
+        send_post_request(&client, "/book_trip", json_bytes(&payment_info).to_vec()).await;
+    let path = format!("{}&format=json", booking_path(&booking, "/ticket"));
+    let mut tickets: Vec<TicketView> = send_get_request(&client, &path).await;
+    let TicketView { ticket, signed } = tickets.remove(0);
+
+    let scan = |trip| json!({ "ticket": signed, "trip": trip });
+    // Only conductors can check tickets with the booking service
+    let res = client
+        .post(BASE_URL.join("/verify").unwrap())
+        .body(json_bytes(scan(&booking.trip.id)).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let conductor = conductor_client();
+    let first: ScanView = send_post_request(
+        &conductor,
+        "/verify",
+        json_bytes(scan(&booking.trip.id)).to_vec(),
+    )
+    .await;
+    assert_eq!(first.outcome, ScanOutcome::First);
+    assert_eq!(first.ticket, ticket);
+    let again: ScanView = send_post_request(
+        &conductor,
+        "/verify",
+        json_bytes(scan(&booking.trip.id)).to_vec(),
+    )
+    .await;
+    assert_eq!(again.outcome, ScanOutcome::Duplicate);
+    let other_trip = prepare_booking(&http_client()).await.trip.id;
+    let wrong_train: ScanView = send_post_request(
+        &conductor,
+        "/verify",
+        json_bytes(scan(&other_trip)).to_vec(),
+    )
+    .await;
+    assert_eq!(wrong_train.outcome, ScanOutcome::WrongTrain);
+    // Also on a train that has left the timetable
+    let gone: ScanView = send_post_request(
+        &conductor,
+        "/verify",
+        json_bytes(json!({ "ticket": signed, "trip": uuid::Uuid::new_v4() })).to_vec(),
+    )
+    .await;
+    assert_eq!(gone.outcome, ScanOutcome::WrongTrain);
+
+    // Tickets that aren't genuine are rejected
+    let forged = json!({ "ticket": format!("x{}", signed.as_str()), "trip": booking.trip.id });
+    let res = conductor
+        .post(BASE_URL.join("/verify").unwrap())
+        .body(json_bytes(forged).to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Devices can check the same ticket offline with the published key
+    let key: TicketKeyView = send_get_request(&client, "/tickets/public_key").await;
+    let mut verifier = Verifier::new(takeoff_tickets::decode_key(&key.public_key).unwrap());
+    let train = Train {
+        trip: booking.trip.id.clone().into(),
+        origin: booking.trip.origin.to_string(),
+        destination: booking.trip.destination.to_string(),
+    };
+    let (payload, outcome) = verifier.scan(&signed, &train, Utc::now()).unwrap();
+    assert_eq!((payload, outcome), (ticket, ScanOutcome::First));
+
+    // Once the booking is cancelled, only the booking service knows
+    let path = booking_path(&booking, "/cancel");
+    let _: BookingView = send_post_request(&client, &path, vec![]).await;
+    let revoked: ScanView = send_post_request(
+        &conductor,
+        "/verify",
+        json_bytes(scan(&booking.trip.id)).to_vec(),
+    )
+    .await;
+    assert_eq!(revoked.outcome, ScanOutcome::Revoked);
 }
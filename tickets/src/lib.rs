@@ -0,0 +1,12 @@
+//! The tickets that are issued for bookings, and how to check them. This
+//! crate is shared by the booking service, which signs the tickets, and the
+//! devices conductors scan them with, which verify them offline using the
+//! public key the booking service publishes.
+
+mod payload;
+mod scan;
+mod signature;
+
+pub use payload::{Class, DiscountCardType, TicketPayload};
+pub use scan::{check, ScanOutcome, Train, Verifier};
+pub use signature::{decode_key, encode_key, sign, verify, SignedTicket, TicketError};
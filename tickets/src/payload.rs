@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// What a ticket says, which is signed and put in its QR code. The keys are
+/// kept short, and the times are Unix timestamps, so that the QR code stays
+/// small enough to scan from a phone screen.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TicketPayload {
+    /// The reference of the booking the ticket is part of
+    #[serde(rename = "r")]
+    pub reference: String,
+    /// The trip the ticket is for, or [`None`] for the way
+    /// back of an open return, which is valid on any trip
+    #[serde(rename = "t")]
+    pub trip: Option<Uuid>,
+    #[serde(rename = "o")]
+    pub origin: String,
+    #[serde(rename = "d")]
+    pub destination: String,
+    #[serde(rename = "p")]
+    pub passenger: String,
+    #[serde(rename = "c")]
+    pub class: Class,
+    /// The discount card the passenger has to show on board, if the
+    /// fare was discounted for one
+    #[serde(rename = "dc", default, skip_serializing_if = "Option::is_none")]
+    pub discount_card: Option<DiscountCardType>,
+    #[serde(rename = "vf", with = "chrono::serde::ts_seconds")]
+    pub valid_from: DateTime<Utc>,
+    #[serde(rename = "vu", with = "chrono::serde::ts_seconds")]
+    pub valid_until: DateTime<Utc>,
+}
+
+/// The class a ticket is for, which is all a conductor
+/// needs to know about what was paid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    First,
+    Second,
+}
+
+/// The discount cards a fare can be discounted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountCardType {
+    YoungPersonsRailcard,
+    SeniorRailcard,
+    #[serde(rename = "bahncard_25")]
+    BahnCard25,
+    #[serde(rename = "bahncard_50")]
+    BahnCard50,
+}
+
+impl TicketPayload {
+    pub fn is_valid_at(&self, at: DateTime<Utc>) -> bool {
+        (self.valid_from..=self.valid_until).contains(&at)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{DateTime, Duration, Utc};
+    use uuid::Uuid;
+
+    use super::{Class, DiscountCardType, TicketPayload};
+
+    pub(crate) fn ticket() -> TicketPayload {
+        let valid_from = DateTime::from_timestamp(1_800_000_000, 0).unwrap();
+        TicketPayload {
+            reference: "CZPCL4".to_owned(),
+            trip: Some(Uuid::new_v4()),
+            origin: "Amsterdam Centraal".to_owned(),
+            destination: "Berlin Hbf".to_owned(),
+            passenger: "Henk".to_owned(),
+            class: Class::Second,
+            discount_card: None,
+            valid_from,
+            valid_until: valid_from + Duration::hours(8),
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let ticket = ticket();
+        let json = serde_json::to_value(&ticket).unwrap();
+        assert_eq!(json["vf"], 1_800_000_000);
+        assert_eq!(json["p"], "Henk");
+        assert_eq!(json["c"], "second");
+        assert!(json.get("dc").is_none());
+        assert_eq!(
+            serde_json::from_value::<TicketPayload>(json).unwrap(),
+            ticket
+        );
+
+        let ticket = TicketPayload {
+            discount_card: Some(DiscountCardType::BahnCard25),
+            ..ticket
+        };
+        let json = serde_json::to_value(&ticket).unwrap();
+        assert_eq!(json["dc"], "bahncard_25");
+        assert_eq!(
+            serde_json::from_value::<TicketPayload>(json).unwrap(),
+            ticket
+        );
+    }
+
+    #[test]
+    fn test_is_valid_at() {
+        let ticket = ticket();
+        assert!(ticket.is_valid_at(ticket.valid_from));
+        assert!(ticket.is_valid_at(ticket.valid_until));
+        assert!(!ticket.is_valid_at(ticket.valid_until + Duration::seconds(1)));
+        assert!(!ticket.is_valid_at(Utc::now() - Duration::days(365 * 10)));
+    }
+}
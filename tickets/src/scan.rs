@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::VerifyingKey;
+use uuid::Uuid;
+
+use crate::{verify, SignedTicket, TicketError, TicketPayload};
+
+/// The train a ticket is scanned on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Train {
+    pub trip: Uuid,
+    pub origin: String,
+    pub destination: String,
+}
+
+/// What the scan of a genuine ticket found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOutcome {
+    /// The ticket is valid on this train, and is used for the first time
+    First,
+    /// The ticket was let through on an earlier scan, so it's being
+    /// used again, possibly by someone else
+    Duplicate,
+    /// The ticket is for another trip, or for another route
+    WrongTrain,
+    /// The ticket can't be used this long before its trip departs
+    NotYetValid,
+    /// The ticket can't be used this long after its trip arrived, or
+    /// after the time to take the way back of an open return is up
+    Expired,
+    /// The booking was cancelled or changed after the ticket was issued.
+    /// Only the booking service knows, so scans made offline can't find
+    /// this.
+    Revoked,
+}
+
+impl ScanOutcome {
+    /// Whether the passenger can travel on the ticket
+    pub fn is_accepted(self) -> bool {
+        self == Self::First
+    }
+}
+
+/// Check `ticket`, whose signature has been verified, on `train` at `at`.
+/// `accepted_before` is whether an earlier scan of it let it through.
+pub fn check(
+    ticket: &TicketPayload,
+    train: &Train,
+    at: DateTime<Utc>,
+    accepted_before: bool,
+) -> ScanOutcome {
+    let right_train = match ticket.trip {
+        Some(trip) => trip == train.trip,
+        // The way back of an open return can be taken on any trip on its route
+        None => ticket.origin == train.origin && ticket.destination == train.destination,
+    };
+
+    if !right_train {
+        ScanOutcome::WrongTrain
+    } else if at < ticket.valid_from {
+        ScanOutcome::NotYetValid
+    } else if at > ticket.valid_until {
+        ScanOutcome::Expired
+    } else if accepted_before {
+        ScanOutcome::Duplicate
+    } else {
+        ScanOutcome::First
+    }
+}
+
+/// Checks tickets without a connection to the booking service, keeping
+/// track of the tickets it let through. Duplicates are only found among
+/// the scans made with the same verifier.
+#[derive(Debug)]
+pub struct Verifier {
+    key: VerifyingKey,
+    accepted: HashSet<String>,
+}
+
+impl Verifier {
+    pub fn new(key: VerifyingKey) -> Self {
+        Self {
+            key,
+            accepted: HashSet::new(),
+        }
+    }
+
+    /// Verify the signature on `ticket` and check it on `train` at `at`
+    pub fn scan(
+        &mut self,
+        ticket: &SignedTicket,
+        train: &Train,
+        at: DateTime<Utc>,
+    ) -> Result<(TicketPayload, ScanOutcome), TicketError> {
+        let payload = verify(&self.key, ticket)?;
+        let outcome = check(&payload, train, at, self.accepted.contains(ticket.id()));
+        if outcome.is_accepted() {
+            self.accepted.insert(ticket.id().to_owned());
+        }
+        Ok((payload, outcome))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use ed25519_dalek::SigningKey;
+    use uuid::Uuid;
+
+    use super::{check, ScanOutcome, Train, Verifier};
+    use crate::{payload::tests::ticket, sign, TicketPayload};
+
+    fn train(trip: Uuid) -> Train {
+        Train {
+            trip,
+            origin: "Amsterdam Centraal".to_owned(),
+            destination: "Berlin Hbf".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let ticket = ticket();
+        let train = train(ticket.trip.unwrap());
+        let during = ticket.valid_from + Duration::hours(1);
+
+        assert_eq!(check(&ticket, &train, during, false), ScanOutcome::First);
+        assert_eq!(check(&ticket, &train, during, true), ScanOutcome::Duplicate);
+        let before = ticket.valid_from - Duration::minutes(1);
+        assert_eq!(
+            check(&ticket, &train, before, false),
+            ScanOutcome::NotYetValid
+        );
+        let after = ticket.valid_until + Duration::minutes(1);
+        assert_eq!(check(&ticket, &train, after, false), ScanOutcome::Expired);
+        let other = Train {
+            trip: Uuid::new_v4(),
+            ..train.clone()
+        };
+        assert_eq!(
+            check(&ticket, &other, during, false),
+            ScanOutcome::WrongTrain
+        );
+
+        // An open return is valid on any trip on its route
+        let open_return = TicketPayload {
+            trip: None,
+            ..ticket
+        };
+        assert_eq!(
+            check(&open_return, &other, during, false),
+            ScanOutcome::First
+        );
+        let reversed = Train {
+            origin: other.destination.clone(),
+            destination: other.origin.clone(),
+            ..other
+        };
+        assert_eq!(
+            check(&open_return, &reversed, during, false),
+            ScanOutcome::WrongTrain
+        );
+    }
+
+    #[test]
+    fn test_verifier() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let ticket = ticket();
+        let signed = sign(&key, &ticket);
+        let train = train(ticket.trip.unwrap());
+        let during = ticket.valid_from + Duration::hours(1);
+
+        let mut verifier = Verifier::new(key.verifying_key());
+        let (payload, outcome) = verifier.scan(&signed, &train, during).unwrap();
+        assert_eq!(payload, ticket);
+        assert_eq!(outcome, ScanOutcome::First);
+        let (_, outcome) = verifier.scan(&signed, &train, during).unwrap();
+        assert_eq!(outcome, ScanOutcome::Duplicate);
+    }
+}
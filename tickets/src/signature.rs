@@ -0,0 +1,157 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::TicketPayload;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TicketError {
+    #[error("The key must be 32 bytes, encoded as base64")]
+    InvalidKey,
+
+    #[error("The ticket is not in the expected format")]
+    Malformed,
+
+    #[error("The ticket signature is not valid")]
+    InvalidSignature,
+}
+
+/// A ticket as it's put in a QR code: the JSON payload and its Ed25519
+/// signature, both base64url-encoded and separated by a dot
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(from = "String", into = "String")]
+pub struct SignedTicket(String);
+
+impl SignedTicket {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// What tells the ticket apart from all others: its signature, which
+    /// is different for every payload
+    pub fn id(&self) -> &str {
+        self.0
+            .rsplit_once('.')
+            .map_or(&self.0, |(_, signature)| signature)
+    }
+}
+
+/// A ticket as it's read from a QR code, which is yet to be verified
+impl From<String> for SignedTicket {
+    fn from(ticket: String) -> Self {
+        Self(ticket.trim().to_owned())
+    }
+}
+
+impl From<SignedTicket> for String {
+    fn from(SignedTicket(ticket): SignedTicket) -> Self {
+        ticket
+    }
+}
+
+pub fn sign(key: &SigningKey, ticket: &TicketPayload) -> SignedTicket {
+    // Serializing a payload can't fail, as all of its keys are strings
+    let payload = serde_json::to_vec(ticket).unwrap();
+    let signature = key.sign(&payload);
+    SignedTicket(format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(&payload),
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    ))
+}
+
+/// Check the signature on `ticket`, returning what it says if it's genuine.
+/// Whether it's valid on the train it's used on is up to [`crate::check`].
+pub fn verify(key: &VerifyingKey, ticket: &SignedTicket) -> Result<TicketPayload, TicketError> {
+    let (payload, signature) = ticket.0.split_once('.').ok_or(TicketError::Malformed)?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| TicketError::Malformed)?;
+    let signature: [u8; 64] = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| TicketError::Malformed)?
+        .try_into()
+        .map_err(|_| TicketError::Malformed)?;
+    key.verify(&payload, &Signature::from_bytes(&signature))
+        .map_err(|_| TicketError::InvalidSignature)?;
+    serde_json::from_slice(&payload).map_err(|_| TicketError::Malformed)
+}
+
+/// Encode the public key as (standard) base64, the way the booking
+/// service publishes it for the devices that verify tickets
+pub fn encode_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.as_bytes())
+}
+
+/// Decode a public key published by the booking service
+pub fn decode_key(key: &str) -> Result<VerifyingKey, TicketError> {
+    let key = STANDARD
+        .decode(key.trim())
+        .map_err(|_| TicketError::InvalidKey)?
+        .try_into()
+        .map_err(|_| TicketError::InvalidKey)?;
+    VerifyingKey::from_bytes(&key).map_err(|_| TicketError::InvalidKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use ed25519_dalek::SigningKey;
+
+    use super::{decode_key, encode_key, sign, verify, SignedTicket, TicketError};
+    use crate::payload::tests::ticket;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let ticket = ticket();
+        let signed = sign(&key, &ticket);
+
+        assert_eq!(verify(&key.verifying_key(), &signed).unwrap(), ticket);
+        let other_key = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(matches!(
+            verify(&other_key, &signed),
+            Err(TicketError::InvalidSignature)
+        ));
+
+        // Changing the payload breaks the signature
+        let mut other = ticket.clone();
+        other.valid_until += Duration::days(1);
+        let other = sign(&key, &other);
+        let (payload, _) = other.0.split_once('.').unwrap();
+        let tampered = SignedTicket::from(format!("{payload}.{}", signed.id()));
+        assert!(matches!(
+            verify(&key.verifying_key(), &tampered),
+            Err(TicketError::InvalidSignature)
+        ));
+        assert!(matches!(
+            verify(&key.verifying_key(), &"not a ticket".to_owned().into()),
+            Err(TicketError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_as_read() {
+        let signed = sign(&SigningKey::from_bytes(&[7; 32]), &ticket());
+        // Scanners may pick up whitespace around the ticket
+        let read = format!("\" {} \\n\"", signed.as_str());
+        let ticket: SignedTicket = serde_json::from_str(&read).unwrap();
+        assert_eq!(ticket, signed);
+        assert_eq!(
+            serde_json::to_string(&ticket).unwrap(),
+            format!("\"{}\"", signed.as_str())
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let key = SigningKey::from_bytes(&[7; 32]).verifying_key();
+        assert_eq!(decode_key(&encode_key(&key)).unwrap(), key);
+        assert!(matches!(
+            decode_key("c2hvcnQ="),
+            Err(TicketError::InvalidKey)
+        ));
+    }
+}